@@ -0,0 +1,119 @@
+use crate::Vfs;
+use std::io::{self, Error, Read, Write};
+use std::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+type Job = Box<dyn FnOnce(&mut Vfs) + Send>;
+
+/// Async front-end for a [`Vfs`]. The image is owned by a single blocking
+/// worker started with `tokio::task::spawn_blocking`, so every operation on
+/// one image runs serialized, in the order it was issued. The worker stops
+/// once the `AsyncVfs` is dropped; use [`AsyncVfs::close`] to also wait for
+/// the image to be released.
+pub struct AsyncVfs {
+    jobs: mpsc::Sender<Job>,
+    worker: JoinHandle<()>,
+}
+
+impl AsyncVfs {
+    pub async fn create(path: &str, total_size: u64) -> io::Result<Self> {
+        let path = path.to_string();
+        Self::start(move || Vfs::create(&path, total_size)).await
+    }
+
+    pub async fn open(path: &str) -> io::Result<Self> {
+        let path = path.to_string();
+        Self::start(move || Vfs::open(&path)).await
+    }
+
+    async fn start<F>(mount: F) -> io::Result<Self>
+    where
+        F: FnOnce() -> io::Result<Vfs> + Send + 'static,
+    {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready_tx, ready_rx) = oneshot::channel();
+
+        let worker = tokio::task::spawn_blocking(move || {
+            let mut vfs = match mount() {
+                Ok(vfs) => {
+                    let _ = ready_tx.send(Ok(()));
+                    vfs
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            while let Ok(job) = queue.recv() {
+                job(&mut vfs);
+            }
+        });
+
+        ready_rx.await.map_err(|_| worker_gone())??;
+        Ok(Self { jobs, worker })
+    }
+
+    /// Stops the worker and waits until the image has been unmounted.
+    pub async fn close(self) -> io::Result<()> {
+        drop(self.jobs);
+        self.worker.await.map_err(|_| worker_gone())
+    }
+
+    async fn run<T, F>(&self, op: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Vfs) -> io::Result<T> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |vfs| {
+                let _ = tx.send(op(vfs));
+            }))
+            .map_err(|_| worker_gone())?;
+        rx.await.map_err(|_| worker_gone())?
+    }
+
+    pub async fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let path = path.to_string();
+        self.run(move |vfs| {
+            let mut content = Vec::new();
+            vfs.open_file(&path)?.read_to_end(&mut content)?;
+            Ok(content)
+        })
+        .await
+    }
+
+    /// Replaces the file at `path` with `data`, creating it if needed.
+    pub async fn write_file(&self, path: &str, data: Vec<u8>) -> io::Result<()> {
+        let path = path.to_string();
+        self.run(move |vfs| {
+            if vfs.find_inode_by_path(&path).is_ok() {
+                vfs.remove(&path)?;
+            }
+            let mut file = vfs.create_file(&path)?;
+            file.write_all(&data)?;
+            file.flush()
+        })
+        .await
+    }
+
+    pub async fn create_dir(&self, path: &str) -> io::Result<()> {
+        let path = path.to_string();
+        self.run(move |vfs| vfs.create_dir(&path)).await
+    }
+
+    pub async fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = path.to_string();
+        self.run(move |vfs| vfs.read_dir(&path)).await
+    }
+
+    pub async fn remove(&self, path: &str) -> io::Result<()> {
+        let path = path.to_string();
+        self.run(move |vfs| vfs.remove(&path)).await
+    }
+}
+
+fn worker_gone() -> Error {
+    Error::other("VFS worker has stopped!")
+}
@@ -0,0 +1,56 @@
+use crate::disk::Disk;
+use crate::models::SuperBlock;
+use std::io::{self, Error, Read, Seek, SeekFrom, Write};
+
+/// Data blocks known to be unreadable or unwritable, kept after the quota
+/// table in the tail of block 0. Block 0 is never a data block, so a zero
+/// slot is free and older images read as having no bad blocks.
+const BAD_TABLE_START: u64 = 2048;
+const BAD_SLOTS: usize = 256;
+
+pub(crate) fn read_table(disk: &mut Disk) -> io::Result<Vec<u32>> {
+    let mut buffer = [0u8; BAD_SLOTS * 4];
+    disk.seek(SeekFrom::Start(BAD_TABLE_START))?;
+    disk.read_exact(&mut buffer)?;
+    Ok(buffer
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .filter(|&id| id != 0)
+        .collect())
+}
+
+/// Records `block_id` as bad. Recording a block twice changes nothing.
+pub(crate) fn add(disk: &mut Disk, block_id: u32) -> io::Result<()> {
+    let mut buffer = [0u8; BAD_SLOTS * 4];
+    disk.seek(SeekFrom::Start(BAD_TABLE_START))?;
+    disk.read_exact(&mut buffer)?;
+    let mut slots = buffer
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+    if slots.clone().any(|id| id == block_id) {
+        return Ok(());
+    }
+    let slot = slots.position(|id| id == 0).ok_or_else(|| {
+        Error::new(
+            io::ErrorKind::StorageFull,
+            format!("Bad block table is full, {} blocks recorded!", BAD_SLOTS),
+        )
+    })?;
+    disk.seek(SeekFrom::Start(BAD_TABLE_START + (slot * 4) as u64))?;
+    disk.write_all(&block_id.to_le_bytes())
+}
+
+/// The data block bitmap with every bad block set, so allocators scanning it
+/// skip them even if a file that held one has since freed it.
+pub(crate) fn data_bitmap(disk: &mut Disk, sb: &SuperBlock) -> io::Result<Vec<u8>> {
+    let start = sb.data_bitmap_start;
+    let mut bitmap = vec![0u8; (sb.inode_table_start - start) as usize];
+    disk.seek(SeekFrom::Start(start))?;
+    disk.read_exact(&mut bitmap)?;
+    for id in read_table(disk)? {
+        if let Some(byte) = bitmap.get_mut((id / 8) as usize) {
+            *byte |= 1 << (id % 8);
+        }
+    }
+    Ok(bitmap)
+}
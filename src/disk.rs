@@ -0,0 +1,373 @@
+use crate::models::BLOCK_SIZE;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
+
+mod snapshot;
+use snapshot::Snapshot;
+
+#[cfg(feature = "journal")]
+mod journal;
+#[cfg(feature = "crash-test")]
+pub(crate) use journal::CrashPoint;
+
+/// The image's backing store. Reads and writes go to the file through regular
+/// syscalls, or, once `enable_mmap` was called, through a shared memory map
+/// that is only flushed at sync points.
+pub(crate) struct Disk {
+    file: File,
+    /// Offset of the image inside `file`; positions are relative to it.
+    base: u64,
+    #[cfg(feature = "mmap")]
+    map: Option<MmapMut>,
+    pos: u64,
+    io_calls: u64,
+    /// Calls to `write`, so cached file bytes can tell nothing changed.
+    writes: u64,
+    read_only: bool,
+    /// Set when the image is not locked, so others may write it meanwhile.
+    unlocked: bool,
+    /// Previous contents of every range written since `begin_undo`.
+    undo: Option<Vec<(u64, Vec<u8>)>>,
+    snapshots: Vec<Snapshot>,
+    /// Writes held back until the running journal transaction commits.
+    #[cfg(feature = "journal")]
+    pending: Option<Vec<(u64, Vec<u8>)>>,
+    #[cfg(feature = "crash-test")]
+    crash_point: Option<CrashPoint>,
+}
+
+impl Disk {
+    pub(crate) fn new(file: File) -> Self {
+        Self {
+            file,
+            base: 0,
+            #[cfg(feature = "mmap")]
+            map: None,
+            pos: 0,
+            io_calls: 0,
+            writes: 0,
+            read_only: false,
+            unlocked: false,
+            undo: None,
+            snapshots: Vec::new(),
+            #[cfg(feature = "journal")]
+            pending: None,
+            #[cfg(feature = "crash-test")]
+            crash_point: None,
+        }
+    }
+
+    /// A store that rejects every write with `PermissionDenied`.
+    pub(crate) fn read_only(file: File) -> Self {
+        Self {
+            read_only: true,
+            ..Self::new(file)
+        }
+    }
+
+    /// Like `read_only`, for an image opened without a lock.
+    pub(crate) fn unlocked(file: File) -> Self {
+        Self {
+            unlocked: true,
+            ..Self::read_only(file)
+        }
+    }
+
+    pub(crate) fn is_unlocked(&self) -> bool {
+        self.unlocked
+    }
+
+    /// A store for an image that starts `base` bytes into `file`, such as one
+    /// embedded in a container file.
+    pub(crate) fn with_base(file: File, base: u64) -> Self {
+        Self {
+            base,
+            ..Self::new(file)
+        }
+    }
+
+    /// Length of the image part of the file: everything from the base on.
+    pub(crate) fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len().saturating_sub(self.base))
+    }
+
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub(crate) fn io_calls(&self) -> u64 {
+        self.io_calls
+    }
+
+    pub(crate) fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Writes `len` zero bytes from `start` on, one block-sized buffer at a
+    /// time, so the cost in memory stays the same however large the region.
+    pub(crate) fn zero_region(&mut self, start: u64, len: u64) -> io::Result<()> {
+        let zeros = [0u8; BLOCK_SIZE];
+        self.seek(SeekFrom::Start(start))?;
+        let mut left = len;
+        while left > 0 {
+            let chunk = left.min(BLOCK_SIZE as u64) as usize;
+            self.write_all(&zeros[..chunk])?;
+            left -= chunk as u64;
+        }
+        Ok(())
+    }
+
+    /// Clears bit `id` of the bitmap at `start` for every id. Each run of
+    /// adjacent affected bytes is read and written back once, however many
+    /// bits in it change.
+    pub(crate) fn clear_bits(&mut self, start: u64, ids: &[u32]) -> io::Result<()> {
+        self.change_bits(start, ids, false)
+    }
+
+    /// Sets bit `id` of the bitmap at `start` for every id, like `clear_bits`.
+    pub(crate) fn set_bits(&mut self, start: u64, ids: &[u32]) -> io::Result<()> {
+        self.change_bits(start, ids, true)
+    }
+
+    fn change_bits(&mut self, start: u64, ids: &[u32], set: bool) -> io::Result<()> {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut rest = &ids[..];
+        while let Some(&first) = rest.first() {
+            let first_byte = first / 8;
+            // Extend the run while the next id lands in this or the next byte.
+            let mut last_byte = first_byte;
+            let mut count = 0;
+            for &id in rest {
+                if id / 8 > last_byte + 1 {
+                    break;
+                }
+                last_byte = id / 8;
+                count += 1;
+            }
+            let mut bytes = vec![0u8; (last_byte - first_byte + 1) as usize];
+            self.seek(SeekFrom::Start(start + first_byte as u64))?;
+            self.read_exact(&mut bytes)?;
+            for &id in &rest[..count] {
+                let byte = &mut bytes[(id / 8 - first_byte) as usize];
+                if set {
+                    *byte |= 1 << (id % 8);
+                } else {
+                    *byte &= !(1 << (id % 8));
+                }
+            }
+            self.seek(SeekFrom::Start(start + first_byte as u64))?;
+            self.write_all(&bytes)?;
+            rest = &rest[count..];
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sync_all(&mut self) -> io::Result<()> {
+        self.io_calls += 1;
+        #[cfg(feature = "mmap")]
+        if let Some(map) = &self.map {
+            return map.flush();
+        }
+        self.file.sync_all()
+    }
+
+    /// Starts recording the bytes each write overwrites. Returns `false` if a
+    /// recording is already running, in which case it keeps going.
+    pub(crate) fn begin_undo(&mut self) -> bool {
+        if self.undo.is_some() {
+            return false;
+        }
+        self.undo = Some(Vec::new());
+        true
+    }
+
+    pub(crate) fn commit_undo(&mut self) {
+        self.undo = None;
+    }
+
+    /// Puts back everything written since `begin_undo`, newest first.
+    pub(crate) fn rollback_undo(&mut self) -> io::Result<()> {
+        let Some(log) = self.undo.take() else {
+            return Ok(());
+        };
+        for (pos, old) in log.into_iter().rev() {
+            self.seek(SeekFrom::Start(pos))?;
+            self.write_all(&old)?;
+        }
+        self.reload_snapshots()?;
+        self.sync_all()
+    }
+
+    fn capture(&mut self, len: usize) -> io::Result<()> {
+        let pos = self.pos;
+        let mut old = vec![0u8; len];
+        let mut filled = 0;
+        while filled < len {
+            match self.read(&mut old[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        self.seek(SeekFrom::Start(pos))?;
+        if let Some(log) = &mut self.undo {
+            log.push((pos, old));
+        }
+        Ok(())
+    }
+
+    /// Truncates or extends the backing file, remapping it if mapped.
+    pub(crate) fn set_len(&mut self, len: u64) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Image is opened read-only!",
+            ));
+        }
+        #[cfg(feature = "mmap")]
+        if let Some(map) = self.map.take() {
+            map.flush()?;
+            drop(map);
+            self.file.set_len(len)?;
+            self.io_calls += 2;
+            return self.enable_mmap();
+        }
+        self.io_calls += 1;
+        self.file.set_len(self.base + len)
+    }
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn enable_mmap(&mut self) -> io::Result<()> {
+        if self.base != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Memory mapping needs the image to start at the beginning of the file!",
+            ));
+        }
+        if self.unlocked {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Memory mapping needs a locked image, not one opened read-only!",
+            ));
+        }
+        // SAFETY: disks that reach this point hold the image lock for as long
+        // as the `Vfs` lives, so no other user of this crate can resize or
+        // rewrite the file under the map.
+        self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+        Ok(())
+    }
+
+    #[cfg(feature = "mmap")]
+    fn grow_map(&mut self, len: u64) -> io::Result<()> {
+        if let Some(map) = &self.map {
+            map.flush()?;
+        }
+        self.file.set_len(len)?;
+        self.io_calls += 2;
+        self.enable_mmap()
+    }
+}
+
+impl Disk {
+    fn read_raw(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(feature = "mmap")]
+        if let Some(map) = &self.map {
+            let start = (self.pos as usize).min(map.len());
+            let n = buf.len().min(map.len() - start);
+            buf[..n].copy_from_slice(&map[start..start + n]);
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        self.io_calls += 1;
+        let n = self.file.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Read for Disk {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(feature = "journal")]
+        let start = self.pos;
+        let n = self.read_raw(buf)?;
+        #[cfg(feature = "journal")]
+        self.overlay_pending(start, &mut buf[..n]);
+        Ok(n)
+    }
+}
+
+impl Write for Disk {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Image is opened read-only!",
+            ));
+        }
+        self.writes += 1;
+        if self.undo.is_some() {
+            self.capture(buf.len())?;
+        }
+        #[cfg(feature = "journal")]
+        if let Some(pending) = &mut self.pending {
+            pending.push((self.pos, buf.to_vec()));
+            self.seek(SeekFrom::Current(buf.len() as i64))?;
+            return Ok(buf.len());
+        }
+        if !self.snapshots.is_empty() {
+            self.preserve_blocks(buf.len())?;
+        }
+        #[cfg(feature = "mmap")]
+        if self.map.is_some() {
+            let end = self.pos + buf.len() as u64;
+            if end > self.map.as_ref().map_or(0, |map| map.len() as u64) {
+                self.grow_map(end)?;
+            }
+            let map = self.map.as_mut().unwrap();
+            map[self.pos as usize..end as usize].copy_from_slice(buf);
+            self.pos = end;
+            return Ok(buf.len());
+        }
+        self.io_calls += 1;
+        let n = self.file.write(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Disk {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        #[cfg(feature = "mmap")]
+        if let Some(map) = &self.map {
+            let base = match pos {
+                SeekFrom::Start(n) => {
+                    self.pos = n;
+                    return Ok(n);
+                }
+                SeekFrom::Current(n) => (self.pos, n),
+                SeekFrom::End(n) => (map.len() as u64, n),
+            };
+            self.pos = base.0.checked_add_signed(base.1).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position!")
+            })?;
+            return Ok(self.pos);
+        }
+        self.io_calls += 1;
+        let pos = match pos {
+            SeekFrom::Start(n) => SeekFrom::Start(self.base + n),
+            other => other,
+        };
+        self.pos = self.file.seek(pos)?.checked_sub(self.base).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Seek before the image start!")
+        })?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,191 @@
+use super::Disk;
+use std::io::{self, Error, Read, Seek, SeekFrom, Write};
+
+/// The first journal block holds the commit record, the following ones the
+/// logged writes as `pos: u64, len: u32, data` records. A transaction counts
+/// as committed once the commit record carries the magic value.
+const COMMIT_MAGIC: u64 = u64::from_be_bytes(*b"VFSJRNL1");
+const COMMIT_RECORD_SIZE: usize = 16;
+
+/// Where a simulated crash interrupts the next commit, for testing recovery.
+#[cfg(feature = "crash-test")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum CrashPoint {
+    BeforeCommit,
+    AfterCommit,
+}
+
+impl Disk {
+    /// Starts holding writes back in memory. Returns `false` if a journal
+    /// transaction is already running, in which case it keeps going.
+    pub(crate) fn begin_journal(&mut self) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        self.pending = Some(Vec::new());
+        true
+    }
+
+    /// Drops the held-back writes; the image never saw them.
+    pub(crate) fn abort_journal(&mut self) {
+        self.pending = None;
+    }
+
+    #[cfg(feature = "crash-test")]
+    pub(crate) fn set_crash_point(&mut self, point: CrashPoint) {
+        self.crash_point = Some(point);
+    }
+
+    /// Fails once at `point` if a crash was requested there; later commits
+    /// go through.
+    #[cfg(feature = "crash-test")]
+    fn crash_if(&mut self, point: CrashPoint) -> io::Result<()> {
+        if self.crash_point != Some(point) {
+            return Ok(());
+        }
+        self.crash_point = None;
+        Err(Error::other("Simulated crash during journal commit!"))
+    }
+
+    /// Logs the held-back writes to the journal at `start`, commits them and
+    /// then applies them in place. The first block of the journal holds the
+    /// commit record.
+    pub(crate) fn commit_journal(
+        &mut self,
+        start: u64,
+        blocks: u32,
+        block_size: u32,
+    ) -> io::Result<()> {
+        let Some(writes) = self.pending.take() else {
+            return Ok(());
+        };
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        let mut payload = Vec::new();
+        for (pos, data) in &writes {
+            payload.extend_from_slice(&pos.to_le_bytes());
+            payload.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            payload.extend_from_slice(data);
+        }
+        if payload.len() > (blocks as usize - 1) * block_size as usize {
+            return Err(Error::new(
+                io::ErrorKind::StorageFull,
+                "Operation is too large for the journal!",
+            ));
+        }
+
+        self.seek(SeekFrom::Start(start + block_size as u64))?;
+        self.write_all(&payload)?;
+        self.sync_all()?;
+        #[cfg(feature = "crash-test")]
+        self.crash_if(CrashPoint::BeforeCommit)?;
+
+        let mut commit = [0u8; COMMIT_RECORD_SIZE];
+        commit[0..8].copy_from_slice(&COMMIT_MAGIC.to_le_bytes());
+        commit[8..12].copy_from_slice(&(writes.len() as u32).to_le_bytes());
+        commit[12..16].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.seek(SeekFrom::Start(start))?;
+        self.write_all(&commit)?;
+        self.sync_all()?;
+        #[cfg(feature = "crash-test")]
+        self.crash_if(CrashPoint::AfterCommit)?;
+
+        self.apply(&writes)?;
+        self.clear_commit(start)
+    }
+
+    /// Applies a committed transaction left behind by a crash. Logged writes
+    /// without a commit record are ignored, so an interrupted operation has
+    /// no effect. Returns whether anything was replayed. A commit record or
+    /// logged write that does not fit the journal or the image fails with
+    /// `InvalidData` without applying anything.
+    pub(crate) fn replay_journal(
+        &mut self,
+        start: u64,
+        blocks: u32,
+        block_size: u32,
+    ) -> io::Result<bool> {
+        let mut commit = [0u8; COMMIT_RECORD_SIZE];
+        self.seek(SeekFrom::Start(start))?;
+        self.read_exact(&mut commit)?;
+        if u64::from_le_bytes(commit[0..8].try_into().unwrap()) != COMMIT_MAGIC {
+            return Ok(false);
+        }
+        let count = u32::from_le_bytes(commit[8..12].try_into().unwrap()) as usize;
+        let payload_len = u32::from_le_bytes(commit[12..16].try_into().unwrap()) as usize;
+        let corrupt = |what: &str| {
+            Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Journal is corrupt: {}!", what),
+            )
+        };
+        if payload_len > (blocks as usize).saturating_sub(1) * block_size as usize {
+            return Err(corrupt("commit record is larger than the journal"));
+        }
+
+        let mut payload = vec![0u8; payload_len];
+        self.seek(SeekFrom::Start(start + block_size as u64))?;
+        self.read_exact(&mut payload)?;
+
+        let image_len = self.len()?;
+        let mut writes = Vec::new();
+        let mut rest = &payload[..];
+        for _ in 0..count {
+            if rest.len() < 12 {
+                return Err(corrupt("logged write is cut off"));
+            }
+            let pos = u64::from_le_bytes(rest[0..8].try_into().unwrap());
+            let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+            rest = &rest[12..];
+            if len > rest.len() {
+                return Err(corrupt("logged write is cut off"));
+            }
+            if pos
+                .checked_add(len as u64)
+                .is_none_or(|end| end > image_len)
+            {
+                return Err(corrupt("logged write lies past the end of the image"));
+            }
+            writes.push((pos, rest[..len].to_vec()));
+            rest = &rest[len..];
+        }
+
+        self.apply(&writes)?;
+        self.clear_commit(start)?;
+        Ok(true)
+    }
+
+    /// Makes reads see the writes of the running journal transaction.
+    pub(crate) fn overlay_pending(&self, start: u64, buf: &mut [u8]) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let end = start + buf.len() as u64;
+        for (pos, data) in pending {
+            let data_end = pos + data.len() as u64;
+            if *pos >= end || data_end <= start {
+                continue;
+            }
+            let from = start.max(*pos);
+            let to = end.min(data_end);
+            buf[(from - start) as usize..(to - start) as usize]
+                .copy_from_slice(&data[(from - pos) as usize..(to - pos) as usize]);
+        }
+    }
+
+    fn apply(&mut self, writes: &[(u64, Vec<u8>)]) -> io::Result<()> {
+        for (pos, data) in writes {
+            self.seek(SeekFrom::Start(*pos))?;
+            self.write_all(data)?;
+        }
+        self.sync_all()
+    }
+
+    fn clear_commit(&mut self, start: u64) -> io::Result<()> {
+        self.seek(SeekFrom::Start(start))?;
+        self.write_all(&[0u8; COMMIT_RECORD_SIZE])?;
+        self.sync_all()
+    }
+}
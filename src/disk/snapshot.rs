@@ -0,0 +1,417 @@
+use super::Disk;
+use crate::badblocks;
+use crate::models::SuperBlock;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Error, Read, Seek, SeekFrom, Write};
+
+/// Snapshots are kept in the image. A table in the tail of block 0 names
+/// each one and points at its first log block. A log block starts with the
+/// id of the next one and an entry count, followed by `(original, copy)`
+/// block id pairs: the copy of each piece of the metadata area, with `META`
+/// set in the original, and each data block copied aside the first time it
+/// is overwritten. Every block a snapshot stores is marked used in the live
+/// data bitmap, and blocks a snapshot uses are never handed out for that.
+const TABLE_START: u64 = 3072;
+const SLOTS: usize = 16;
+const SLOT_SIZE: usize = 64;
+const MAX_NAME_LEN: usize = 48;
+const META: u32 = 1 << 31;
+const LOG_HEADER: usize = 8;
+
+/// A point-in-time state of the image: a copy of the metadata area
+/// (superblock, bitmaps and inode table) plus the data blocks overwritten
+/// since. Only block ids are kept in memory; the contents stay on disk.
+pub(crate) struct Snapshot {
+    name: String,
+    slot: usize,
+    seq: u32,
+    sb: SuperBlock,
+    /// The data bitmap as it was when the snapshot was taken.
+    data_bitmap: Vec<u8>,
+    log_blocks: Vec<u32>,
+    /// Entries in the last log block.
+    tail_count: usize,
+    meta: Vec<u32>,
+    saved: HashMap<u32, u32>,
+    /// Every block holding a log, metadata piece or data copy.
+    stored: HashSet<u32>,
+}
+
+impl Snapshot {
+    /// Whether the snapshot needs the old contents of data block `id`. The
+    /// journal is left out: it is marked used but holds no file data.
+    fn uses_block(&self, id: u32) -> bool {
+        id != 0
+            && id < self.sb.data_block_count() - self.sb.journal_blocks
+            && self
+                .data_bitmap
+                .get((id / 8) as usize)
+                .is_some_and(|byte| byte & (1 << (id % 8)) != 0)
+    }
+}
+
+impl Disk {
+    pub(crate) fn take_snapshot(&mut self, name: &str, sb: &SuperBlock) -> io::Result<()> {
+        if self.snapshots.iter().any(|s| s.name == name) {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Snapshot '{}' already exists!", name),
+            ));
+        }
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Snapshot names must be 1 to {} bytes long!", MAX_NAME_LEN),
+            ));
+        }
+        let table = self.read_table()?;
+        let slot = (0..SLOTS)
+            .find(|&i| table[i * SLOT_SIZE + MAX_NAME_LEN] == 0)
+            .ok_or_else(|| {
+                Error::new(
+                    io::ErrorKind::StorageFull,
+                    format!("No room for another snapshot, {} exist!", SLOTS),
+                )
+            })?;
+
+        let mut metadata = vec![0u8; sb.data_blocks_start as usize];
+        self.seek(SeekFrom::Start(0))?;
+        self.read_exact(&mut metadata)?;
+        let data_bitmap =
+            metadata[sb.data_bitmap_start as usize..sb.inode_table_start as usize].to_vec();
+        let seq = self.snapshots.iter().map(|s| s.seq).max().unwrap_or(0) + 1;
+        self.snapshots.push(Snapshot {
+            name: name.to_string(),
+            slot,
+            seq,
+            sb: *sb,
+            data_bitmap,
+            log_blocks: Vec::new(),
+            tail_count: 0,
+            meta: Vec::new(),
+            saved: HashMap::new(),
+            stored: HashSet::new(),
+        });
+
+        let index = self.snapshots.len() - 1;
+        let result = self.store_metadata(index, &metadata);
+        if let Err(e) = result {
+            // Give back whatever was stored before running out of space.
+            let snapshot = self.snapshots.pop().unwrap();
+            let stored: Vec<u32> = snapshot.stored.into_iter().collect();
+            self.clear_bits(sb.data_bitmap_start, &stored)?;
+            self.write_slot_head(slot, 0)?;
+            return Err(e);
+        }
+
+        let mut record = [0u8; LOG_HEADER];
+        record[..4].copy_from_slice(&seq.to_le_bytes());
+        record[4..].copy_from_slice(&self.snapshots[index].log_blocks[0].to_le_bytes());
+        let slot_pos = TABLE_START + (slot * SLOT_SIZE) as u64;
+        self.seek(SeekFrom::Start(slot_pos))?;
+        let mut name_field = [0u8; MAX_NAME_LEN + 4];
+        name_field[..name.len()].copy_from_slice(name.as_bytes());
+        name_field[MAX_NAME_LEN] = name.len() as u8;
+        self.write_all(&name_field)?;
+        self.write_all(&record)?;
+        self.sync_all()
+    }
+
+    fn store_metadata(&mut self, index: usize, metadata: &[u8]) -> io::Result<()> {
+        let sb = self.snapshots[index].sb;
+        for (piece, chunk) in metadata.chunks(sb.block_size as usize).enumerate() {
+            let copy = self.take_free_block(index)?;
+            self.seek(SeekFrom::Start(sb.block_offset(copy)))?;
+            self.write_all(chunk)?;
+            self.snapshots[index].meta.push(copy);
+            self.append_log(index, META | piece as u32, copy)?;
+        }
+        Ok(())
+    }
+
+    /// Puts the image back the way it was when `name` was taken. That snapshot
+    /// and every later one are dropped, and their blocks become free again.
+    pub(crate) fn rollback_snapshot(&mut self, name: &str) -> io::Result<()> {
+        let index = self
+            .snapshots
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or_else(|| {
+                Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Snapshot '{}' does not exist!", name),
+                )
+            })?;
+        let snapshot = self.snapshots.split_off(index).remove(0);
+        let sb = snapshot.sb;
+        let block_size = sb.block_size as usize;
+
+        // Data first, while the live bitmap still protects every stored block.
+        // Earlier snapshots copy aside what this overwrites as usual.
+        let mut block = vec![0u8; block_size];
+        for (&original, &copy) in &snapshot.saved {
+            self.seek(SeekFrom::Start(sb.block_offset(copy)))?;
+            self.read_exact(&mut block)?;
+            self.seek(SeekFrom::Start(sb.block_offset(original)))?;
+            self.write_all(&block)?;
+        }
+
+        let mut metadata = vec![0u8; sb.data_blocks_start as usize];
+        for (chunk, &copy) in metadata.chunks_mut(block_size).zip(&snapshot.meta) {
+            self.seek(SeekFrom::Start(sb.block_offset(copy)))?;
+            self.read_exact(chunk)?;
+        }
+        self.seek(SeekFrom::Start(0))?;
+        self.write_all(&metadata)?;
+
+        // The restored table lists exactly the earlier snapshots, but the
+        // restored bitmap predates the blocks they took since.
+        let kept: Vec<u32> = self
+            .snapshots
+            .iter()
+            .flat_map(|s| s.stored.iter().copied())
+            .collect();
+        self.set_bits(sb.data_bitmap_start, &kept)?;
+        self.sync_all()
+    }
+
+    pub(crate) fn has_snapshots(&self) -> bool {
+        !self.snapshots.is_empty()
+    }
+
+    /// Reads back the snapshots recorded in the image, replacing the ones in
+    /// memory.
+    pub(crate) fn load_snapshots(&mut self, sb: &SuperBlock) -> io::Result<()> {
+        let table = self.read_table()?;
+        let block_size = sb.block_size as usize;
+        let per_block = (block_size - LOG_HEADER) / 8;
+        let corrupt = |what: &str| {
+            Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Snapshot table is corrupt: {}!", what),
+            )
+        };
+        let in_range = |id: u32| id != 0 && id < sb.data_block_count();
+
+        let mut snapshots = Vec::new();
+        for (slot, raw) in table.chunks_exact(SLOT_SIZE).enumerate() {
+            let name_len = raw[MAX_NAME_LEN] as usize;
+            if name_len == 0 {
+                continue;
+            }
+            let field = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+            let mut snapshot = Snapshot {
+                name: String::from_utf8_lossy(&raw[..name_len.min(MAX_NAME_LEN)]).into_owned(),
+                slot,
+                seq: field(MAX_NAME_LEN + 4),
+                sb: *sb,
+                data_bitmap: Vec::new(),
+                log_blocks: Vec::new(),
+                tail_count: 0,
+                meta: Vec::new(),
+                saved: HashMap::new(),
+                stored: HashSet::new(),
+            };
+
+            let mut pieces = Vec::new();
+            let mut block = vec![0u8; block_size];
+            let mut next = field(MAX_NAME_LEN + 8);
+            while next != 0 {
+                if !in_range(next) || snapshot.log_blocks.contains(&next) {
+                    return Err(corrupt("bad log block"));
+                }
+                snapshot.log_blocks.push(next);
+                self.seek(SeekFrom::Start(sb.block_offset(next)))?;
+                self.read_exact(&mut block)?;
+                let word = |at: usize| u32::from_le_bytes(block[at..at + 4].try_into().unwrap());
+                let count = (word(4) as usize).min(per_block);
+                for entry in 0..count {
+                    let original = word(LOG_HEADER + entry * 8);
+                    let copy = word(LOG_HEADER + entry * 8 + 4);
+                    if !in_range(copy) {
+                        return Err(corrupt("bad copy block"));
+                    }
+                    if original & META != 0 {
+                        pieces.push((original & !META, copy));
+                    } else {
+                        snapshot.saved.insert(original, copy);
+                    }
+                }
+                snapshot.tail_count = count;
+                next = word(0);
+            }
+            snapshot.stored.extend(&snapshot.log_blocks);
+            snapshot.stored.extend(pieces.iter().map(|&(_, copy)| copy));
+            snapshot.stored.extend(snapshot.saved.values());
+            pieces.sort_unstable();
+            snapshot.meta = pieces.into_iter().map(|(_, copy)| copy).collect();
+            if snapshot.meta.len() != sb.data_blocks_start.div_ceil(sb.block_size as u64) as usize {
+                return Err(corrupt("metadata copy is incomplete"));
+            }
+            snapshot.data_bitmap = self.read_stored_metadata(
+                sb,
+                &snapshot.meta,
+                sb.data_bitmap_start,
+                sb.inode_table_start,
+            )?;
+            snapshots.push(snapshot);
+        }
+        snapshots.sort_by_key(|s| s.seq);
+        self.snapshots = snapshots;
+        Ok(())
+    }
+
+    /// Rereads the snapshots after writes were undone, since the copies made
+    /// for those writes were undone with them.
+    pub(super) fn reload_snapshots(&mut self) -> io::Result<()> {
+        match self.snapshots.first().map(|s| s.sb) {
+            Some(sb) => self.load_snapshots(&sb),
+            None => Ok(()),
+        }
+    }
+
+    /// Bytes `start..end` of the metadata area as stored in `meta`.
+    fn read_stored_metadata(
+        &mut self,
+        sb: &SuperBlock,
+        meta: &[u32],
+        start: u64,
+        end: u64,
+    ) -> io::Result<Vec<u8>> {
+        let block_size = sb.block_size as u64;
+        let mut out = vec![0u8; (end - start) as usize];
+        let mut pos = start;
+        while pos < end {
+            let within = pos % block_size;
+            let n = (block_size - within).min(end - pos);
+            let copy = meta[(pos / block_size) as usize];
+            self.seek(SeekFrom::Start(sb.block_offset(copy) + within))?;
+            let at = (pos - start) as usize;
+            self.read_exact(&mut out[at..at + n as usize])?;
+            pos += n;
+        }
+        Ok(out)
+    }
+
+    /// Copies aside the blocks about to be overwritten by a write of `len`
+    /// bytes at the current position, for every snapshot still using them.
+    pub(super) fn preserve_blocks(&mut self, len: usize) -> io::Result<()> {
+        let pos = self.pos;
+        let end = pos + len as u64;
+        for i in 0..self.snapshots.len() {
+            let sb = self.snapshots[i].sb;
+            if end <= sb.data_blocks_start {
+                continue;
+            }
+            let (data_start, block_size) = (sb.data_blocks_start, sb.block_size as u64);
+            let first = (pos.max(data_start) - data_start) / block_size;
+            let last = (end - 1 - data_start) / block_size;
+            for id in first as u32..=last as u32 {
+                // Blocks holding snapshot data are never file data, so no
+                // snapshot needs them as they were.
+                let snapshot = &self.snapshots[i];
+                if !snapshot.uses_block(id)
+                    || snapshot.saved.contains_key(&id)
+                    || self.snapshots.iter().any(|s| s.stored.contains(&id))
+                {
+                    continue;
+                }
+                let mut block = vec![0u8; block_size as usize];
+                self.seek(SeekFrom::Start(sb.block_offset(id)))?;
+                self.read_raw_exact(&mut block)?;
+                let copy = self.take_free_block(i)?;
+                self.seek(SeekFrom::Start(sb.block_offset(copy)))?;
+                self.write_all(&block)?;
+                self.snapshots[i].saved.insert(id, copy);
+                self.append_log(i, id, copy)?;
+            }
+        }
+        self.seek(SeekFrom::Start(pos))?;
+        Ok(())
+    }
+
+    /// Claims a data block for snapshot `index` that is free and that no
+    /// snapshot needs, marking it used in the live bitmap.
+    fn take_free_block(&mut self, index: usize) -> io::Result<u32> {
+        let sb = self.snapshots[index].sb;
+        let bitmap = badblocks::data_bitmap(self, &sb)?;
+        let end = sb.data_block_count() - sb.journal_blocks;
+        let id = (1..end)
+            .find(|&id| {
+                bitmap[(id / 8) as usize] & (1 << (id % 8)) == 0
+                    && !self.snapshots.iter().any(|s| s.uses_block(id))
+            })
+            .ok_or_else(|| {
+                Error::new(
+                    io::ErrorKind::StorageFull,
+                    "No free blocks left to keep snapshot data in!",
+                )
+            })?;
+        self.set_bits(sb.data_bitmap_start, &[id])?;
+        self.snapshots[index].stored.insert(id);
+        Ok(id)
+    }
+
+    /// Records `(original, copy)` in the snapshot's log, starting a new log
+    /// block when the last one is full.
+    fn append_log(&mut self, index: usize, original: u32, copy: u32) -> io::Result<()> {
+        let sb = self.snapshots[index].sb;
+        let per_block = (sb.block_size as usize - LOG_HEADER) / 8;
+        let snapshot = &self.snapshots[index];
+        if snapshot.log_blocks.is_empty() || snapshot.tail_count == per_block {
+            let previous = snapshot.log_blocks.last().copied();
+            let slot = snapshot.slot;
+            let block = self.take_free_block(index)?;
+            self.zero_region(sb.block_offset(block), sb.block_size as u64)?;
+            match previous {
+                Some(previous) => {
+                    self.seek(SeekFrom::Start(sb.block_offset(previous)))?;
+                    self.write_all(&block.to_le_bytes())?;
+                }
+                None => self.write_slot_head(slot, block)?,
+            }
+            let snapshot = &mut self.snapshots[index];
+            snapshot.log_blocks.push(block);
+            snapshot.tail_count = 0;
+        }
+
+        let snapshot = &mut self.snapshots[index];
+        let tail = *snapshot.log_blocks.last().unwrap();
+        let entry = snapshot.tail_count;
+        snapshot.tail_count += 1;
+        let count = snapshot.tail_count as u32;
+
+        let mut pair = [0u8; 8];
+        pair[..4].copy_from_slice(&original.to_le_bytes());
+        pair[4..].copy_from_slice(&copy.to_le_bytes());
+        let log_pos = sb.block_offset(tail);
+        self.seek(SeekFrom::Start(log_pos + (LOG_HEADER + entry * 8) as u64))?;
+        self.write_all(&pair)?;
+        self.seek(SeekFrom::Start(log_pos + 4))?;
+        self.write_all(&count.to_le_bytes())
+    }
+
+    fn write_slot_head(&mut self, slot: usize, block: u32) -> io::Result<()> {
+        let pos = TABLE_START + (slot * SLOT_SIZE + MAX_NAME_LEN + 8) as u64;
+        self.seek(SeekFrom::Start(pos))?;
+        self.write_all(&block.to_le_bytes())
+    }
+
+    fn read_table(&mut self) -> io::Result<Vec<u8>> {
+        let mut table = vec![0u8; SLOTS * SLOT_SIZE];
+        self.seek(SeekFrom::Start(TABLE_START))?;
+        self.read_exact(&mut table)?;
+        Ok(table)
+    }
+
+    fn read_raw_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read_raw(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use crate::disk::Disk;
+use crate::models::{DIR_SIZE, DirEntry, Inode, SuperBlock};
+use crate::{bit_set, entry_name};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+/// Active entry names of a directory, read one block at a time as the
+/// iterator advances. Returned by `Vfs::entries`.
+pub struct EntryIter {
+    file: Rc<RefCell<Disk>>,
+    sb: SuperBlock,
+    dir_inode: Inode,
+    block_index: u32,
+    pending: VecDeque<String>,
+}
+
+impl EntryIter {
+    pub(crate) fn new(file: Rc<RefCell<Disk>>, sb: SuperBlock, dir_inode: Inode) -> Self {
+        EntryIter {
+            file,
+            sb,
+            dir_inode,
+            block_index: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn physical_block(&self, block_index: u32) -> io::Result<Option<u32>> {
+        if block_index < 10 {
+            let id = self.dir_inode.direct_blocks[block_index as usize];
+            return Ok(if id == 0 { None } else { Some(id) });
+        }
+
+        if self.dir_inode.indirect_blocks == 0 {
+            return Ok(None);
+        }
+
+        let indirect_idx = block_index - 10;
+        let pointer_pos =
+            self.sb.block_offset(self.dir_inode.indirect_blocks) + (indirect_idx as u64 * 4);
+
+        let mut buf = [0u8; 4];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(pointer_pos))?;
+        file.read_exact(&mut buf)?;
+        let id = u32::from_le_bytes(buf);
+
+        Ok(if id == 0 { None } else { Some(id) })
+    }
+
+    /// Queues the active names of the next block. Returns false once the
+    /// directory has no more blocks.
+    fn load_next_block(&mut self) -> io::Result<bool> {
+        if self.block_index >= self.sb.max_file_blocks() {
+            return Ok(false);
+        }
+        let physical_id = match self.physical_block(self.block_index)? {
+            Some(id) => id,
+            None => {
+                self.block_index = self.sb.max_file_blocks();
+                return Ok(false);
+            }
+        };
+        self.block_index += 1;
+
+        let mut block = vec![0u8; self.sb.entries_per_block() * DIR_SIZE];
+        let mut inode_bitmap =
+            vec![0u8; (self.sb.data_bitmap_start - self.sb.inode_bitmap_start) as usize];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(self.sb.block_offset(physical_id)))?;
+        file.read_exact(&mut block)?;
+        file.seek(SeekFrom::Start(self.sb.inode_bitmap_start))?;
+        file.read_exact(&mut inode_bitmap)?;
+
+        // Like `read_dir`, leave out entries whose inode is not allocated.
+        let inode_count = self.sb.inode_count();
+        self.pending.extend(
+            block
+                .chunks_exact(DIR_SIZE)
+                .map(DirEntry::from_bytes)
+                .filter(|entry| {
+                    entry.is_active == 1 && bit_set(&inode_bitmap, inode_count, entry.inode_id)
+                })
+                .map(|entry| entry_name(&entry).into_owned()),
+        );
+        Ok(true)
+    }
+}
+
+impl Iterator for EntryIter {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(name) = self.pending.pop_front() {
+                return Some(Ok(name));
+            }
+            match self.load_next_block() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => {
+                    // Stop after the first error instead of retrying forever.
+                    self.block_index = self.sb.max_file_blocks();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
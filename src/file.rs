@@ -1,25 +1,515 @@
-use crate::models::{BLOCK_SIZE, INODE_SIZE, Inode, SuperBlock};
-use std::cell::RefCell;
-use std::fs::File;
-use std::io::{self, Error, Read, Seek, SeekFrom, Write};
+use crate::badblocks;
+use crate::disk::Disk;
+use crate::models::{INODE_SIZE, Inode, InodeKind, SuperBlock, SyncPolicy};
+use crate::quota;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Error, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
 use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum LockState {
+    Shared(usize),
+    Exclusive,
+}
+
+/// Advisory locks held on each inode, shared by a `Vfs` and its handles.
+pub(crate) type LockTable = Rc<RefCell<HashMap<u32, LockState>>>;
+
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct OpenState {
+    pub(crate) handles: usize,
+    pub(crate) unlinked: bool,
+}
+
+/// Open handles per inode, and whether the inode was removed while open.
+pub(crate) type OpenTable = Rc<RefCell<HashMap<u32, OpenState>>>;
+
 pub struct VfsFile {
-    pub(crate) file: Rc<RefCell<File>>,
+    pub(crate) file: Rc<RefCell<Disk>>,
     pub(crate) sb: SuperBlock,
+    pub(crate) locks: LockTable,
+    pub(crate) lock: Option<LockMode>,
+    pub(crate) open: OpenTable,
+    /// The file's inode id, the same one `Vfs::find_inode_by_path` resolves
+    /// its path to. It stays the same across renames and reopening the image
+    /// until the file is removed.
     pub inode_id: u32,
-    pub position: u64,
+    /// Only moved through `seek` or `set_position`, which keep it in range.
+    pub(crate) position: u64,
+    /// Read-ahead: file bytes from `buffer_start` onward, shared by `read` and
+    /// `BufRead`. Cleared by seeks and by writes to the image.
+    pub(crate) buffer: Vec<u8>,
+    pub(crate) buffer_start: u64,
+    /// Disk write count, and the inode's size and modification time, when
+    /// the buffer was filled.
+    pub(crate) buffer_writes: u64,
+    pub(crate) buffer_inode: (u64, u64),
+    pub(crate) sync_policy: Rc<Cell<SyncPolicy>>,
+    /// Generation of the inode when the handle was opened.
+    pub(crate) generation: u32,
 }
 
 impl VfsFile {
+    /// Takes an advisory exclusive lock on the inode. Locks are only honored
+    /// by callers that take them; reads and writes never check them.
+    pub fn lock_exclusive(&mut self) -> io::Result<()> {
+        let mut locks = self.locks.borrow_mut();
+        match (locks.get(&self.inode_id), self.lock) {
+            (_, Some(LockMode::Exclusive)) => return Ok(()),
+            (None, _) | (Some(LockState::Shared(1)), Some(LockMode::Shared)) => {}
+            _ => return Err(lock_conflict()),
+        }
+        locks.insert(self.inode_id, LockState::Exclusive);
+        self.lock = Some(LockMode::Exclusive);
+        Ok(())
+    }
+
+    pub fn lock_shared(&mut self) -> io::Result<()> {
+        let mut locks = self.locks.borrow_mut();
+        let next = match (locks.get(&self.inode_id), self.lock) {
+            (_, Some(LockMode::Shared)) => return Ok(()),
+            (_, Some(LockMode::Exclusive)) | (None, _) => LockState::Shared(1),
+            (Some(LockState::Shared(n)), None) => LockState::Shared(n + 1),
+            (Some(LockState::Exclusive), None) => return Err(lock_conflict()),
+        };
+        locks.insert(self.inode_id, next);
+        self.lock = Some(LockMode::Shared);
+        Ok(())
+    }
+
+    pub fn unlock(&mut self) -> io::Result<()> {
+        let mut locks = self.locks.borrow_mut();
+        match (locks.get(&self.inode_id).copied(), self.lock.take()) {
+            (Some(LockState::Shared(n)), Some(LockMode::Shared)) if n > 1 => {
+                locks.insert(self.inode_id, LockState::Shared(n - 1));
+            }
+            (Some(_), Some(_)) => {
+                locks.remove(&self.inode_id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Offset the next read or write starts at.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Moves to `pos`, like `seek(SeekFrom::Start(pos))`. Positions past the
+    /// maximum file size fail with `InvalidInput` and leave it unchanged.
+    pub fn set_position(&mut self, pos: u64) -> io::Result<()> {
+        self.seek(SeekFrom::Start(pos)).map(|_| ())
+    }
+
+    /// Current size of the file in bytes.
+    pub fn len(&self) -> io::Result<u64> {
+        Ok(self.get_inode()?.size)
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    pub fn is_dir(&self) -> io::Result<bool> {
+        Ok(self.inode_kind()? == InodeKind::Dir)
+    }
+
+    pub fn inode_kind(&self) -> io::Result<InodeKind> {
+        Ok(self.get_inode()?.inode_type)
+    }
+
+    /// Reads the whole block holding the current position, from its start,
+    /// into the front of `buf` and moves to the start of the next block.
+    /// Returns a full block, less for the file's last block, and 0 at the end.
+    /// `buf` must hold at least one block of the image.
+    pub fn read_block_aligned(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bs = self.sb.block_size as u64;
+        if (buf.len() as u64) < bs {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Buffer of {} bytes is smaller than a block of {} bytes!",
+                    buf.len(),
+                    bs
+                ),
+            ));
+        }
+        let start = self.position - self.position % bs;
+        self.position = start;
+        let n = self.read_direct(&mut buf[..bs as usize])?;
+        if n > 0 {
+            self.position = start + bs;
+        }
+        Ok(n)
+    }
+
+    /// Offset of the first byte at or after `from` that lies in an allocated
+    /// block, like `SEEK_DATA`. `None` if only holes remain before the end.
+    pub fn next_data_offset(&self, from: u64) -> io::Result<Option<u64>> {
+        self.next_boundary(from, true)
+    }
+
+    /// Offset of the first byte at or after `from` that lies in a hole, like
+    /// `SEEK_HOLE`. The end of the file counts as a hole, so this is only
+    /// `None` when `from` is at or past the end.
+    pub fn next_hole_offset(&self, from: u64) -> io::Result<Option<u64>> {
+        self.next_boundary(from, false)
+    }
+
+    fn next_boundary(&self, from: u64, data: bool) -> io::Result<Option<u64>> {
+        let inode = self.get_inode()?;
+        if from >= inode.size {
+            return Ok(None);
+        }
+        let bs = self.sb.block_size as u64;
+        let end_block = inode.size.div_ceil(bs) as u32;
+        for block_index in (from / bs) as u32..end_block {
+            if self.just_read(&inode, block_index)?.is_some() == data {
+                return Ok(Some(from.max(block_index as u64 * bs)));
+            }
+        }
+        Ok(if data { None } else { Some(inode.size) })
+    }
+
+    /// Opens a second handle to the same inode. It starts at this handle's
+    /// position but seeks independently, and holds no lock.
+    pub fn try_clone(&self) -> io::Result<VfsFile> {
+        self.get_inode()?;
+        self.open
+            .borrow_mut()
+            .entry(self.inode_id)
+            .or_default()
+            .handles += 1;
+        Ok(VfsFile {
+            file: Rc::clone(&self.file),
+            sb: self.sb,
+            locks: Rc::clone(&self.locks),
+            lock: None,
+            open: Rc::clone(&self.open),
+            inode_id: self.inode_id,
+            position: self.position,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            buffer_writes: 0,
+            buffer_inode: (0, 0),
+            sync_policy: Rc::clone(&self.sync_policy),
+            generation: self.generation,
+        })
+    }
+
+    /// Allocates every block needed to hold `position + additional` bytes so
+    /// later writes in that range cannot run out of space. The file size is
+    /// not changed. Fails without allocating anything if the image does not
+    /// have enough free blocks.
+    pub fn reserve(&mut self, additional: u64) -> io::Result<()> {
+        let end = self.position.saturating_add(additional);
+        let block_count = end.div_ceil(self.sb.block_size as u64);
+        let max_blocks = self.sb.max_file_blocks() as u64;
+        if block_count > max_blocks {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!(
+                    "File is too large! Maximum {} bytes ({} blocks)!",
+                    self.sb.max_file_size(),
+                    max_blocks
+                ),
+            ));
+        }
+
+        self.allocate_block_range(0, block_count as u32)
+    }
+
+    /// Sets the file size to `len`. Shrinking frees every block past the new
+    /// end, reserved ones included, and clears the rest of the new last block;
+    /// growing leaves a hole that reads back as zeros. The position is kept.
+    pub fn set_len(&mut self, len: u64) -> io::Result<()> {
+        if len > self.sb.max_file_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!(
+                    "Length {} is past the maximum file size of {} bytes!",
+                    len,
+                    self.sb.max_file_size()
+                ),
+            ));
+        }
+        let mut inode = self.get_inode()?;
+        if inode.inode_type == InodeKind::Dir {
+            return Err(Error::new(
+                io::ErrorKind::IsADirectory,
+                "Cannot change the length of a directory!",
+            ));
+        }
+        crate::check_mutable(&inode)?;
+        self.buffer.clear();
+
+        let bs = self.sb.block_size as u64;
+        let keep = len.div_ceil(bs) as u32;
+        if len < inode.size
+            && !len.is_multiple_of(bs)
+            && let Some(id) = self.just_read(&inode, keep - 1)?
+        {
+            let offset = len % bs;
+            self.file
+                .borrow_mut()
+                .zero_region(self.sb.block_offset(id) + offset, bs - offset)?;
+        }
+
+        let mut freed = Vec::new();
+        for slot in inode.direct_blocks.iter_mut().skip(keep as usize) {
+            if *slot != 0 {
+                freed.push(*slot);
+                *slot = 0;
+            }
+        }
+        if inode.indirect_blocks != 0 {
+            let mut pointers = vec![0u8; bs as usize];
+            let pos = self.sb.block_offset(inode.indirect_blocks);
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(pos))?;
+            file.read_exact(&mut pointers)?;
+            for pointer in pointers
+                .chunks_exact_mut(4)
+                .skip(keep.saturating_sub(10) as usize)
+            {
+                let id = u32::from_le_bytes(pointer.try_into().unwrap());
+                if id != 0 {
+                    freed.push(id);
+                    pointer.fill(0);
+                }
+            }
+            if keep <= 10 {
+                freed.push(inode.indirect_blocks);
+                inode.indirect_blocks = 0;
+            } else {
+                file.seek(SeekFrom::Start(pos))?;
+                file.write_all(&pointers)?;
+            }
+        }
+
+        self.file
+            .borrow_mut()
+            .clear_bits(self.sb.data_bitmap_start, &freed)?;
+        quota::charge(
+            &mut self.file.borrow_mut(),
+            &self.sb,
+            self.inode_id,
+            -(freed.len() as i64),
+        )?;
+
+        inode.size = len;
+        inode.modified_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::other(e.to_string()))?
+            .as_secs();
+        self.save_inode(&inode)?;
+        self.sync_write()
+    }
+
+    /// Makes sure blocks `first_block..end_block` are allocated, taking all
+    /// missing ones (plus the pointer block, if needed) in one bitmap pass.
+    fn allocate_block_range(&self, first_block: u32, end_block: u32) -> io::Result<()> {
+        let mut inode = self.get_inode()?;
+        let mut missing = Vec::new();
+        for block_index in first_block..end_block {
+            if self.just_read(&inode, block_index)?.is_none() {
+                missing.push(block_index);
+            }
+        }
+        let needs_pointer_block = end_block > 10 && inode.indirect_blocks == 0;
+        let count = missing.len() as u32 + needs_pointer_block as u32;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut ids = self.allocate_data_blocks(count)?;
+        let mut file = self.file.borrow_mut();
+        if needs_pointer_block {
+            // Taken from the end of the batch so the data blocks stay adjacent.
+            inode.indirect_blocks = ids.pop().unwrap();
+        }
+
+        for (block_index, id) in missing.into_iter().zip(ids) {
+            if block_index < 10 {
+                inode.direct_blocks[block_index as usize] = id;
+            } else {
+                let pointer_pos =
+                    self.sb.block_offset(inode.indirect_blocks) + ((block_index - 10) as u64 * 4);
+                file.seek(SeekFrom::Start(pointer_pos))?;
+                file.write_all(&id.to_le_bytes())?;
+            }
+        }
+        drop(file);
+        self.save_inode(&inode)
+    }
+
+    /// Allocates `count` data blocks in a single pass over the bitmap,
+    /// preferring one contiguous run. Nothing is allocated if fewer than
+    /// `count` blocks are free.
+    fn allocate_data_blocks(&self, count: u32) -> io::Result<Vec<u32>> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        quota::charge(
+            &mut self.file.borrow_mut(),
+            &self.sb,
+            self.inode_id,
+            count as i64,
+        )?;
+        let ids = match self.take_free_blocks(count) {
+            Ok(ids) => ids,
+            Err(e) => {
+                quota::charge(
+                    &mut self.file.borrow_mut(),
+                    &self.sb,
+                    self.inode_id,
+                    -(count as i64),
+                )?;
+                return Err(e);
+            }
+        };
+        self.zero_blocks(&ids)?;
+        Ok(ids)
+    }
+
+    /// Clears freshly allocated blocks so the parts of them a write does not
+    /// cover read back as zeros instead of a removed file's old contents.
+    /// Adjacent ids are cleared as one region.
+    fn zero_blocks(&self, ids: &[u32]) -> io::Result<()> {
+        let mut file = self.file.borrow_mut();
+        let mut rest = ids;
+        while let Some(&first) = rest.first() {
+            let run = rest
+                .iter()
+                .enumerate()
+                .take_while(|&(i, &id)| id == first + i as u32)
+                .count();
+            file.zero_region(
+                self.sb.block_offset(first),
+                run as u64 * self.sb.block_size as u64,
+            )?;
+            rest = &rest[run..];
+        }
+        Ok(())
+    }
+
+    fn take_free_blocks(&self, count: u32) -> io::Result<Vec<u32>> {
+        let start = self.sb.data_bitmap_start;
+        let mut file = self.file.borrow_mut();
+        let mut bitmap = badblocks::data_bitmap(&mut file, &self.sb)?;
+        if self.sb.reserved_blocks > 0
+            && crate::count_clear_bits(&bitmap, self.sb.data_block_count())
+                < count + self.sb.reserved_blocks
+        {
+            return Err(Error::new(
+                io::ErrorKind::StorageFull,
+                "No more free blocks outside the reserved space!",
+            ));
+        }
+
+        let mut first_fit = Vec::with_capacity(count as usize);
+        let mut run = None;
+        let mut run_start = 0;
+        let mut run_len = 0;
+        for bit in 0..(bitmap.len() * 8) as u32 {
+            if bitmap[(bit / 8) as usize] & (1 << (bit % 8)) != 0 {
+                run_len = 0;
+                continue;
+            }
+            if run_len == 0 {
+                run_start = bit;
+            }
+            run_len += 1;
+            if first_fit.len() < count as usize {
+                first_fit.push(bit);
+            }
+            if run_len == count {
+                run = Some(run_start);
+                break;
+            }
+        }
+
+        let ids: Vec<u32> = match run {
+            Some(first) => (first..first + count).collect(),
+            None if first_fit.len() == count as usize => first_fit,
+            None => {
+                return Err(Error::new(
+                    io::ErrorKind::StorageFull,
+                    "No more free blocks!",
+                ));
+            }
+        };
+
+        for id in &ids {
+            bitmap[(id / 8) as usize] |= 1 << (id % 8);
+        }
+        let first_byte = (ids[0] / 8) as usize;
+        let last_byte = (ids[ids.len() - 1] / 8) as usize;
+        file.seek(SeekFrom::Start(start + first_byte as u64))?;
+        file.write_all(&bitmap[first_byte..=last_byte])?;
+        Ok(ids)
+    }
+
+    /// Frees the inode and all of its blocks. Called when the last handle to a
+    /// file that was removed while open goes away.
+    fn release(&self) -> io::Result<()> {
+        let inode = self.get_inode()?;
+        crate::free_file_blocks(&mut self.file.borrow_mut(), &self.sb, &inode)?;
+        self.free_bit(self.sb.inode_bitmap_start, self.inode_id)?;
+        self.file.borrow_mut().sync_all()
+    }
+
+    fn free_bit(&self, start_offset: u64, bit_idx: u32) -> io::Result<()> {
+        let pos = start_offset + (bit_idx / 8) as u64;
+        let mut byte = [0u8; 1];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut byte)?;
+        byte[0] &= !(1 << (bit_idx % 8));
+        file.seek(SeekFrom::Start(pos))?;
+        file.write_all(&byte)
+    }
+
+    fn sync_write(&self) -> io::Result<()> {
+        if self.sync_policy.get() != SyncPolicy::EveryWrite {
+            return Ok(());
+        }
+        self.file.borrow_mut().sync_all()
+    }
+
+    /// Fails with `StaleNetworkFileHandle` once the inode slot holds a
+    /// different file than the one this handle was opened on.
     fn get_inode(&self) -> io::Result<Inode> {
         let pos = self.sb.inode_table_start + (self.inode_id as u64 * INODE_SIZE as u64);
         let mut buffer = [0u8; INODE_SIZE];
         let mut file = self.file.borrow_mut();
         file.seek(SeekFrom::Start(pos))?;
         file.read_exact(&mut buffer)?;
-        Ok(Inode::from_bytes(&buffer))
+        let inode = Inode::from_bytes(&buffer)?;
+        self.check_generation(&inode)?;
+        Ok(inode)
+    }
+
+    fn check_generation(&self, inode: &Inode) -> io::Result<()> {
+        if inode.generation != self.generation {
+            return Err(Error::new(
+                io::ErrorKind::StaleNetworkFileHandle,
+                format!(
+                    "Handle is stale: inode {} was reused since it was opened!",
+                    self.inode_id
+                ),
+            ));
+        }
+        Ok(())
     }
 
     fn save_inode(&self, inode: &Inode) -> io::Result<()> {
@@ -30,37 +520,31 @@ impl VfsFile {
         Ok(())
     }
 
-    fn allocate_data_block(&self) -> io::Result<u32> {
-        let total_bytes = self.sb.inode_table_start - self.sb.data_bitmap_start;
-        let mut buffer = [0u8; 512];
+    /// Raises the stored size to `end` unless another handle already wrote
+    /// past it, and marks the inode modified and valid again. The inode is
+    /// re-read and saved under one borrow of the disk, so the size written
+    /// is never older than what is on disk.
+    fn grow_to(&self, end: u64) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::other(e.to_string()))?
+            .as_secs();
+        let pos = self.sb.inode_table_start + (self.inode_id as u64 * INODE_SIZE as u64);
+        let mut buffer = [0u8; INODE_SIZE];
         let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut buffer)?;
+        let mut inode = Inode::from_bytes(&buffer)?;
+        self.check_generation(&inode)?;
+        inode.size = inode.size.max(end);
+        inode.modified_at = now;
+        inode.is_valid = 1;
+        file.seek(SeekFrom::Start(pos))?;
+        file.write_all(&inode.to_bytes())
+    }
 
-        for chunk_idx in 0..(total_bytes / 512 + 1) {
-            let current_offset = self.sb.data_bitmap_start + (chunk_idx * 512);
-            let to_read = std::cmp::min(512, total_bytes - (chunk_idx * 512));
-            if to_read == 0 {
-                break;
-            }
-
-            file.seek(SeekFrom::Start(current_offset))?;
-            file.read_exact(&mut buffer[..to_read as usize])?;
-
-            for (byte_idx, byte) in buffer[..to_read as usize].iter_mut().enumerate() {
-                if *byte != 0xFF {
-                    for bit_idx in 0..8 {
-                        if (*byte & (1 << bit_idx)) == 0 {
-                            *byte |= 1 << bit_idx;
-                            file.seek(SeekFrom::Start(current_offset + byte_idx as u64))?;
-                            file.write_all(&[*byte])?;
-                            return Ok((chunk_idx as u32 * 512 * 8)
-                                + (byte_idx as u32 * 8)
-                                + bit_idx as u32);
-                        }
-                    }
-                }
-            }
-        }
-        Err(Error::other("No more free blocks!"))
+    fn allocate_data_block(&self) -> io::Result<u32> {
+        Ok(self.allocate_data_blocks(1)?[0])
     }
 
     fn allocate_indirect_or_direct_blocks(&self, block_index: u32) -> io::Result<u32> {
@@ -78,31 +562,26 @@ impl VfsFile {
         }
 
         let indirect_block_index = block_index - 10;
-        let max_pointers_per_block = (BLOCK_SIZE / 4) as u32;
+        let max_pointers_per_block = self.sb.pointers_per_block();
         if indirect_block_index >= max_pointers_per_block {
             return Err(io::Error::new(
                 io::ErrorKind::FileTooLarge,
                 format!(
-                    "File is too large! Maximum {} blocks!",
-                    10 + max_pointers_per_block
+                    "File is too large! Maximum {} bytes ({} blocks)!",
+                    self.sb.max_file_size(),
+                    self.sb.max_file_blocks()
                 ),
             ));
         }
 
-        if inode.indirect_blocks == 0 {
+        let fresh_pointer_block = inode.indirect_blocks == 0;
+        if fresh_pointer_block {
             let new_pointer_block = self.allocate_data_block()?;
             inode.indirect_blocks = new_pointer_block;
             self.save_inode(&inode)?;
-            let buffer = vec![0u8; BLOCK_SIZE];
-            let disk_position =
-                self.sb.data_blocks_start + (new_pointer_block as u64 * BLOCK_SIZE as u64);
-            let mut file = self.file.borrow_mut();
-            file.seek(SeekFrom::Start(disk_position))?;
-            file.write_all(&buffer)?;
         }
 
-        let indirect_block_disk_start =
-            self.sb.data_blocks_start + (inode.indirect_blocks as u64 * BLOCK_SIZE as u64);
+        let indirect_block_disk_start = self.sb.block_offset(inode.indirect_blocks);
         let pointer_address_on_disk = indirect_block_disk_start + (indirect_block_index as u64 * 4);
 
         let mut pointer_bytes = [0u8; 4];
@@ -114,7 +593,16 @@ impl VfsFile {
 
         if data_block_pointer == 0 {
             drop(file);
-            data_block_pointer = self.allocate_data_block()?;
+            data_block_pointer = match self.allocate_data_block() {
+                Ok(id) => id,
+                Err(e) => {
+                    // Do not keep a pointer block that points nowhere.
+                    if fresh_pointer_block {
+                        self.release_pointer_block(&mut inode)?;
+                    }
+                    return Err(e);
+                }
+            };
             let mut file = self.file.borrow_mut();
             file.seek(SeekFrom::Start(pointer_address_on_disk))?;
             file.write_all(&data_block_pointer.to_le_bytes())?;
@@ -123,6 +611,13 @@ impl VfsFile {
         Ok(data_block_pointer)
     }
 
+    fn release_pointer_block(&self, inode: &mut Inode) -> io::Result<()> {
+        self.free_bit(self.sb.data_bitmap_start, inode.indirect_blocks)?;
+        quota::charge(&mut self.file.borrow_mut(), &self.sb, self.inode_id, -1)?;
+        inode.indirect_blocks = 0;
+        self.save_inode(inode)
+    }
+
     fn just_read(&self, inode: &Inode, block_index: u32) -> io::Result<Option<u32>> {
         if block_index < 10 {
             let id = inode.direct_blocks[block_index as usize];
@@ -134,9 +629,7 @@ impl VfsFile {
         }
 
         let indirect_idx = block_index - 10;
-        let pointer_pos = self.sb.data_blocks_start
-            + (inode.indirect_blocks as u64 * BLOCK_SIZE as u64)
-            + (indirect_idx as u64 * 4);
+        let pointer_pos = self.sb.block_offset(inode.indirect_blocks) + (indirect_idx as u64 * 4);
 
         let mut buf = [0u8; 4];
         let mut file = self.file.borrow_mut();
@@ -146,124 +639,323 @@ impl VfsFile {
 
         Ok(if id == 0 { None } else { Some(id) })
     }
+
+    fn read_direct(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inode = self.get_inode()?;
+        if inode.inode_type == InodeKind::Dir {
+            return Err(Error::new(
+                io::ErrorKind::IsADirectory,
+                "Cannot read a directory as a file, use read_dir!",
+            ));
+        }
+        if self.position >= inode.size {
+            return Ok(0);
+        }
+
+        // Fill as much of `buf` as the file allows, reading runs of physically
+        // adjacent blocks with a single disk access.
+        let end = inode.size.min(self.position + buf.len() as u64);
+        let mut filled = 0;
+        while self.position < end {
+            let block_idx = (self.position / self.sb.block_size as u64) as u32;
+            let offset = (self.position % self.sb.block_size as u64) as usize;
+            let mut len =
+                (self.sb.block_size as usize - offset).min((end - self.position) as usize);
+
+            match self.just_read(&inode, block_idx)? {
+                None => buf[filled..filled + len].fill(0),
+                Some(block_id) => {
+                    let mut last_id = block_id;
+                    let mut next_idx = block_idx + 1;
+                    while self.position + (len as u64) < end {
+                        match self.just_read(&inode, next_idx)? {
+                            Some(id) if id == last_id + 1 => {
+                                last_id = id;
+                                next_idx += 1;
+                                len += (self.sb.block_size as usize)
+                                    .min((end - self.position) as usize - len);
+                            }
+                            _ => break,
+                        }
+                    }
+
+                    let disk_pos = self.sb.block_offset(block_id) + offset as u64;
+                    let mut file = self.file.borrow_mut();
+                    file.seek(SeekFrom::Start(disk_pos))?;
+                    file.read_exact(&mut buf[filled..filled + len])?;
+                }
+            }
+            filled += len;
+            self.position += len as u64;
+        }
+        Ok(filled)
+    }
+
+    /// Drops the read-ahead buffer once anything was written through this
+    /// image since it was filled, or, when others may write the image, once
+    /// the inode's size or modification time changed. A reused inode fails
+    /// with `StaleNetworkFileHandle` instead of returning old bytes.
+    fn check_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.file.borrow().writes() != self.buffer_writes {
+            self.buffer.clear();
+            return Ok(());
+        }
+        if self.file.borrow().is_unlocked() {
+            let inode = self.get_inode()?;
+            if (inode.size, inode.modified_at) != self.buffer_inode {
+                self.buffer.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies from the read-ahead buffer if it covers the current position.
+    fn read_buffered(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.position < self.buffer_start || self.position >= buffer_end {
+            return None;
+        }
+        let start = (self.position - self.buffer_start) as usize;
+        let n = buf.len().min(self.buffer.len() - start);
+        buf[..n].copy_from_slice(&self.buffer[start..start + n]);
+        self.position += n as u64;
+        Some(n)
+    }
+
+    /// Loads the rest of the current block and the whole next one into the
+    /// buffer, without moving the position.
+    fn fill_read_ahead(&mut self) -> io::Result<()> {
+        let block_size = self.sb.block_size as usize;
+        let offset = (self.position % block_size as u64) as usize;
+        let position = self.position;
+        let inode = self.get_inode()?;
+        let mut data = vec![0u8; 2 * block_size - offset];
+        let n = self.read_direct(&mut data)?;
+        data.truncate(n);
+        self.position = position;
+        self.buffer = data;
+        self.buffer_start = position;
+        self.buffer_writes = self.file.borrow().writes();
+        self.buffer_inode = (inode.size, inode.modified_at);
+        Ok(())
+    }
+}
+
+fn lock_conflict() -> Error {
+    Error::new(
+        io::ErrorKind::WouldBlock,
+        "File is locked by another handle!",
+    )
+}
+
+impl Drop for VfsFile {
+    fn drop(&mut self) {
+        let _ = self.unlock();
+
+        let mut open = self.open.borrow_mut();
+        let Some(state) = open.get_mut(&self.inode_id) else {
+            return;
+        };
+        state.handles -= 1;
+        if state.handles > 0 {
+            return;
+        }
+        let unlinked = state.unlinked;
+        open.remove(&self.inode_id);
+        drop(open);
+        if unlinked {
+            let _ = self.release();
+        }
+    }
 }
 
 impl Write for VfsFile {
+    /// Writes at most up to the end of the current block. The size only grows
+    /// once the bytes are on disk, so if the image fills up mid-stream the
+    /// file holds exactly what earlier calls reported written, and the
+    /// failing call changes nothing.
+    ///
+    /// Several handles to one file may write to it. Each keeps its own
+    /// position, the size never shrinks because of a write, and where regions
+    /// overlap the last write wins.
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         if buf.is_empty() {
             return Ok(0);
         }
+        crate::check_mutable(&self.get_inode()?)?;
+        if self.position + buf.len() as u64 > self.sb.max_file_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!(
+                    "Writing {} bytes at offset {} would pass the maximum file size of {} bytes!",
+                    buf.len(),
+                    self.position,
+                    self.sb.max_file_size()
+                ),
+            ));
+        }
+        self.buffer.clear();
+        let block_idx = (self.position / self.sb.block_size as u64) as u32;
+        let offset = (self.position % self.sb.block_size as u64) as usize;
+
+        let end_block =
+            (self.position + buf.len() as u64).div_ceil(self.sb.block_size as u64) as u32;
+        if end_block > block_idx + 1 {
+            // The caller will keep writing, so grab the following blocks now
+            // while a contiguous run is still likely. If the whole buffer does
+            // not fit, fall back to allocating block by block.
+            if let Err(e) = self.allocate_block_range(block_idx, end_block)
+                && !matches!(
+                    e.kind(),
+                    io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+                )
+            {
+                return Err(e);
+            }
+        }
+        let physical_block_id = self.allocate_indirect_or_direct_blocks(block_idx)?;
+
+        // Only mark the inode once allocation succeeded, so a write refused for
+        // lack of space does not get the file reclaimed on the next open.
         let mut inode = self.get_inode()?;
         if inode.is_valid == 1 {
             inode.is_valid = 0;
             self.save_inode(&inode)?;
-            self.file.borrow_mut().sync_all()?;
+            self.sync_write()?;
         }
-        let block_idx = (self.position / BLOCK_SIZE as u64) as u32;
-        let offset = (self.position % BLOCK_SIZE as u64) as usize;
-        let physical_block_id = self.allocate_indirect_or_direct_blocks(block_idx)?;
-        let disk_pos = self.sb.data_blocks_start
-            + (physical_block_id as u64 * BLOCK_SIZE as u64)
-            + offset as u64;
+        let disk_pos = self.sb.block_offset(physical_block_id) + offset as u64;
 
-        let space_left_in_block = BLOCK_SIZE - offset;
+        let space_left_in_block = self.sb.block_size as usize - offset;
         let to_write = std::cmp::min(space_left_in_block, buf.len());
 
         {
             let mut file = self.file.borrow_mut();
             file.seek(SeekFrom::Start(disk_pos))?;
             file.write_all(&buf[..to_write])?;
-            file.sync_all()?;
         }
+        self.sync_write()?;
         self.position += to_write as u64;
-        let mut inode = self.get_inode()?;
-
-        if self.position > inode.size {
-            inode.size = self.position;
-        }
-
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| Error::other(e.to_string()))?
-            .as_secs();
-
-        inode.modified_at = now;
-        inode.is_valid = 1;
-
-        self.save_inode(&inode)?;
-        self.file.borrow_mut().sync_all()?;
+        self.grow_to(self.position)?;
+        self.sync_write()?;
 
         Ok(to_write)
     }
 
+    /// Writes the slices back to back as if they were one buffer, so a record
+    /// built from several fragments is laid out across blocks in one call.
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let data: Vec<u8> = bufs.iter().flat_map(|b| b.iter().copied()).collect();
+        let mut written = 0;
+        while written < data.len() {
+            match self.write(&data[written..]) {
+                Ok(0) => break,
+                Ok(n) => written += n,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        Ok(written)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
+        if self.sync_policy.get() == SyncPolicy::Never {
+            return Ok(());
+        }
         self.file.borrow_mut().sync_all()
     }
 }
 
 impl Read for VfsFile {
+    /// Small reads are served from a read-ahead buffer holding the rest of
+    /// the current block and the next one, so streaming a file in small
+    /// pieces does not go to the disk for each of them. Large reads bypass it.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if buf.is_empty() {
             return Ok(0);
         }
-
-        let inode = self.get_inode()?;
-        if self.position >= inode.size {
-            return Ok(0);
+        self.check_buffer()?;
+        if let Some(n) = self.read_buffered(buf) {
+            return Ok(n);
         }
+        if buf.len() < self.sb.block_size as usize {
+            self.fill_read_ahead()?;
+            return Ok(self.read_buffered(buf).unwrap_or(0));
+        }
+        self.read_direct(buf)
+    }
 
-        let block_idx = (self.position / BLOCK_SIZE as u64) as u32;
-        let offset = (self.position % BLOCK_SIZE as u64) as usize;
-
-        let block_id = match self.just_read(&inode, block_idx)? {
-            Some(id) => id,
-            None => {
-                let to_read = std::cmp::min(BLOCK_SIZE - offset, buf.len());
-                let available_in_file = (inode.size - self.position) as usize;
-                let final_read = std::cmp::min(to_read, available_in_file);
-                buf[..final_read].fill(0);
-                self.position += final_read as u64;
-                return Ok(final_read);
+    /// Fills the slices in order from one contiguous range of the file.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        let total = bufs.iter().map(|b| b.len()).sum();
+        let mut data = vec![0u8; total];
+        let mut filled = 0;
+        while filled < total {
+            match self.read(&mut data[filled..])? {
+                0 => break,
+                n => filled += n,
             }
-        };
-
-        let disk_pos =
-            self.sb.data_blocks_start + (block_id as u64 * BLOCK_SIZE as u64) + offset as u64;
-
-        let available_in_file = inode.size - self.position;
-        let available_in_block = BLOCK_SIZE as u64 - offset as u64;
-        let to_read = std::cmp::min(
-            std::cmp::min(available_in_block, available_in_file) as usize,
-            buf.len(),
-        );
-
-        let mut file = self.file.borrow_mut();
-        file.seek(SeekFrom::Start(disk_pos))?;
-        file.read_exact(&mut buf[..to_read])?;
+        }
 
-        self.position += to_read as u64;
-        Ok(to_read)
+        let mut copied = 0;
+        for buf in bufs.iter_mut() {
+            if copied == filled {
+                break;
+            }
+            let n = buf.len().min(filled - copied);
+            buf[..n].copy_from_slice(&data[copied..copied + n]);
+            copied += n;
+        }
+        Ok(filled)
     }
 }
 
 impl Seek for VfsFile {
+    /// Only `SeekFrom::End` reads the inode, and it is relative to the size
+    /// at the time of the call, including growth from other handles.
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        let inode = self.get_inode()?;
+        let new_position = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => self.position.checked_add_signed(n),
+            SeekFrom::End(n) => self.get_inode()?.size.checked_add_signed(n),
+        };
 
-        let new_position: i64 = match pos {
-            SeekFrom::Start(n) => n as i64,
-            SeekFrom::Current(n) => self.position as i64 + n,
-            SeekFrom::End(n) => inode.size as i64 + n,
+        let new_position = match new_position {
+            Some(position) if position <= self.sb.max_file_size() => position,
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Position is past the maximum file size!",
+                ));
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Negative or overflowing position in file!",
+                ));
+            }
         };
 
-        if new_position < 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Negative position in file!",
-            ));
+        self.buffer.clear();
+        self.position = new_position;
+        Ok(self.position)
+    }
+}
+
+impl BufRead for VfsFile {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check_buffer()?;
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.position < self.buffer_start || self.position >= buffer_end {
+            self.fill_read_ahead()?;
         }
+        let start = (self.position - self.buffer_start) as usize;
+        Ok(&self.buffer[start..])
+    }
 
-        self.position = new_position as u64;
-        Ok(self.position)
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
     }
 }
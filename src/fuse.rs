@@ -0,0 +1,249 @@
+use crate::Vfs;
+use crate::models::{Inode, InodeKind};
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+    ReplyEmpty, ReplyEntry, ReplyWrite, Request,
+};
+use std::ffi::OsStr;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::time::{Duration, UNIX_EPOCH};
+
+const TTL: Duration = Duration::from_secs(1);
+
+/// FUSE reserves inode number 1 for the root, while the image uses 0, so
+/// every VFS inode id is exposed shifted by one.
+fn to_ino(inode_id: u32) -> u64 {
+    inode_id as u64 + 1
+}
+
+fn to_inode_id(ino: u64) -> u32 {
+    (ino - 1) as u32
+}
+
+fn errno(e: &io::Error) -> libc::c_int {
+    match e.kind() {
+        io::ErrorKind::NotFound => libc::ENOENT,
+        io::ErrorKind::AlreadyExists => libc::EEXIST,
+        io::ErrorKind::NotADirectory => libc::ENOTDIR,
+        io::ErrorKind::IsADirectory => libc::EISDIR,
+        io::ErrorKind::DirectoryNotEmpty => libc::ENOTEMPTY,
+        io::ErrorKind::StorageFull => libc::ENOSPC,
+        io::ErrorKind::FileTooLarge => libc::EFBIG,
+        io::ErrorKind::PermissionDenied => libc::EPERM,
+        io::ErrorKind::InvalidInput => libc::EINVAL,
+        _ => libc::EIO,
+    }
+}
+
+struct FuseFs<'a> {
+    vfs: &'a mut Vfs,
+    uid: u32,
+    gid: u32,
+}
+
+impl FuseFs<'_> {
+    fn attr(&mut self, inode_id: u32) -> io::Result<FileAttr> {
+        if !self.vfs.is_inode_allocated(inode_id)? {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        let inode = self.vfs.get_inode(inode_id)?;
+        Ok(self.to_attr(inode_id, &inode))
+    }
+
+    fn to_attr(&self, inode_id: u32, inode: &Inode) -> FileAttr {
+        let (kind, perm) = match inode.inode_type {
+            InodeKind::File => (FileType::RegularFile, 0o644),
+            InodeKind::Dir => (FileType::Directory, 0o755),
+            InodeKind::Symlink => (FileType::Symlink, 0o777),
+        };
+        let mtime = UNIX_EPOCH + Duration::from_secs(inode.modified_at);
+        FileAttr {
+            ino: to_ino(inode_id),
+            size: inode.size,
+            blocks: inode.size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: UNIX_EPOCH + Duration::from_secs(inode.created_at),
+            kind,
+            perm,
+            nlink: 1,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: self.vfs.sb.block_size,
+            flags: 0,
+        }
+    }
+}
+
+impl Filesystem for FuseFs<'_> {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = name.to_string_lossy();
+        let result = self
+            .vfs
+            .find_in_dir(to_inode_id(parent), &name)
+            .and_then(|id| self.attr(id));
+        match result {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.attr(to_inode_id(ino)) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let mut buffer = vec![0u8; size as usize];
+        let result = self.vfs.handle(to_inode_id(ino)).and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            let mut filled = 0;
+            while filled < buffer.len() {
+                let n = file.read(&mut buffer[filled..])?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            Ok(filled)
+        });
+        match result {
+            Ok(n) => reply.data(&buffer[..n]),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let result = self.vfs.handle(to_inode_id(ino)).and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.write_all(data)
+        });
+        match result {
+            Ok(()) => reply.written(data.len() as u32),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let entries = match self.vfs.dir_entries(to_inode_id(ino)) {
+            Ok(entries) => entries,
+            Err(e) => return reply.error(errno(&e)),
+        };
+
+        for (i, entry) in entries.iter().enumerate().skip(offset as usize) {
+            let kind = match self.vfs.get_inode(entry.inode_id) {
+                Ok(inode) if inode.inode_type == InodeKind::Dir => FileType::Directory,
+                Ok(inode) if inode.inode_type == InodeKind::Symlink => FileType::Symlink,
+                _ => FileType::RegularFile,
+            };
+            // Names are raw bytes on disk, as they are to the kernel.
+            let name = OsStr::from_bytes(entry.name_bytes());
+            if reply.add(to_ino(entry.inode_id), (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = name.to_string_lossy();
+        let result = self
+            .vfs
+            .create_dir_in(to_inode_id(parent), &name)
+            .and_then(|id| self.attr(id));
+        match result {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let name = name.to_string_lossy();
+        let result = self
+            .vfs
+            .create_file_in(to_inode_id(parent), &name)
+            .and_then(|file| self.attr(file.inode_id));
+        match result {
+            Ok(attr) => reply.created(&TTL, &attr, 0, 0, 0),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = name.to_string_lossy();
+        match self.vfs.remove_entry(to_inode_id(parent), &name) {
+            Ok(_) => reply.ok(),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+}
+
+impl Vfs {
+    /// Mounts the image at `mountpoint` and serves it until the directory is
+    /// unmounted (e.g. with `umount` or `fusermount -u`). Files show up owned
+    /// by the owner of the mountpoint.
+    pub fn mount(&mut self, mountpoint: &str) -> io::Result<()> {
+        let owner = std::fs::metadata(mountpoint)?;
+        let fs = FuseFs {
+            vfs: self,
+            uid: owner.uid(),
+            gid: owner.gid(),
+        };
+        let options = [
+            MountOption::FSName("vfs".to_string()),
+            MountOption::DefaultPermissions,
+        ];
+        fuser::mount2(fs, mountpoint, &options)
+    }
+}
@@ -1,84 +1,219 @@
 use chrono::{DateTime, Utc};
-use std::cell::RefCell;
-use std::fs::{File, OpenOptions};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions, TryLockError};
 use std::io::{self, Error, Read, Seek, SeekFrom, Write};
 use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub mod models;
-use models::{BLOCK_SIZE, DirEntry, INODE_SIZE, Inode, KEY, MAX_NAME_LEN, SuperBlock};
+use models::{
+    BLOCK_SIZE, DirEntry, FORMAT_VERSION, INODE_SIZE, INODES_PER_BLOCKS, Inode, InodeKind, KEY,
+    MAX_NAME_LEN, SUPERBLOCK_SIZE, SuperBlock,
+};
+pub use models::{FragStats, FsStats, HashAlgo, LongEntry, Metadata, RemoveStats, SyncPolicy};
+
+mod disk;
+use disk::Disk;
 
 pub mod file;
+use file::{LockTable, OpenTable};
+
+mod badblocks;
+mod quota;
 pub use file::VfsFile;
 
+#[cfg(feature = "async")]
+pub mod async_vfs;
+#[cfg(feature = "async")]
+pub use async_vfs::AsyncVfs;
+
+#[cfg(feature = "fuse")]
+mod fuse;
+
+mod xattr;
+
+mod entries;
+pub use entries::EntryIter;
+
 use crate::models::DIR_SIZE;
 
+const MIN_DATA_BLOCKS: u32 = 4;
+/// Deepest path, in components below the root, a `Vfs` accepts unless
+/// changed with `with_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+/// Most symbolic links followed while resolving one path, as on Linux.
+const MAX_SYMLINK_HOPS: u32 = 40;
+
+/// A mounted image. The backing file is locked for the lifetime of the `Vfs`
+/// and its handles so that two processes using this crate cannot mutate the
+/// same image at once; the OS lock is advisory and does not stop other tools.
 pub struct Vfs {
-    file: Rc<RefCell<File>>,
+    file: Rc<RefCell<Disk>>,
     sb: SuperBlock,
+    locks: LockTable,
+    open: OpenTable,
+    case_insensitive: bool,
+    #[cfg(feature = "unicode")]
+    nfc_names: bool,
+    sync_policy: Rc<Cell<SyncPolicy>>,
+    max_depth: usize,
 }
 
 impl Vfs {
+    /// Formats a new image at `path`, overwriting any file already there.
     pub fn create(path: &str, total_size: u64) -> io::Result<Self> {
-        let mut file = OpenOptions::new()
+        Self::create_with_block_size(path, total_size, BLOCK_SIZE as u32)
+    }
+
+    /// Like `create`, but fails with `AlreadyExists` instead of overwriting
+    /// an existing file at `path`.
+    pub fn create_new(path: &str, total_size: u64) -> io::Result<Self> {
+        let sb = Self::layout(total_size, BLOCK_SIZE as u32)?;
+        let file = OpenOptions::new()
             .read(true)
             .write(true)
-            .create(true)
-            .truncate(true)
+            .create_new(true)
             .open(path)?;
-        file.set_len(total_size)?;
+        lock_image(&file, false)?;
+        Self::format(Disk::new(file), sb)
+    }
 
-        let total_blocks = (total_size / BLOCK_SIZE as u64) as u32;
-        let max_inodes = total_blocks / 4;
+    /// Like `create`, but keeps `reserve_percent` of the data blocks back
+    /// from normal allocations, so `allocate_data_block_reserved` still works
+    /// once the image is otherwise full. At most 50 percent can be reserved.
+    pub fn create_with_reserved(
+        path: &str,
+        total_size: u64,
+        reserve_percent: u32,
+    ) -> io::Result<Self> {
+        if reserve_percent > 50 {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Reserve of {}% is too large, at most 50% can be reserved!",
+                    reserve_percent
+                ),
+            ));
+        }
+        let mut vfs = Self::create(path, total_size)?;
+        let usable = vfs.sb.data_block_count() - vfs.sb.journal_blocks;
+        vfs.sb.reserved_blocks = (usable as u64 * reserve_percent as u64 / 100) as u32;
+        vfs.sb.checksum = vfs.sb.compute_checksum();
+        let mut file = vfs.file.borrow_mut();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&vfs.sb.to_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        Ok(vfs)
+    }
 
-        let sb_size = BLOCK_SIZE as u64;
-        let inode_bitmap_size = ((max_inodes as f32 / 8.0).ceil() as u64).max(1);
-        let data_bitmap_size = ((total_blocks as f32 / 8.0).ceil() as u64).max(1);
-        let inode_table_size = max_inodes as u64 * std::mem::size_of::<Inode>() as u64;
+    /// Like `create`, but with data blocks of `block_size` bytes, which must be
+    /// a power of two between 512 and 65536.
+    pub fn create_with_block_size(
+        path: &str,
+        total_size: u64,
+        block_size: u32,
+    ) -> io::Result<Self> {
+        if !block_size.is_power_of_two() || !(512..=65536).contains(&block_size) {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Block size {} must be a power of two between 512 and 65536!",
+                    block_size
+                ),
+            ));
+        }
+        let sb = Self::layout(total_size, block_size)?;
+        Self::create_from_layout(path, sb)
+    }
 
-        let inode_bitmap_st = sb_size;
-        let data_bitmap_st = inode_bitmap_st + inode_bitmap_size;
-        let inode_table_st = data_bitmap_st + data_bitmap_size;
+    /// Like `create`, but stamps `magic` into the superblock instead of the
+    /// default `KEY`, so the image only opens with `open_with_magic`. A magic
+    /// of 0 is rejected, since a zeroed file would carry it.
+    pub fn create_with_magic(path: &str, total_size: u64, magic: u64) -> io::Result<Self> {
+        if magic == 0 {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                "Magic must not be 0!",
+            ));
+        }
+        let mut sb = Self::layout(total_size, BLOCK_SIZE as u32)?;
+        sb.key = magic;
+        sb.checksum = sb.compute_checksum();
+        Self::create_from_layout(path, sb)
+    }
 
-        let data_blocks_st = ((inode_table_st + inode_table_size + BLOCK_SIZE as u64 - 1)
-            .div_ceil(BLOCK_SIZE as u64))
-            * BLOCK_SIZE as u64;
+    fn create_from_layout(path: &str, sb: SuperBlock) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        lock_image(&file, false)?;
+        file.set_len(0)?;
+        Self::format(Disk::new(file), sb)
+    }
 
-        let sb = SuperBlock {
-            key: KEY,
-            block_size: BLOCK_SIZE as u32,
-            total_blocks,
-            inode_bitmap_start: inode_bitmap_st,
-            data_bitmap_start: data_bitmap_st,
-            inode_table_start: inode_table_st,
-            data_blocks_start: data_blocks_st,
-        };
+    /// Like `create`, but formats an image inside a file opened elsewhere,
+    /// starting at the file's current position. The file grows if needed;
+    /// bytes before the start are left alone.
+    pub fn create_in_handle(mut file: File, total_size: u64) -> io::Result<Self> {
+        let sb = Self::layout(total_size, BLOCK_SIZE as u32)?;
+        let base = file.stream_position()?;
+        lock_image(&file, false)?;
+        Self::format(Disk::with_base(file, base), sb)
+    }
+
+    /// Writes an empty file system with layout `sb` to `file`.
+    fn format(mut file: Disk, sb: SuperBlock) -> io::Result<Self> {
+        let inode_bitmap_st = sb.inode_bitmap_start;
+        let data_bitmap_st = sb.data_bitmap_start;
+        let inode_table_st = sb.inode_table_start;
+        let data_blocks_st = sb.data_blocks_start;
+
+        let image_len = sb.total_blocks as u64 * sb.block_size as u64;
+        if file.len()? < image_len {
+            file.set_len(image_len)?;
+        }
+
+        // The file may hold old bytes, so clear the whole metadata area,
+        // superblock region and quota table included.
+        file.zero_region(0, data_blocks_st)?;
 
         file.seek(SeekFrom::Start(0))?;
         file.write_all(&sb.to_bytes())?;
 
-        let zero_block = vec![0u8; BLOCK_SIZE];
-        let metadata_area_size = data_blocks_st - inode_bitmap_st;
-        let mut written = 0;
-        file.seek(SeekFrom::Start(inode_bitmap_st))?;
-        while written < metadata_area_size {
-            let chunk = std::cmp::min(BLOCK_SIZE as u64, metadata_area_size - written);
-            file.write_all(&zero_block[..chunk as usize])?;
-            written += chunk;
+        // Block 0 doubles as the "no block" pointer, and the bitmap is sized
+        // for `total_blocks` even though the metadata area takes some of them,
+        // so mark both as used to keep the allocators inside the image. The
+        // journal, if any, sits right before the end and is never allocated.
+        let mut data_bitmap = vec![0u8; (inode_table_st - data_bitmap_st) as usize];
+        data_bitmap[0] |= 1;
+        let journal_first = sb.data_block_count() - sb.journal_blocks;
+        for id in journal_first..(data_bitmap.len() * 8) as u32 {
+            data_bitmap[(id / 8) as usize] |= 1 << (id % 8);
         }
+        file.seek(SeekFrom::Start(data_bitmap_st))?;
+        file.write_all(&data_bitmap)?;
 
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
         let root_inode = Inode {
-            inode_type: 1,
+            inode_type: InodeKind::Dir,
             is_valid: 1,
             size: 0,
             created_at: now,
             modified_at: now,
             direct_blocks: [0; 10],
             indirect_blocks: 0,
+            xattr_block: 0,
+            generation: 0,
+            flags: 0,
         };
 
         file.seek(SeekFrom::Start(inode_table_st))?;
@@ -91,6 +226,13 @@ impl Vfs {
         let mut vfs = Vfs {
             file: Rc::new(RefCell::new(file)),
             sb,
+            locks: Rc::new(RefCell::new(HashMap::new())),
+            open: Rc::new(RefCell::new(HashMap::new())),
+            case_insensitive: false,
+            #[cfg(feature = "unicode")]
+            nfc_names: false,
+            sync_policy: Rc::new(Cell::new(SyncPolicy::default())),
+            max_depth: DEFAULT_MAX_DEPTH,
         };
 
         vfs.add_entry_to_parent(0, ".", 0)?;
@@ -99,54 +241,462 @@ impl Vfs {
         Ok(vfs)
     }
 
+    /// Computes the on-disk layout for an image of `total_size` bytes, rounded
+    /// down to whole blocks, and rejects sizes too small to hold the metadata
+    /// plus a few data blocks for the root directory.
+    fn layout(total_size: u64, block_size: u32) -> io::Result<SuperBlock> {
+        let usable = |sb: &SuperBlock| sb.data_block_count() >= MIN_DATA_BLOCKS + sb.journal_blocks;
+        let sb = Self::layout_for_blocks((total_size / block_size as u64) as u32, block_size);
+        if usable(&sb) {
+            return Ok(sb);
+        }
+
+        let min_blocks = (1..)
+            .find(|&blocks| usable(&Self::layout_for_blocks(blocks, block_size)))
+            .unwrap();
+        Err(Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Image size {} is too small, at least {} bytes are needed!",
+                total_size,
+                min_blocks as u64 * block_size as u64
+            ),
+        ))
+    }
+
+    /// The superblock region keeps its fixed 4096 bytes whatever the block
+    /// size, so the quota table inside it stays at the same offset.
+    fn layout_for_blocks(total_blocks: u32, block_size: u32) -> SuperBlock {
+        let max_inodes = total_blocks / INODES_PER_BLOCKS;
+
+        let sb_size = BLOCK_SIZE as u64;
+        let inode_bitmap_size = (max_inodes as u64).div_ceil(8).max(1);
+        let data_bitmap_size = (total_blocks as u64).div_ceil(8).max(1);
+        let inode_table_size = max_inodes as u64 * INODE_SIZE as u64;
+
+        let inode_bitmap_st = sb_size;
+        let data_bitmap_st = inode_bitmap_st + inode_bitmap_size;
+        let inode_table_st = data_bitmap_st + data_bitmap_size;
+        let data_blocks_st =
+            (inode_table_st + inode_table_size).div_ceil(block_size as u64) * block_size as u64;
+
+        let mut sb = SuperBlock {
+            key: KEY,
+            block_size,
+            total_blocks,
+            inode_bitmap_start: inode_bitmap_st,
+            data_bitmap_start: data_bitmap_st,
+            inode_table_start: inode_table_st,
+            data_blocks_start: data_blocks_st,
+            version: FORMAT_VERSION,
+            journal_start: 0,
+            journal_blocks: 0,
+            reserved_blocks: 0,
+            checksum: 0,
+        };
+
+        // The journal takes the last data blocks of the image.
+        if cfg!(feature = "journal") {
+            let first = sb.data_block_count().saturating_sub(models::JOURNAL_BLOCKS);
+            sb.journal_start = sb.block_offset(first);
+            sb.journal_blocks = models::JOURNAL_BLOCKS;
+        }
+        sb.checksum = sb.compute_checksum();
+        sb
+    }
+
+    /// Opens an image written with the default `KEY`.
     pub fn open(name: &str) -> io::Result<Self> {
-        let mut file = OpenOptions::new().read(true).write(true).open(name)?;
-        let mut buffer = vec![0u8; std::mem::size_of::<SuperBlock>()];
+        Self::open_with_magic(name, KEY)
+    }
+
+    /// Like `open`, but for an image made with `create_with_magic`. Images
+    /// carrying any other magic, the default one included, are rejected.
+    pub fn open_with_magic(name: &str, magic: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(name)?;
+        lock_image(&file, false)?;
+        Self::open_disk(Disk::new(file), magic)
+    }
+
+    /// Like `open`, but on a file opened elsewhere, such as a memfd or a
+    /// container holding the image. The image starts at the file's current
+    /// position; the file must be readable and writable.
+    pub fn open_file_handle(mut file: File) -> io::Result<Self> {
+        let base = file.stream_position()?;
+        lock_image(&file, false)?;
+        Self::open_disk(Disk::with_base(file, base), KEY)
+    }
+
+    fn open_disk(mut disk: Disk, magic: u64) -> io::Result<Self> {
+        let sb = Self::read_superblock(&mut disk, magic)?;
+        Self::check_version(sb.version)?;
+
+        let mut vfs = Vfs {
+            file: Rc::new(RefCell::new(disk)),
+            sb,
+            locks: Rc::new(RefCell::new(HashMap::new())),
+            open: Rc::new(RefCell::new(HashMap::new())),
+            case_insensitive: false,
+            #[cfg(feature = "unicode")]
+            nfc_names: false,
+            sync_policy: Rc::new(Cell::new(SyncPolicy::default())),
+            max_depth: DEFAULT_MAX_DEPTH,
+        };
+
+        // Snapshots first, so blocks the replay overwrites are copied aside.
+        vfs.file.borrow_mut().load_snapshots(&vfs.sb)?;
+        #[cfg(feature = "journal")]
+        if vfs.sb.journal_blocks > 0 {
+            vfs.file.borrow_mut().replay_journal(
+                vfs.sb.journal_start,
+                vfs.sb.journal_blocks,
+                vfs.sb.block_size,
+            )?;
+        }
+        vfs.recover()?;
+
+        Ok(vfs)
+    }
+
+    /// Opens an image for inspection without locking it or running recovery,
+    /// so the on-disk state is seen exactly as it was left. Every operation
+    /// that would modify the image fails with `PermissionDenied`.
+    pub fn open_readonly(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        let mut disk = Disk::unlocked(file);
+        let sb = Self::read_superblock(&mut disk, KEY)?;
+        Self::check_version(sb.version)?;
+
+        Ok(Vfs {
+            file: Rc::new(RefCell::new(disk)),
+            sb,
+            locks: Rc::new(RefCell::new(HashMap::new())),
+            open: Rc::new(RefCell::new(HashMap::new())),
+            case_insensitive: false,
+            #[cfg(feature = "unicode")]
+            nfc_names: false,
+            sync_policy: Rc::new(Cell::new(SyncPolicy::default())),
+            max_depth: DEFAULT_MAX_DEPTH,
+        })
+    }
+
+    /// Opens an image read-only under a shared lock, so several readers can
+    /// use it at once while writers are kept out. Recovery is skipped since it
+    /// would need to modify the image.
+    pub fn open_shared(name: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(name)?;
+        lock_image(&file, true)?;
+        let mut disk = Disk::read_only(file);
+        let sb = Self::read_superblock(&mut disk, KEY)?;
+        Self::check_version(sb.version)?;
+
+        Ok(Vfs {
+            file: Rc::new(RefCell::new(disk)),
+            sb,
+            locks: Rc::new(RefCell::new(HashMap::new())),
+            open: Rc::new(RefCell::new(HashMap::new())),
+            case_insensitive: false,
+            #[cfg(feature = "unicode")]
+            nfc_names: false,
+            sync_policy: Rc::new(Cell::new(SyncPolicy::default())),
+            max_depth: DEFAULT_MAX_DEPTH,
+        })
+    }
+
+    /// Reads the format version of an image without mounting it. Images written
+    /// before versioning was introduced report `0`.
+    pub fn image_version(path: &str) -> io::Result<u32> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        Ok(Self::read_superblock(&mut Disk::read_only(file), KEY)?.version)
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.sb.block_size
+    }
+
+    /// Largest size in bytes a file can reach; writes past it fail with
+    /// `FileTooLarge`.
+    pub fn max_file_size(&self) -> u64 {
+        self.sb.max_file_size()
+    }
+
+    pub fn total_blocks(&self) -> u32 {
+        self.sb.total_blocks
+    }
+
+    pub fn data_blocks_start(&self) -> u64 {
+        self.sb.data_blocks_start
+    }
+
+    pub fn inode_table_start(&self) -> u64 {
+        self.sb.inode_table_start
+    }
+
+    /// A copy of the superblock describing the image layout.
+    pub fn superblock(&self) -> SuperBlock {
+        self.sb
+    }
+
+    /// Switches the backing store to a shared memory map. Reads and writes
+    /// then become memory copies, and the map is flushed wherever the file
+    /// would otherwise be synced. Images from `open_readonly` are not locked
+    /// and cannot be mapped.
+    #[cfg(feature = "mmap")]
+    pub fn enable_mmap(&mut self) -> io::Result<()> {
+        self.file.borrow_mut().enable_mmap()
+    }
+
+    /// Number of read, write, seek and sync calls issued to the OS for the
+    /// backing file so far.
+    pub fn io_calls(&self) -> u64 {
+        self.file.borrow().io_calls()
+    }
+
+    fn read_superblock(file: &mut Disk, magic: u64) -> io::Result<SuperBlock> {
+        // An empty or cut-off file would otherwise fail `read_exact` with a
+        // bare `UnexpectedEof`.
+        let len = file.len()?;
+        if len < SUPERBLOCK_SIZE as u64 {
+            return Err(Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Not a VFS image: file too small ({} bytes)!", len),
+            ));
+        }
+        let mut buffer = [0u8; SUPERBLOCK_SIZE];
         file.seek(SeekFrom::Start(0))?;
         file.read_exact(&mut buffer)?;
 
         let sb = SuperBlock::from_bytes(&buffer);
-        if sb.key != KEY {
+        if sb.key != magic {
             return Err(Error::new(
                 io::ErrorKind::InvalidData,
                 "Not supported by library!",
             ));
         }
+        // Newer versions may lay the superblock out differently; leave those
+        // to `check_version`.
+        if sb.checksum != 0 && sb.version <= FORMAT_VERSION {
+            let computed = sb.compute_checksum();
+            if sb.checksum != computed {
+                return Err(Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Superblock checksum mismatch: stored {:#010x}, computed {:#010x}!",
+                        sb.checksum, computed
+                    ),
+                ));
+            }
+        }
+        if !sb.block_size.is_power_of_two() || !(512..=65536).contains(&sb.block_size) {
+            return Err(Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Superblock has an invalid block size of {}!", sb.block_size),
+            ));
+        }
+        // The blocks cover the whole image, metadata included.
+        let expected = sb.total_blocks as u64 * sb.block_size as u64;
+        let actual = file.len()?;
+        if actual < expected || sb.data_blocks_start > expected {
+            return Err(Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Image is truncated or corrupt: it is {} bytes but its superblock describes {} bytes!",
+                    actual, expected
+                ),
+            ));
+        }
+        Ok(sb)
+    }
 
-        let mut vfs = Vfs {
-            file: Rc::new(RefCell::new(file)),
-            sb,
+    fn check_version(version: u32) -> io::Result<()> {
+        // Version 0 images predate the version field and share the v1 layout.
+        if version == 0 || version == FORMAT_VERSION {
+            return Ok(());
+        }
+        let reason = if version > FORMAT_VERSION {
+            "was written by a newer release"
+        } else {
+            "needs to be migrated"
         };
+        Err(Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "Image format version {} {}; this library supports version {}!",
+                version, reason, FORMAT_VERSION
+            ),
+        ))
+    }
+
+    /// Shrinks the image to end shortly after its last allocated data block,
+    /// returning the space to the host file system. Allocated blocks and
+    /// inodes are never dropped; the journal, if any, moves down with the end
+    /// of the image, and the reserved blocks stay free on top of a few for
+    /// normal writes. Does nothing if there is nothing to cut. Fails with
+    /// `ResourceBusy` while snapshots exist, since rolling back to one needs
+    /// the blocks and the size the image had when it was taken.
+    pub fn trim(&mut self) -> io::Result<()> {
+        if self.file.borrow().has_snapshots() {
+            return Err(Error::new(
+                io::ErrorKind::ResourceBusy,
+                "Cannot trim an image that has snapshots!",
+            ));
+        }
+        let bs = self.sb.block_size as u64;
+        let start = self.sb.data_bitmap_start;
+        let mut bitmap = vec![0u8; (self.sb.inode_table_start - start) as usize];
+        {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut bitmap)?;
+        }
+        let journal_first = self.sb.data_block_count() - self.sb.journal_blocks;
+        let last_used = (0..journal_first)
+            .rev()
+            .find(|&id| bitmap[(id / 8) as usize] & (1 << (id % 8)) != 0)
+            .unwrap_or(0);
+        let last_inode = (0..self.sb.inode_count())
+            .rev()
+            .find_map(|id| match self.is_inode_allocated(id) {
+                Ok(true) => Some(Ok(id)),
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .transpose()?
+            .unwrap_or(0);
+
+        // Keep a few free blocks and inodes so the image stays writable, on
+        // top of the reserve, which normal writes may not touch.
+        let data_blocks =
+            last_used + 1 + MIN_DATA_BLOCKS + self.sb.reserved_blocks + self.sb.journal_blocks;
+        let metadata_blocks = (self.sb.data_blocks_start / bs) as u32;
+        let total_blocks = (metadata_blocks + data_blocks)
+            .max((last_inode + 1 + MIN_DATA_BLOCKS) * INODES_PER_BLOCKS);
+        if total_blocks >= self.sb.total_blocks {
+            return Ok(());
+        }
 
-        vfs.recover_corrupted_inodes()?;
+        let mut sb = self.sb;
+        sb.total_blocks = total_blocks;
+        let new_journal_first = sb.data_block_count() - sb.journal_blocks;
+        if sb.journal_blocks > 0 {
+            sb.journal_start = sb.block_offset(new_journal_first);
+        }
+        sb.checksum = sb.compute_checksum();
+        // Like on `create`: the journal and every bit past the new end stay
+        // marked used so the allocator never hands them out.
+        for id in new_journal_first..(bitmap.len() * 8) as u32 {
+            bitmap[(id / 8) as usize] |= 1 << (id % 8);
+        }
 
-        Ok(vfs)
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(start))?;
+        file.write_all(&bitmap)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&sb.to_bytes())?;
+        file.sync_all()?;
+        file.set_len(total_blocks as u64 * bs)?;
+        file.sync_all()?;
+        drop(file);
+        self.sb = sb;
+        Ok(())
     }
 
-    fn recover_corrupted_inodes(&mut self) -> io::Result<()> {
-        let max_inodes = (self.sb.data_bitmap_start - self.sb.inode_bitmap_start) * 8;
+    /// Frees inodes left behind by interrupted operations: ones marked invalid
+    /// mid-write and ones no directory links to. Runs automatically on `open`;
+    /// returns how many inodes were freed.
+    pub fn recover(&mut self) -> io::Result<usize> {
+        let max_inodes = self.sb.inode_count();
         let mut recovered_count = 0;
 
-        for inode_id in 1..max_inodes as u32 {
+        for inode_id in 1..max_inodes {
             if !self.is_inode_allocated(inode_id)? {
                 continue;
             }
 
-            let inode = self.get_inode(inode_id)?;
-            if inode.is_valid == 0 {
+            // Soft-removed files are invalid on purpose.
+            let Some(inode) = self.known_inode(inode_id)? else {
+                continue;
+            };
+            if inode.is_valid == 0 && !inode.is_deleted() {
                 self.deallocate_inode(inode_id)?;
                 recovered_count += 1;
             }
         }
 
-        if recovered_count > 0 {
-            println!("{} corrupted inodes!", recovered_count);
+        Ok(recovered_count + self.reclaim_orphaned_inodes(max_inodes)?)
+    }
+
+    /// Frees inodes that are allocated and valid but not referenced by any
+    /// directory, e.g. when a crash hit between allocating the inode and
+    /// linking it into its parent.
+    fn reclaim_orphaned_inodes(&mut self, max_inodes: u32) -> io::Result<usize> {
+        let reachable = self.reachable_inodes()?;
+        let mut reclaimed_count = 0;
+
+        for inode_id in 1..max_inodes {
+            if reachable.contains(&inode_id)
+                || self.open.borrow().contains_key(&inode_id)
+                || !self.is_inode_allocated(inode_id)?
+            {
+                continue;
+            }
+
+            let Some(inode) = self.known_inode(inode_id)? else {
+                continue;
+            };
+            if inode.is_deleted() {
+                continue;
+            }
+            self.free_file_blocks(&inode)?;
+            self.deallocate_inode(inode_id)?;
+            reclaimed_count += 1;
         }
 
-        Ok(())
+        Ok(reclaimed_count)
+    }
+
+    /// Inodes linked from the root directory, directly or through
+    /// subdirectories. A directory that cannot be read fails the walk rather
+    /// than having everything below it taken for orphans.
+    fn reachable_inodes(&mut self) -> io::Result<HashSet<u32>> {
+        let mut reachable = HashSet::from([0]);
+        let mut pending = vec![0];
+
+        while let Some(dir_id) = pending.pop() {
+            if dir_id >= self.sb.inode_count()
+                || self
+                    .known_inode(dir_id)?
+                    .is_none_or(|inode| inode.inode_type != InodeKind::Dir)
+            {
+                continue;
+            }
+            for entry in self.dir_entries(dir_id)? {
+                if reachable.insert(entry.inode_id) {
+                    pending.push(entry.inode_id);
+                }
+            }
+        }
+
+        Ok(reachable)
+    }
+
+    /// Like `get_inode`, but `None` for an inode of unknown type. Recovery
+    /// leaves those alone rather than failing the open; `verify_file` reports
+    /// them.
+    fn known_inode(&mut self, id: u32) -> io::Result<Option<Inode>> {
+        match self.get_inode(id) {
+            Ok(inode) => Ok(Some(inode)),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
-    fn is_inode_allocated(&mut self, inode_id: u32) -> io::Result<bool> {
+    /// Ids past the inode table are never allocated; their bit would lie in
+    /// the data bitmap.
+    pub(crate) fn is_inode_allocated(&mut self, inode_id: u32) -> io::Result<bool> {
+        if inode_id >= self.sb.inode_count() {
+            return Ok(false);
+        }
         let byte_offset = inode_id / 8;
         let bit_offset = inode_id % 8;
 
@@ -180,7 +730,8 @@ impl Vfs {
         Ok(())
     }
 
-    fn allocate_bit(&mut self, start: u64, end: u64) -> io::Result<u32> {
+    /// Claims the first clear bit below `limit` in the bitmap at `start..end`.
+    fn allocate_bit(&mut self, start: u64, end: u64, limit: u32) -> io::Result<Option<u32>> {
         let total_bytes = end - start;
         let mut buffer = [0u8; 512];
         let mut file = self.file.borrow_mut();
@@ -198,39 +749,247 @@ impl Vfs {
             for (byte_idx, byte) in buffer[..to_read as usize].iter_mut().enumerate() {
                 if *byte != 0xFF {
                     for bit_idx in 0..8 {
+                        let id =
+                            (chunk_idx as u32 * 512 * 8) + (byte_idx as u32 * 8) + bit_idx as u32;
+                        if id >= limit {
+                            return Ok(None);
+                        }
                         if (*byte & (1 << bit_idx)) == 0 {
                             *byte |= 1 << bit_idx;
                             file.seek(SeekFrom::Start(current_offset + byte_idx as u64))?;
                             file.write_all(&[*byte])?;
-                            return Ok((chunk_idx as u32 * 512 * 8)
-                                + (byte_idx as u32 * 8)
-                                + bit_idx as u32);
+                            return Ok(Some(id));
                         }
                     }
                 }
             }
         }
-        Err(Error::other("No more free inodes!"))
+        Ok(None)
+    }
+
+    /// Generation for a newly allocated inode: one past whatever the slot
+    /// held last.
+    fn next_generation(&mut self, inode_id: u32) -> io::Result<u32> {
+        Ok(self.get_inode(inode_id)?.generation.wrapping_add(1))
     }
 
+    /// Fails with a `StorageFull` error carrying [`NoInodes`] once every slot
+    /// of the inode table is taken, even if data blocks are still free.
     fn allocate_inode(&mut self) -> io::Result<u32> {
-        self.allocate_bit(self.sb.inode_bitmap_start, self.sb.data_bitmap_start)
+        self.allocate_bit(
+            self.sb.inode_bitmap_start,
+            self.sb.data_bitmap_start,
+            self.sb.inode_count(),
+        )?
+        .ok_or_else(|| Error::new(io::ErrorKind::StorageFull, NoInodes))
+    }
+
+    /// Allocates one data block and clears it, so stale bytes from a removed
+    /// file never show up as directory entries or file contents. Blocks taken
+    /// directly are not linked to any inode; use `read_block`, `write_block`
+    /// and `free_data_block` on them.
+    pub fn allocate_data_block(&mut self) -> io::Result<u32> {
+        if !self.outside_reserve(1)? {
+            return Err(Error::new(
+                io::ErrorKind::StorageFull,
+                "No more free blocks outside the reserved space!",
+            ));
+        }
+        self.allocate_data_block_reserved()
+    }
+
+    /// Allocates one cleared data block, dipping into the reserved space if
+    /// needed. The block is marked used and not linked to any inode.
+    pub fn allocate_data_block_reserved(&mut self) -> io::Result<u32> {
+        let bad = badblocks::read_table(&mut self.file.borrow_mut())?;
+        // A bad block freed by its file is claimed again here and skipped.
+        let id = loop {
+            let id = self
+                .allocate_bit(
+                    self.sb.data_bitmap_start,
+                    self.sb.inode_table_start,
+                    self.sb.data_block_count(),
+                )?
+                .ok_or_else(|| Error::new(io::ErrorKind::StorageFull, "No more free blocks!"))?;
+            if !bad.contains(&id) {
+                break id;
+            }
+        };
+        self.file
+            .borrow_mut()
+            .zero_region(self.sb.block_offset(id), self.sb.block_size as u64)?;
+        Ok(id)
+    }
+
+    /// Marks a block taken with `allocate_data_block` free again. Fails with
+    /// `InvalidInput` if it is out of range or not allocated.
+    pub fn free_data_block(&mut self, block_id: u32) -> io::Result<()> {
+        self.check_block_id(block_id)?;
+        let pos = self.sb.data_bitmap_start + (block_id / 8) as u64;
+        let mut byte = [0u8; 1];
+        {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(pos))?;
+            file.read_exact(&mut byte)?;
+        }
+        if byte[0] & (1 << (block_id % 8)) == 0 {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Block {} is not allocated!", block_id),
+            ));
+        }
+        self.free_bit(self.sb.data_bitmap_start, block_id)
+    }
+
+    /// Reads the start of data block `block_id` into `buf`, which may be at
+    /// most one block long. An I/O error records the block as bad and comes
+    /// back carrying [`BadBlock`].
+    pub fn read_block(&mut self, block_id: u32, buf: &mut [u8]) -> io::Result<()> {
+        self.check_block_access(block_id, buf.len())?;
+        let result = {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.block_offset(block_id)))
+                .and_then(|_| file.read_exact(buf))
+        };
+        result.map_err(|e| self.bad_block_error(block_id, e))
+    }
+
+    /// Writes `data`, at most one block long, to the start of data block
+    /// `block_id`. Nothing stops this from overwriting blocks that belong to
+    /// files; only write to blocks you allocated. I/O errors are handled as
+    /// in `read_block`.
+    pub fn write_block(&mut self, block_id: u32, data: &[u8]) -> io::Result<()> {
+        self.check_block_access(block_id, data.len())?;
+        let result = {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.block_offset(block_id)))
+                .and_then(|_| file.write_all(data))
+        };
+        result.map_err(|e| self.bad_block_error(block_id, e))
+    }
+
+    /// Records data block `block_id` as bad and marks it used, so no
+    /// allocator hands it out again. A file already holding it keeps it.
+    pub fn mark_bad_block(&mut self, block_id: u32) -> io::Result<()> {
+        self.check_block_id(block_id)?;
+        badblocks::add(&mut self.file.borrow_mut(), block_id)?;
+        self.set_bit(self.sb.data_bitmap_start, block_id)
+    }
+
+    /// Data blocks recorded as bad, in the order they were found.
+    pub fn bad_blocks(&mut self) -> io::Result<Vec<u32>> {
+        badblocks::read_table(&mut self.file.borrow_mut())
+    }
+
+    /// Turns an I/O error on `block_id` into a [`BadBlock`] error and records
+    /// the block. A read-only image or a read past the end of a truncated one
+    /// says nothing about the block, so those errors pass through.
+    fn bad_block_error(&mut self, block_id: u32, e: Error) -> Error {
+        if matches!(
+            e.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::UnexpectedEof
+        ) {
+            return e;
+        }
+        // The device just failed; if recording fails too, the block is still
+        // reported as bad.
+        let _ = self.mark_bad_block(block_id);
+        Error::new(
+            e.kind(),
+            BadBlock {
+                block_id,
+                source: e,
+            },
+        )
+    }
+
+    fn check_block_access(&self, block_id: u32, len: usize) -> io::Result<()> {
+        self.check_block_id(block_id)?;
+        if len > self.sb.block_size as usize {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Buffer of {} bytes is larger than a block of {} bytes!",
+                    len, self.sb.block_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Block 0 is the "no block" pointer and the journal is off limits.
+    fn check_block_id(&self, block_id: u32) -> io::Result<()> {
+        let end = self.sb.data_block_count() - self.sb.journal_blocks;
+        if block_id == 0 || block_id >= end {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Block {} is out of range 1..{}!", block_id, end),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether `count` more blocks can be taken without touching the reserve.
+    fn outside_reserve(&mut self, count: u32) -> io::Result<bool> {
+        if self.sb.reserved_blocks == 0 {
+            return Ok(true);
+        }
+        Ok(self.free_data_blocks()? >= count + self.sb.reserved_blocks)
+    }
+
+    fn free_data_blocks(&mut self) -> io::Result<u32> {
+        let bitmap = badblocks::data_bitmap(&mut self.file.borrow_mut(), &self.sb)?;
+        Ok(count_clear_bits(&bitmap, self.sb.data_block_count()))
     }
 
-    pub(crate) fn allocate_data_block(&mut self) -> io::Result<u32> {
-        self.allocate_bit(self.sb.data_bitmap_start, self.sb.inode_table_start)
+    fn allocate_contiguous_blocks(&mut self, count: u32) -> io::Result<Option<u32>> {
+        if count == 0 || !self.outside_reserve(count)? {
+            return Ok(None);
+        }
+
+        let start = self.sb.data_bitmap_start;
+        let mut file = self.file.borrow_mut();
+        let mut bitmap = badblocks::data_bitmap(&mut file, &self.sb)?;
+
+        let mut run_start = 0;
+        let mut run_len = 0;
+        for bit in 0..(bitmap.len() * 8) as u32 {
+            if bitmap[(bit / 8) as usize] & (1 << (bit % 8)) != 0 {
+                run_len = 0;
+                continue;
+            }
+            if run_len == 0 {
+                run_start = bit;
+            }
+            run_len += 1;
+
+            if run_len == count {
+                for id in run_start..run_start + count {
+                    bitmap[(id / 8) as usize] |= 1 << (id % 8);
+                }
+                let first_byte = (run_start / 8) as usize;
+                let last_byte = ((run_start + count - 1) / 8) as usize;
+                file.seek(SeekFrom::Start(start + first_byte as u64))?;
+                file.write_all(&bitmap[first_byte..=last_byte])?;
+                return Ok(Some(run_start));
+            }
+        }
+        Ok(None)
     }
 
     pub fn get_inode(&mut self, id: u32) -> io::Result<Inode> {
+        self.check_inode_id(id, io::ErrorKind::InvalidData)?;
         let pos = self.sb.inode_table_start + (id as u64 * INODE_SIZE as u64);
         let mut buffer = [0u8; INODE_SIZE];
         let mut file = self.file.borrow_mut();
         file.seek(SeekFrom::Start(pos))?;
         file.read_exact(&mut buffer)?;
-        Ok(Inode::from_bytes(&buffer))
+        Inode::from_bytes(&buffer)
+            .map_err(|e| Error::new(e.kind(), format!("Inode {} is corrupt: {}", id, e)))
     }
 
     pub fn save_inode(&mut self, id: u32, inode: Inode) -> io::Result<()> {
+        self.check_inode_id(id, io::ErrorKind::InvalidInput)?;
         let pos = self.sb.inode_table_start + (id as u64 * INODE_SIZE as u64);
         let mut file = self.file.borrow_mut();
         file.seek(SeekFrom::Start(pos))?;
@@ -238,27 +997,118 @@ impl Vfs {
         Ok(())
     }
 
-    pub fn find_inode_by_path(&mut self, path: &str) -> io::Result<u32> {
-        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-        let mut current_id = 0;
-        for part in parts {
-            current_id = self.find_in_dir(current_id, part)?;
-        }
+    /// Ids past the inode table would read or clobber the data area.
+    fn check_inode_id(&self, id: u32, kind: io::ErrorKind) -> io::Result<()> {
+        if id >= self.sb.inode_count() {
+            return Err(Error::new(
+                kind,
+                format!(
+                    "Inode id {} is out of range, the image has {} inodes!",
+                    id,
+                    self.sb.inode_count()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Resolves `path` to an inode id. Repeated and trailing slashes are
+    /// ignored; an empty path is rejected rather than taken as the root.
+    /// Symbolic links are followed, relative targets from the directory
+    /// holding the link.
+    pub fn find_inode_by_path(&mut self, path: &str) -> io::Result<u32> {
+        if path.is_empty() {
+            return Err(Error::new(io::ErrorKind::InvalidInput, "Path is empty!"));
+        }
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        self.check_depth(parts.len())?;
+        // Components still to look up, and the ones already walked. Links are
+        // replaced by their targets, so `walked` never goes through one.
+        let mut pending: VecDeque<String> = parts.iter().map(|s| s.to_string()).collect();
+        let mut walked: Vec<String> = Vec::new();
+        let mut hops = 0;
+        let mut current_id = 0;
+        while let Some(part) = pending.pop_front() {
+            if !walked.is_empty() && self.get_inode(current_id)?.inode_type != InodeKind::Dir {
+                return Err(Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("'{}' is not a directory!", walked.join("/")),
+                ));
+            }
+            let id = self.lookup_in_dir(current_id, &part)?.ok_or_else(|| {
+                Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "Path '{}' not found: component '{}' missing in '/{}'!",
+                        path,
+                        part,
+                        walked.join("/")
+                    ),
+                )
+            })?;
+            let inode = self.get_inode(id)?;
+            if inode.inode_type != InodeKind::Symlink {
+                walked.push(part);
+                current_id = id;
+                continue;
+            }
+
+            hops += 1;
+            if hops > MAX_SYMLINK_HOPS {
+                return Err(Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Too many levels of symbolic links in '{}'!", path),
+                ));
+            }
+            let target = self.link_target(&inode)?;
+            if target.starts_with('/') {
+                walked.clear();
+            }
+            let mut resolved = normalize_components(
+                walked
+                    .iter()
+                    .map(String::as_str)
+                    .chain(target.split('/').filter(|s| !s.is_empty())),
+            );
+            resolved.extend(pending);
+            self.check_depth(resolved.len())?;
+            pending = resolved;
+            walked.clear();
+            current_id = 0;
+        }
         Ok(current_id)
     }
 
-    fn find_in_dir(&mut self, dir_id: u32, name: &str) -> io::Result<u32> {
+    pub(crate) fn find_in_dir(&mut self, dir_id: u32, name: &str) -> io::Result<u32> {
+        self.find_in_dir_bytes(dir_id, name.as_bytes())
+    }
+
+    fn find_in_dir_bytes(&mut self, dir_id: u32, name: &[u8]) -> io::Result<u32> {
+        self.lookup_in_dir_bytes(dir_id, name)?.ok_or_else(|| {
+            Error::new(
+                io::ErrorKind::NotFound,
+                format!("Name '{}' does not exist!", String::from_utf8_lossy(name)),
+            )
+        })
+    }
+
+    /// Like `find_in_dir`, but a missing name is `None` rather than an error.
+    fn lookup_in_dir(&mut self, dir_id: u32, name: &str) -> io::Result<Option<u32>> {
+        self.lookup_in_dir_bytes(dir_id, name.as_bytes())
+    }
+
+    fn lookup_in_dir_bytes(&mut self, dir_id: u32, name: &[u8]) -> io::Result<Option<u32>> {
         let dir_inode = self.get_inode(dir_id)?;
 
-        for block_index in 0..1034 {
+        for block_index in 0..self.sb.max_file_blocks() {
             let physical_id = match self.just_read(&dir_inode, block_index)? {
                 Some(id) => id,
                 None => break,
             };
 
-            let block_pos = self.sb.data_blocks_start + (physical_id as u64 * BLOCK_SIZE as u64);
+            let block_pos = self.sb.block_offset(physical_id);
 
-            for i in 0..(BLOCK_SIZE / DIR_SIZE) {
+            for i in 0..self.sb.entries_per_block() {
                 let mut file = self.file.borrow_mut();
                 file.seek(SeekFrom::Start(block_pos + (i as u64 * DIR_SIZE as u64)))?;
                 let mut buffer = [0u8; DIR_SIZE];
@@ -267,39 +1117,211 @@ impl Vfs {
 
                 let entry = DirEntry::from_bytes(&buffer);
 
-                if entry.is_active == 1 {
-                    let entry_name = std::str::from_utf8(&entry.name)
-                        .unwrap_or("")
-                        .trim_matches('\0');
-                    if entry_name == name {
-                        if !self.is_inode_allocated(entry.inode_id)? {
-                            return Err(Error::new(
-                                io::ErrorKind::NotFound,
-                                format!("Inode for '{}' is corrupted!", name),
-                            ));
-                        }
-                        return Ok(entry.inode_id);
+                if entry.is_active == 1 && self.names_match(entry.name_bytes(), name) {
+                    if entry.inode_id >= self.sb.inode_count() {
+                        return Err(Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Entry '{}' points at inode {}, past the {} inodes of the image!",
+                                entry_name(&entry),
+                                entry.inode_id,
+                                self.sb.inode_count()
+                            ),
+                        ));
+                    }
+                    if !self.is_inode_allocated(entry.inode_id)? {
+                        return Err(Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("Inode for '{}' is corrupted!", entry_name(&entry)),
+                        ));
                     }
+                    return Ok(Some(entry.inode_id));
                 }
             }
         }
-        Err(Error::new(
-            io::ErrorKind::NotFound,
-            format!("Name '{}' does not exist!", name),
-        ))
+        Ok(None)
+    }
+
+    /// Sets when file writes sync the image, for this `Vfs` and all of its
+    /// handles, including ones already open. Defaults to
+    /// `SyncPolicy::EveryWrite`.
+    pub fn set_sync_policy(&mut self, policy: SyncPolicy) {
+        self.sync_policy.set(policy);
+    }
+
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.sync_policy.get()
+    }
+
+    /// Writes the superblock back and forces everything written so far to
+    /// the disk, whatever the sync policy. One durability checkpoint for
+    /// callers that sync rarely; read-only images are only synced.
+    pub fn sync(&mut self) -> io::Result<()> {
+        let mut file = self.file.borrow_mut();
+        if !file.is_read_only() {
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&self.sb.to_bytes())?;
+        }
+        file.sync_all()
+    }
+
+    /// Makes name lookups ignore ASCII case, so `README.TXT` finds
+    /// `readme.txt`. Names keep the casing they were created with, and creating
+    /// a name that differs from an existing one only in case fails.
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Makes name lookups compare names in Unicode NFC, so `café` written
+    /// with a combining accent finds `café` written with a precomposed one.
+    /// Names are stored with the bytes they were created with.
+    #[cfg(feature = "unicode")]
+    pub fn with_nfc_names(mut self, nfc_names: bool) -> Self {
+        self.nfc_names = nfc_names;
+        self
+    }
+
+    /// Limits paths to `max_depth` components below the root. Deeper paths,
+    /// and trees found deeper than that while walking, fail with an
+    /// `InvalidInput` error carrying [`PathTooDeep`]. Bounds the recursion of
+    /// tree operations on corrupted or hostile images.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    fn check_depth(&self, depth: usize) -> io::Result<()> {
+        if depth > self.max_depth {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                PathTooDeep {
+                    max_depth: self.max_depth,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    /// Compares raw name bytes, so names that are not valid UTF-8 can still
+    /// be matched exactly. Such names also match the lossy form `read_dir`
+    /// lists them under.
+    fn names_match(&self, stored: &[u8], wanted: &[u8]) -> bool {
+        let same = |a: &[u8], b: &[u8]| {
+            if self.case_insensitive {
+                a.eq_ignore_ascii_case(b)
+            } else {
+                a == b
+            }
+        };
+        if same(stored, wanted) {
+            return true;
+        }
+        #[cfg(feature = "unicode")]
+        if self.nfc_names
+            && let (Ok(stored), Ok(wanted)) =
+                (std::str::from_utf8(stored), std::str::from_utf8(wanted))
+        {
+            use unicode_normalization::UnicodeNormalization;
+            let stored: String = stored.nfc().collect();
+            let wanted: String = wanted.nfc().collect();
+            return same(stored.as_bytes(), wanted.as_bytes());
+        }
+        std::str::from_utf8(stored).is_err()
+            && same(String::from_utf8_lossy(stored).as_bytes(), wanted)
+    }
+
+    /// Fails with `AlreadyExists` if the directory has an entry that `name`
+    /// would match, whether a file or a directory.
+    fn check_name_free(&mut self, parent_id: u32, name: &str) -> io::Result<()> {
+        if self.lookup_in_dir(parent_id, name)?.is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("'{}' already exists!", name),
+            ));
+        }
+        Ok(())
     }
 
     pub fn create_dir(&mut self, path: &str) -> io::Result<()> {
-        let (parent_path, new_name) = path
+        self.journaled(|vfs| {
+            let (parent_id, new_name) = vfs.resolve_parent(path)?;
+            vfs.create_dir_in(parent_id, new_name)?;
+            Ok(())
+        })
+    }
+
+    /// Creates `path` and any missing parent directories. Directories that
+    /// already exist are left alone; a file in the way fails with
+    /// `NotADirectory`.
+    pub fn create_dir_all(&mut self, path: &str) -> io::Result<()> {
+        self.check_depth(path_depth(path))?;
+        self.journaled(|vfs| {
+            let mut current = 0;
+            for name in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+                current = match vfs.lookup_in_dir(current, name)? {
+                    Some(id) if vfs.get_inode(id)?.inode_type == InodeKind::Dir => id,
+                    Some(_) => {
+                        return Err(Error::new(
+                            io::ErrorKind::NotADirectory,
+                            format!("'{}' in '{}' is not a directory!", name, path),
+                        ));
+                    }
+                    None => vfs.create_dir_in(current, name)?,
+                };
+            }
+            Ok(())
+        })
+    }
+
+    /// Runs `create_dir_all` on each path in turn. Stops at the first failure,
+    /// keeping the directories made before it, and names the failing path in
+    /// the error.
+    pub fn create_dirs<I, S>(&mut self, paths: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for path in paths {
+            let path = path.as_ref();
+            self.create_dir_all(path)
+                .map_err(|e| Error::new(e.kind(), format!("Cannot create '{}': {}", path, e)))?;
+        }
+        Ok(())
+    }
+
+    /// Splits `path` into its parent directory and final name. Trailing and
+    /// repeated slashes are ignored, so `/a//b/` names `b` inside `/a`.
+    fn resolve_parent<'a>(&mut self, path: &'a str) -> io::Result<(u32, &'a str)> {
+        let trimmed = path.trim_end_matches('/');
+        let (parent_path, name) = trimmed
             .rfind('/')
-            .map_or(("", path), |pos| (&path[..pos], &path[pos + 1..]));
+            .map_or(("", trimmed), |pos| (&trimmed[..pos], &trimmed[pos + 1..]));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Path '{}' does not name an entry!", path),
+            ));
+        }
+        self.check_depth(path_depth(trimmed))?;
 
         let parent_id = if parent_path.is_empty() {
             0
         } else {
             self.find_inode_by_path(parent_path)?
         };
+        if self.get_inode(parent_id)?.inode_type != InodeKind::Dir {
+            return Err(Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("'{}' is not a directory!", parent_path),
+            ));
+        }
+        Ok((parent_id, name))
+    }
 
+    pub(crate) fn create_dir_in(&mut self, parent_id: u32, new_name: &str) -> io::Result<u32> {
+        validate_name(new_name)?;
+        self.check_name_free(parent_id, new_name)?;
         let new_id = self.allocate_inode()?;
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -307,13 +1329,16 @@ impl Vfs {
             .as_secs();
 
         let inode = Inode {
-            inode_type: 1,
+            inode_type: InodeKind::Dir,
             is_valid: 1,
             size: 0,
             created_at: now,
             modified_at: now,
             direct_blocks: [0; 10],
             indirect_blocks: 0,
+            xattr_block: 0,
+            generation: self.next_generation(new_id)?,
+            flags: 0,
         };
 
         self.save_inode(new_id, inode)?;
@@ -321,55 +1346,73 @@ impl Vfs {
         self.add_entry_to_parent(new_id, ".", new_id)?;
         self.add_entry_to_parent(new_id, "..", parent_id)?;
 
-        Ok(())
+        Ok(new_id)
     }
 
     fn add_entry_to_parent(&mut self, parent_id: u32, name: &str, child_id: u32) -> io::Result<()> {
+        // Names are never cut short, or two could end up stored the same.
+        validate_name(name)?;
         let mut name_bytes = [0u8; MAX_NAME_LEN];
-        let bytes = name.as_bytes();
-        let len = std::cmp::min(bytes.len(), MAX_NAME_LEN);
-        name_bytes[..len].copy_from_slice(&bytes[..len]);
+        name_bytes[..name.len()].copy_from_slice(name.as_bytes());
 
         let entry = DirEntry {
             inode_id: child_id,
             name: name_bytes,
             is_active: 1,
+            name_len: name.len() as u8,
         };
 
-        let pointers_per_block = (BLOCK_SIZE / 4) as u32;
+        let pointers_per_block = self.sb.pointers_per_block();
         let max_blocks = 10 + pointers_per_block;
 
         for block_index in 0..max_blocks {
             let physical_id = self.allocate_indirect_or_direct_blocks(parent_id, block_index)?;
-            let block_pos = self.sb.data_blocks_start + (physical_id as u64 * BLOCK_SIZE as u64);
-            for i in 0..(BLOCK_SIZE / DIR_SIZE) {
-                let entry_pos = block_pos + (i as u64 * DIR_SIZE as u64);
-
+            let block_pos = self.sb.block_offset(physical_id);
+            let mut block = vec![0u8; self.sb.entries_per_block() * DIR_SIZE];
+            {
                 let mut file = self.file.borrow_mut();
-                file.seek(SeekFrom::Start(entry_pos))?;
-                let mut buf = [0u8; DIR_SIZE];
-                file.read_exact(&mut buf)?;
-                if DirEntry::from_bytes(&buf).is_active == 0 {
+                file.seek(SeekFrom::Start(block_pos))?;
+                file.read_exact(&mut block)?;
+            }
+
+            // Slots of soft-removed files are kept so `undelete` can find them,
+            // until `purge_deleted` frees the inode.
+            let mut free_slot = None;
+            for (i, slot) in block.chunks_exact(DIR_SIZE).enumerate() {
+                let slot = DirEntry::from_bytes(slot);
+                let free = match slot.is_active {
+                    0 => true,
+                    models::ENTRY_TRASHED => !self.in_trash(slot.inode_id)?,
+                    _ => false,
+                };
+                if free {
+                    free_slot = Some(i);
+                    break;
+                }
+            }
+            if let Some(i) = free_slot {
+                {
+                    let entry_pos = block_pos + (i as u64 * DIR_SIZE as u64);
+                    let mut file = self.file.borrow_mut();
                     file.seek(SeekFrom::Start(entry_pos))?;
                     file.write_all(&entry.to_bytes())?;
-                    drop(file);
+                }
 
-                    let mut parent_inode = self.get_inode(parent_id)?;
-                    let now = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .map_err(|e| Error::other(e.to_string()))?
-                        .as_secs();
-                    parent_inode.modified_at = now;
-                    let entry_end_pos = (block_index as u64 * BLOCK_SIZE as u64)
-                        + ((i + 1) as u64 * DIR_SIZE as u64);
-                    if entry_end_pos > parent_inode.size {
-                        parent_inode.size = entry_end_pos;
-                    }
+                let mut parent_inode = self.get_inode(parent_id)?;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| Error::other(e.to_string()))?
+                    .as_secs();
+                parent_inode.modified_at = now;
+                let entry_end_pos = (block_index as u64 * self.sb.block_size as u64)
+                    + ((i + 1) as u64 * DIR_SIZE as u64);
+                if entry_end_pos > parent_inode.size {
+                    parent_inode.size = entry_end_pos;
+                }
 
-                    self.save_inode(parent_id, parent_inode)?;
+                self.save_inode(parent_id, parent_inode)?;
 
-                    return Ok(());
-                }
+                return Ok(());
             }
         }
 
@@ -377,89 +1420,413 @@ impl Vfs {
     }
 
     pub fn create_file(&mut self, path: &str) -> io::Result<VfsFile> {
-        let (parent_path, file_name) = path
-            .rfind('/')
-            .map_or(("", path), |pos| (&path[..pos], &path[pos + 1..]));
-        let parent_id = if parent_path.is_empty() {
-            0
-        } else {
-            self.find_inode_by_path(parent_path)?
-        };
+        self.journaled(|vfs| {
+            let (parent_id, file_name) = vfs.resolve_parent(path)?;
+            vfs.create_file_in(parent_id, file_name)
+        })
+    }
+
+    /// Like `create_file`, but also returns the new file's inode id.
+    pub fn create_file_with_id(&mut self, path: &str) -> io::Result<(u32, VfsFile)> {
+        let file = self.create_file(path)?;
+        Ok((file.inode_id, file))
+    }
+
+    /// Creates `path` with `contents` already written and flushed. The handle
+    /// is positioned at the end. If the write fails, for example for lack of
+    /// space, the file is removed again so no inode or block is left behind.
+    pub fn create_file_with(&mut self, path: &str, contents: &[u8]) -> io::Result<VfsFile> {
+        let mut file = self.create_file(path)?;
+        match file.write_all(contents).and_then(|_| file.flush()) {
+            Ok(()) => Ok(file),
+            Err(e) => {
+                drop(file);
+                let _ = self.remove(path);
+                Err(e)
+            }
+        }
+    }
+
+    /// Creates a symbolic link at `link` pointing at `target`, which is not
+    /// checked and need not exist. A relative target is resolved from the
+    /// directory holding the link. The target must fit in one block.
+    pub fn symlink(&mut self, target: &str, link: &str) -> io::Result<()> {
+        if target.is_empty() || target.len() > self.sb.block_size as usize {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Symlink target must be 1 to {} bytes long!",
+                    self.sb.block_size
+                ),
+            ));
+        }
+        self.journaled(|vfs| {
+            let (parent_id, name) = vfs.resolve_parent(link)?;
+            validate_name(name)?;
+            vfs.check_name_free(parent_id, name)?;
+            // Like attribute blocks, the target block is not charged to quotas.
+            let block = vfs.allocate_data_block()?;
+            vfs.write_block(block, target.as_bytes())?;
+            let new_id = vfs.allocate_inode()?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| Error::other(e.to_string()))?
+                .as_secs();
+            let mut direct_blocks = [0; 10];
+            direct_blocks[0] = block;
+            let inode = Inode {
+                inode_type: InodeKind::Symlink,
+                is_valid: 1,
+                size: target.len() as u64,
+                created_at: now,
+                modified_at: now,
+                direct_blocks,
+                indirect_blocks: 0,
+                xattr_block: 0,
+                generation: vfs.next_generation(new_id)?,
+                flags: 0,
+            };
+            vfs.save_inode(new_id, inode)?;
+            vfs.add_entry_to_parent(parent_id, name, new_id)
+        })
+    }
+
+    /// Target of the symbolic link at `path`, as given to `symlink`. The link
+    /// itself is not followed.
+    pub fn read_link(&mut self, path: &str) -> io::Result<String> {
+        let link_id = self.find_inode_no_follow(path)?;
+        let inode = self.get_inode(link_id)?;
+        if inode.inode_type != InodeKind::Symlink {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a symbolic link!", path),
+            ));
+        }
+        self.link_target(&inode)
+    }
+
+    /// Like `find_inode_by_path`, but a symbolic link as the last component
+    /// is returned itself rather than followed.
+    fn find_inode_no_follow(&mut self, path: &str) -> io::Result<u32> {
+        let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+        if matches!(name, "" | "." | "..") {
+            return self.find_inode_by_path(path);
+        }
+        let (parent_id, name) = self.resolve_parent(path)?;
+        self.find_in_dir(parent_id, name)
+    }
 
+    fn link_target(&mut self, inode: &Inode) -> io::Result<String> {
+        let len = (inode.size as usize).min(self.sb.block_size as usize);
+        let mut buf = vec![0u8; len];
+        self.read_block(inode.direct_blocks[0], &mut buf)?;
+        String::from_utf8(buf)
+            .map_err(|_| Error::new(io::ErrorKind::InvalidData, "Symlink target is not UTF-8!"))
+    }
+
+    pub(crate) fn create_file_in(
+        &mut self,
+        parent_id: u32,
+        file_name: &str,
+    ) -> io::Result<VfsFile> {
+        validate_name(file_name)?;
+        self.check_name_free(parent_id, file_name)?;
         let new_id = self.allocate_inode()?;
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map_err(|e| Error::other(e.to_string()))?
             .as_secs();
         let inode = Inode {
-            inode_type: 0,
+            inode_type: InodeKind::File,
             is_valid: 1,
             size: 0,
             created_at: now,
             modified_at: now,
             direct_blocks: [0; 10],
             indirect_blocks: 0,
+            xattr_block: 0,
+            generation: self.next_generation(new_id)?,
+            flags: 0,
         };
 
         self.save_inode(new_id, inode)?;
         self.add_entry_to_parent(parent_id, file_name, new_id)?;
         self.file.borrow_mut().sync_all()?;
 
-        Ok(VfsFile {
-            file: Rc::clone(&self.file),
-            sb: self.sb,
-            inode_id: new_id,
-            position: 0,
-        })
+        self.handle(new_id)
     }
 
     pub fn open_file(&mut self, path: &str) -> io::Result<VfsFile> {
         let inode_id = self.find_inode_by_path(path)?;
+        self.handle(inode_id)
+    }
+
+    pub(crate) fn handle(&mut self, inode_id: u32) -> io::Result<VfsFile> {
+        let generation = self.get_inode(inode_id)?.generation;
+        self.open.borrow_mut().entry(inode_id).or_default().handles += 1;
         Ok(VfsFile {
             file: Rc::clone(&self.file),
             sb: self.sb,
+            locks: Rc::clone(&self.locks),
+            open: Rc::clone(&self.open),
+            lock: None,
             inode_id,
             position: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            buffer_writes: 0,
+            buffer_inode: (0, 0),
+            sync_policy: Rc::clone(&self.sync_policy),
+            generation,
         })
     }
 
+    /// Names of the active entries of a directory. Entries pointing at a
+    /// free or out-of-range inode cannot be opened, so they are left out.
     pub fn read_dir(&mut self, path: &str) -> io::Result<Vec<String>> {
+        let dir_id = self.find_inode_by_path(path)?;
+        let entries = self.listed_entries(dir_id)?;
+        Ok(entries
+            .iter()
+            .map(|entry| entry_name(entry).into_owned())
+            .collect())
+    }
+
+    /// Lazily lists the active entry names of a directory, reading its blocks
+    /// only as the iterator advances, so `.find` or `.take` can stop early.
+    /// Changes made to the directory while iterating may or may not be seen.
+    pub fn entries(&mut self, path: &str) -> io::Result<EntryIter> {
         let dir_id = self.find_inode_by_path(path)?;
         let dir_inode = self.get_inode(dir_id)?;
+        if dir_inode.inode_type != InodeKind::Dir {
+            return Err(Error::other("Not a directory!"));
+        }
+        Ok(EntryIter::new(Rc::clone(&self.file), self.sb, dir_inode))
+    }
 
-        if dir_inode.inode_type != 1 {
+    /// Every entry slot of a directory, including removed entries and unused
+    /// slots, for tools that audit or undelete. Inactive slots keep the name
+    /// and inode id they last held.
+    pub fn read_dir_raw(&mut self, path: &str) -> io::Result<Vec<DirEntry>> {
+        let dir_id = self.find_inode_by_path(path)?;
+        self.dir_slots(dir_id)
+    }
+
+    /// The on-disk bytes of every entry slot of a directory, `DIR_SIZE` bytes
+    /// each and in the order `read_dir_raw` lists them, for callers that parse
+    /// the format themselves. Reading a directory through a `VfsFile` fails.
+    pub fn read_dir_bytes(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        let dir_id = self.find_inode_by_path(path)?;
+        self.dir_slot_bytes(dir_id)
+    }
+
+    /// Like `read_dir`, but flags names whose stored bytes are not valid UTF-8.
+    /// Those are listed with U+FFFD in place of the bad bytes and can be opened
+    /// or removed under that name.
+    pub fn read_dir_lossy(&mut self, path: &str) -> io::Result<Vec<(String, bool)>> {
+        let dir_id = self.find_inode_by_path(path)?;
+        let entries = self.listed_entries(dir_id)?;
+        Ok(entries
+            .iter()
+            .map(|entry| {
+                let name = entry_name(entry);
+                let invalid = matches!(name, Cow::Owned(_));
+                (name.into_owned(), invalid)
+            })
+            .collect())
+    }
+
+    /// Limits the data blocks that files under `dir` may use in total, counting
+    /// what the subtree already holds. Writes that would go past the limit fail
+    /// with `QuotaExceeded`. A limit of 0 removes the quota.
+    pub fn set_quota(&mut self, dir: &str, max_blocks: u32) -> io::Result<()> {
+        let dir_id = self.find_inode_by_path(dir)?;
+        if self.get_inode(dir_id)?.inode_type != InodeKind::Dir {
             return Err(Error::other("Not a directory!"));
         }
 
-        let mut entries = Vec::new();
-        for block_index in 0..1034 {
-            let physical_id = match self.just_read(&dir_inode, block_index)? {
-                Some(id) => id,
-                None => break,
-            };
+        let used = self.charged_blocks(dir_id)?;
+        quota::set(&mut self.file.borrow_mut(), dir_id, max_blocks, used)
+    }
 
-            let block_pos = self.sb.data_blocks_start + (physical_id as u64 * BLOCK_SIZE as u64);
-            for i in 0..(BLOCK_SIZE / DIR_SIZE) {
-                let mut file = self.file.borrow_mut();
-                file.seek(SeekFrom::Start(block_pos + (i as u64 * DIR_SIZE as u64)))?;
-                let mut buf = [0u8; DIR_SIZE];
-                file.read_exact(&mut buf)?;
-                drop(file);
+    /// Blocks that count against quotas for a file, or for every file under a
+    /// directory.
+    fn charged_blocks(&mut self, inode_id: u32) -> io::Result<u32> {
+        let mut used = 0;
+        let mut visited = HashSet::from([inode_id]);
+        let mut pending = vec![inode_id];
+        while let Some(id) = pending.pop() {
+            let inode = self.get_inode(id)?;
+            match inode.inode_type {
+                InodeKind::Dir => {
+                    for entry in self.dir_entries(id)? {
+                        let name = entry_name(&entry);
+                        if name != "." && name != ".." && visited.insert(entry.inode_id) {
+                            pending.push(entry.inode_id);
+                        }
+                    }
+                }
+                InodeKind::File => used += self.allocated_blocks(&inode)?.len() as u32,
+                _ => {}
+            }
+        }
+        Ok(used)
+    }
+
+    /// Runs `f` and, if it returns an error, undoes every change it made to the
+    /// image before passing the error on. The undo log is kept in memory, so
+    /// this protects against failures, not crashes. Nested calls join the
+    /// outermost transaction.
+    pub fn transaction<T>(&mut self, f: impl FnOnce(&mut Vfs) -> io::Result<T>) -> io::Result<T> {
+        if !self.file.borrow_mut().begin_undo() {
+            return f(self);
+        }
+        match f(self) {
+            Ok(value) => {
+                self.file.borrow_mut().commit_undo();
+                Ok(value)
+            }
+            Err(e) => {
+                self.file.borrow_mut().rollback_undo()?;
+                Err(e)
+            }
+        }
+    }
 
-                let entry = DirEntry::from_bytes(&buf);
+    /// Records the current state of the image under `name`. Only the metadata
+    /// area is copied up front; data blocks are copied aside the first time
+    /// they are overwritten. Both copies live in free data blocks of the image,
+    /// so snapshots survive reopening it. Names are 1 to 48 bytes long and at
+    /// most 16 snapshots exist at a time.
+    pub fn snapshot(&mut self, name: &str) -> io::Result<()> {
+        self.file.borrow_mut().take_snapshot(name, &self.sb)
+    }
+
+    /// Restores the image to snapshot `name`, dropping it and any snapshot
+    /// taken after it. Open handles should not be used afterwards.
+    pub fn rollback_to(&mut self, name: &str) -> io::Result<()> {
+        let mut file = self.file.borrow_mut();
+        file.rollback_snapshot(name)?;
+        // The superblock is part of what was restored.
+        self.sb = Self::read_superblock(&mut file, self.sb.key)?;
+        Ok(())
+    }
 
-                if entry.is_active == 1 {
-                    let name = std::str::from_utf8(&entry.name)
-                        .unwrap_or("")
-                        .trim_matches('\0')
-                        .to_string();
-                    entries.push(name);
+    /// Full paths of every entry under `root` for which `pred` returns true,
+    /// given the entry's path and inode. Each directory is visited once, so
+    /// cycles in a corrupted tree do not loop forever.
+    pub fn find<F: FnMut(&str, &Inode) -> bool>(
+        &mut self,
+        root: &str,
+        mut pred: F,
+    ) -> io::Result<Vec<String>> {
+        let root_id = self.find_inode_by_path(root)?;
+        let mut visited = HashSet::from([root_id]);
+        let mut pending = vec![(root_id, root.to_string(), path_depth(root))];
+        let mut found = Vec::new();
+
+        while let Some((dir_id, dir_path, depth)) = pending.pop() {
+            self.check_depth(depth + 1)?;
+            for entry in self.dir_entries(dir_id)? {
+                let name = entry_name(&entry);
+                if name == "." || name == ".." {
+                    continue;
+                }
+                let path = join_path(&dir_path, &name);
+                let inode = self.get_inode(entry.inode_id)?;
+                if pred(&path, &inode) {
+                    found.push(path.clone());
+                }
+                if inode.inode_type == InodeKind::Dir && visited.insert(entry.inode_id) {
+                    pending.push((entry.inode_id, path, depth + 1));
                 }
             }
         }
+        Ok(found)
+    }
+
+    /// Number of listed entries in a directory, not counting `.` and `..`.
+    /// Entries pointing at a free inode are left out, as `read_dir` does.
+    pub fn entry_count(&mut self, path: &str) -> io::Result<usize> {
+        let dir_id = self.find_inode_by_path(path)?;
+        let dir_inode = self.get_inode(dir_id)?;
+
+        if dir_inode.inode_type != InodeKind::Dir {
+            return Err(Error::other("Not a directory!"));
+        }
+
+        let entries = self.listed_entries(dir_id)?;
+        Ok(entries
+            .iter()
+            .filter(|entry| {
+                let name = entry_name(entry);
+                name != "." && name != ".."
+            })
+            .count())
+    }
+
+    pub fn is_empty_dir(&mut self, path: &str) -> io::Result<bool> {
+        Ok(self.entry_count(path)? == 0)
+    }
+
+    /// Active entries of a directory, including `.` and `..`.
+    pub(crate) fn dir_entries(&mut self, dir_id: u32) -> io::Result<Vec<DirEntry>> {
+        let mut entries = self.dir_slots(dir_id)?;
+        entries.retain(|entry| entry.is_active == 1);
         Ok(entries)
     }
 
+    /// Active entries whose inode is allocated, the ones `lookup_in_dir` would
+    /// resolve instead of reporting corruption.
+    fn listed_entries(&mut self, dir_id: u32) -> io::Result<Vec<DirEntry>> {
+        let mut entries = self.dir_entries(dir_id)?;
+        let bitmap = self.inode_bitmap()?;
+        entries.retain(|entry| bit_set(&bitmap, self.sb.inode_count(), entry.inode_id));
+        Ok(entries)
+    }
+
+    fn inode_bitmap(&mut self) -> io::Result<Vec<u8>> {
+        let start = self.sb.inode_bitmap_start;
+        let mut bitmap = vec![0u8; (self.sb.data_bitmap_start - start) as usize];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut bitmap)?;
+        Ok(bitmap)
+    }
+
+    /// Every entry slot in the directory's blocks, in on-disk order.
+    fn dir_slots(&mut self, dir_id: u32) -> io::Result<Vec<DirEntry>> {
+        let raw = self.dir_slot_bytes(dir_id)?;
+        Ok(raw
+            .chunks_exact(DIR_SIZE)
+            .map(DirEntry::from_bytes)
+            .collect())
+    }
+
+    /// The bytes of every entry slot, skipping the unused tail of each block.
+    fn dir_slot_bytes(&mut self, dir_id: u32) -> io::Result<Vec<u8>> {
+        let dir_inode = self.get_inode(dir_id)?;
+
+        if dir_inode.inode_type != InodeKind::Dir {
+            return Err(Error::other("Not a directory!"));
+        }
+
+        let mut raw = Vec::new();
+        let mut block = vec![0u8; self.sb.entries_per_block() * DIR_SIZE];
+        for block_index in 0..self.sb.max_file_blocks() {
+            let physical_id = match self.just_read(&dir_inode, block_index)? {
+                Some(id) => id,
+                None => break,
+            };
+
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.block_offset(physical_id)))?;
+            file.read_exact(&mut block)?;
+            raw.extend_from_slice(&block);
+        }
+        Ok(raw)
+    }
+
     pub fn allocate_indirect_or_direct_blocks(
         &mut self,
         inode_id: u32,
@@ -478,13 +1845,14 @@ impl Vfs {
             return Ok(direct_block);
         }
         let indirect_block_index = block_index - 10;
-        let max_pointers_per_block = (BLOCK_SIZE / 4) as u32;
+        let max_pointers_per_block = self.sb.pointers_per_block();
         if indirect_block_index >= max_pointers_per_block {
             return Err(io::Error::new(
                 io::ErrorKind::FileTooLarge,
                 format!(
-                    "File is too large! Maximum {} blocks supported.",
-                    10 + max_pointers_per_block
+                    "File is too large! Maximum {} bytes ({} blocks) supported.",
+                    self.sb.max_file_size(),
+                    self.sb.max_file_blocks()
                 ),
             ));
         }
@@ -492,16 +1860,9 @@ impl Vfs {
             let new_pointer_block = self.allocate_data_block()?;
             inode.indirect_blocks = new_pointer_block;
             self.save_inode(inode_id, inode)?;
-            let buffer = vec![0u8; BLOCK_SIZE];
-            let disk_position =
-                self.sb.data_blocks_start + (new_pointer_block as u64 * BLOCK_SIZE as u64);
-            let mut file = self.file.borrow_mut();
-            file.seek(SeekFrom::Start(disk_position))?;
-            file.write_all(&buffer)?;
         }
 
-        let indirect_block_disk_start =
-            self.sb.data_blocks_start + (inode.indirect_blocks as u64 * BLOCK_SIZE as u64);
+        let indirect_block_disk_start = self.sb.block_offset(inode.indirect_blocks);
         let pointer_address_on_disk = indirect_block_disk_start + (indirect_block_index as u64 * 4);
 
         let mut pointer_bytes = [0u8; 4];
@@ -533,9 +1894,7 @@ impl Vfs {
         }
 
         let indirect_idx = block_index - 10;
-        let pointer_pos = self.sb.data_blocks_start
-            + (inode.indirect_blocks as u64 * BLOCK_SIZE as u64)
-            + (indirect_idx as u64 * 4);
+        let pointer_pos = self.sb.block_offset(inode.indirect_blocks) + (indirect_idx as u64 * 4);
 
         let mut buf = [0u8; 4];
         let mut file = self.file.borrow_mut();
@@ -545,65 +1904,761 @@ impl Vfs {
 
         Ok(if id == 0 { None } else { Some(id) })
     }
-    pub fn remove(&mut self, path: &str) -> io::Result<()> {
-        let (parent_path, name) = path
-            .rfind('/')
-            .map_or(("", path), |pos| (&path[..pos], &path[pos + 1..]));
 
-        let parent_id = if parent_path.is_empty() {
-            0
-        } else {
-            self.find_inode_by_path(parent_path)?
-        };
-        let inode_id = self.find_in_dir(parent_id, name)?;
-        let inode = self.get_inode(inode_id)?;
-        for i in 0..10 {
-            if inode.direct_blocks[i] != 0 {
-                self.free_bit(self.sb.data_bitmap_start, inode.direct_blocks[i])?;
+    /// Digest of the contents of the file at `path`, read one block at a time
+    /// so the file is never held in memory whole.
+    pub fn hash_file(&mut self, path: &str, algo: HashAlgo) -> io::Result<Vec<u8>> {
+        let mut file = self.open_file(path)?;
+        let mut state = algo.start();
+        let mut buffer = vec![0u8; self.sb.block_size as usize];
+        loop {
+            let n = file.read(&mut buffer)?;
+            if n == 0 {
+                break;
             }
+            state.update(&buffer[..n]);
         }
-        if inode.indirect_blocks != 0 {
-            let mut pointer_buf = [0u8; BLOCK_SIZE];
-            let pos =
-                self.sb.data_blocks_start + (inode.indirect_blocks as u64 * BLOCK_SIZE as u64);
-            let mut file = self.file.borrow_mut();
-            file.seek(SeekFrom::Start(pos))?;
-            file.read_exact(&mut pointer_buf)?;
-            drop(file);
+        Ok(state.finish())
+    }
 
-            for chunk in pointer_buf.chunks_exact(4) {
-                let block_ptr = u32::from_le_bytes(chunk.try_into().unwrap());
-                if block_ptr != 0 {
-                    self.free_bit(self.sb.data_bitmap_start, block_ptr)?;
-                }
+    /// Sets or clears the immutable flag, like `chattr +i`. While set, the
+    /// entry cannot be written to, truncated, removed, renamed or replaced.
+    pub fn set_immutable(&mut self, path: &str, immutable: bool) -> io::Result<()> {
+        self.journaled(|vfs| {
+            let inode_id = vfs.find_inode_by_path(path)?;
+            let mut inode = vfs.get_inode(inode_id)?;
+            if immutable {
+                inode.flags |= models::INODE_IMMUTABLE;
+            } else {
+                inode.flags &= !models::INODE_IMMUTABLE;
             }
-            self.free_bit(self.sb.data_bitmap_start, inode.indirect_blocks)?;
-        }
-        self.free_bit(self.sb.inode_bitmap_start, inode_id)?;
-        self.set_entry_active_status(parent_id, name, 0)?;
-
-        Ok(())
+            vfs.save_inode(inode_id, inode)
+        })
     }
 
-    fn free_bit(&mut self, start_offset: u64, bit_idx: u32) -> io::Result<()> {
-        let byte_pos = (bit_idx / 8) as u64;
-        let bit_pos = (bit_idx % 8) as u8;
-
-        let mut file = self.file.borrow_mut();
-        file.seek(SeekFrom::Start(start_offset + byte_pos))?;
-        let mut byte = [0u8; 1];
-        file.read_exact(&mut byte)?;
-
-        byte[0] &= !(1 << bit_pos);
+    /// Sets the size of the file at `path` without opening it, as with
+    /// [`VfsFile::set_len`]. Fails with `IsADirectory` for directories.
+    pub fn truncate_file(&mut self, path: &str, len: u64) -> io::Result<()> {
+        self.journaled(|vfs| {
+            let inode_id = vfs.find_inode_by_path(path)?;
+            vfs.handle(inode_id)?.set_len(len)
+        })
+    }
 
-        file.seek(SeekFrom::Start(start_offset + byte_pos))?;
-        file.write_all(&byte)?;
+    pub fn remove(&mut self, path: &str) -> io::Result<()> {
+        self.remove_counted(path)?;
         Ok(())
     }
 
-    fn set_entry_active_status(&mut self, dir_id: u32, name: &str, status: u8) -> io::Result<()> {
+    /// Like `remove`, but reports the data blocks freed, pointer block
+    /// included, and the removed entry's size. A file that is still open
+    /// keeps its blocks until the last handle is dropped, so none are
+    /// reported freed for it.
+    pub fn remove_counted(&mut self, path: &str) -> io::Result<RemoveStats> {
+        self.journaled(|vfs| {
+            let (parent_id, name) = vfs.resolve_parent(path)?;
+            vfs.remove_entry(parent_id, name)
+        })
+    }
+
+    /// Moves `path` to the trash: its entry is removed and its inode marked
+    /// invalid, but the inode and its blocks stay allocated, so `undelete`
+    /// always works until `purge_deleted` frees them. Directories fail with
+    /// `IsADirectory`, since what is below them would be left unreachable.
+    pub fn remove_soft(&mut self, path: &str) -> io::Result<()> {
+        self.journaled(|vfs| {
+            let (parent_id, name) = vfs.resolve_parent(path)?;
+            let inode_id = vfs.find_in_dir(parent_id, name)?;
+            let mut inode = vfs.get_inode(inode_id)?;
+            check_mutable(&inode)?;
+            if inode.inode_type == InodeKind::Dir {
+                return Err(Error::new(
+                    io::ErrorKind::IsADirectory,
+                    format!("'{}' is a directory!", path),
+                ));
+            }
+            // Quotas only count linked files; `undelete` charges it again.
+            if inode.inode_type == InodeKind::File {
+                let blocks = vfs.allocated_blocks(&inode)?.len();
+                quota::charge(
+                    &mut vfs.file.borrow_mut(),
+                    &vfs.sb,
+                    inode_id,
+                    -(blocks as i64),
+                )?;
+            }
+            inode.is_valid = 0;
+            inode.flags |= models::INODE_DELETED;
+            vfs.save_inode(inode_id, inode)?;
+            vfs.set_entry_active_status(parent_id, name.as_bytes(), models::ENTRY_TRASHED)
+        })
+    }
+
+    /// Whether `inode_id` is a file `remove_soft` kept and that was not
+    /// purged yet.
+    fn in_trash(&mut self, inode_id: u32) -> io::Result<bool> {
+        Ok(inode_id != 0
+            && self.is_inode_allocated(inode_id)?
+            && self.get_inode(inode_id)?.is_deleted())
+    }
+
+    /// Frees the inodes and blocks of everything `remove_soft` kept, after
+    /// which it can no longer be undeleted. Files still open are left for a
+    /// later purge. Returns how many inodes were freed.
+    pub fn purge_deleted(&mut self) -> io::Result<usize> {
+        self.journaled(|vfs| {
+            let mut purged = 0;
+            for inode_id in 1..vfs.sb.inode_count() {
+                if vfs.open.borrow().contains_key(&inode_id) || !vfs.in_trash(inode_id)? {
+                    continue;
+                }
+                let inode = vfs.get_inode(inode_id)?;
+                vfs.free_file_blocks(&inode)?;
+                vfs.deallocate_inode(inode_id)?;
+                purged += 1;
+            }
+            vfs.file.borrow_mut().sync_all()?;
+            Ok(purged)
+        })
+    }
+
+    /// Inode id of the entry in `dir` whose stored name is exactly `name`.
+    /// Names that are not valid UTF-8 show up lossily in `read_dir`; this and
+    /// `remove_bytes` still reach them by their raw bytes.
+    pub fn find_bytes(&mut self, dir: &str, name: &[u8]) -> io::Result<u32> {
+        let dir_id = self.dir_by_path(dir)?;
+        self.find_in_dir_bytes(dir_id, name)
+    }
+
+    /// Removes the entry in `dir` whose stored name is exactly `name`.
+    pub fn remove_bytes(&mut self, dir: &str, name: &[u8]) -> io::Result<()> {
+        self.journaled(|vfs| {
+            let dir_id = vfs.dir_by_path(dir)?;
+            vfs.remove_entry_bytes(dir_id, name)?;
+            Ok(())
+        })
+    }
+
+    fn dir_by_path(&mut self, path: &str) -> io::Result<u32> {
+        let dir_id = self.find_inode_by_path(path)?;
+        if self.get_inode(dir_id)?.inode_type != InodeKind::Dir {
+            return Err(Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("'{}' is not a directory!", path),
+            ));
+        }
+        Ok(dir_id)
+    }
+
+    /// Removes every file in `dir` whose name matches `pattern`, where `*`
+    /// matches any run of characters and `?` any single one. Returns how many
+    /// entries were removed. Fails with `IsADirectory`, removing nothing, if a
+    /// directory matches; see `remove_glob_recursive`.
+    pub fn remove_glob(&mut self, dir: &str, pattern: &str) -> io::Result<usize> {
+        self.remove_matching(dir, pattern, false)
+    }
+
+    /// Like `remove_glob`, but matching directories are removed along with
+    /// everything in them.
+    pub fn remove_glob_recursive(&mut self, dir: &str, pattern: &str) -> io::Result<usize> {
+        self.remove_matching(dir, pattern, true)
+    }
+
+    fn remove_matching(&mut self, dir: &str, pattern: &str, recursive: bool) -> io::Result<usize> {
+        let dir_id = self.find_inode_by_path(dir)?;
+        // Collect first so removing entries does not disturb the scan.
+        let mut matches = Vec::new();
+        for entry in self.dir_entries(dir_id)? {
+            let name = entry_name(&entry).into_owned();
+            if name == "." || name == ".." || !glob_match(pattern, &name) {
+                continue;
+            }
+            let is_dir = self.get_inode(entry.inode_id)?.inode_type == InodeKind::Dir;
+            if is_dir && !recursive {
+                return Err(Error::new(
+                    io::ErrorKind::IsADirectory,
+                    format!("'{}' is a directory!", join_path(dir, &name)),
+                ));
+            }
+            matches.push((name, is_dir));
+        }
+
+        for (name, is_dir) in &matches {
+            self.journaled(|vfs| {
+                if *is_dir {
+                    vfs.remove_tree(dir_id, name, path_depth(dir) + 1, &mut HashSet::new())
+                } else {
+                    vfs.remove_entry(dir_id, name).map(|_| ())
+                }
+            })?;
+        }
+        Ok(matches.len())
+    }
+
+    /// Removes a directory's contents depth-first, then the directory.
+    fn remove_tree(
+        &mut self,
+        parent_id: u32,
+        name: &str,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+    ) -> io::Result<()> {
+        let dir_id = self.find_in_dir(parent_id, name)?;
+        if visited.insert(dir_id) {
+            self.check_depth(depth + 1)?;
+            for entry in self.dir_entries(dir_id)? {
+                let child = entry_name(&entry).into_owned();
+                if child == "." || child == ".." {
+                    continue;
+                }
+                if self.get_inode(entry.inode_id)?.inode_type == InodeKind::Dir {
+                    self.remove_tree(dir_id, &child, depth + 1, visited)?;
+                } else {
+                    self.remove_entry(dir_id, &child)?;
+                }
+            }
+        }
+        self.remove_entry(parent_id, name)?;
+        Ok(())
+    }
+
+    /// Brings back the removed entry `name` of `dir`, which works as long as
+    /// its inode and blocks have not been handed out again. Fails with
+    /// `AlreadyExists` if the name is taken, `NotFound` if no removed entry
+    /// has it, and leaves the image unchanged if anything was reused.
+    pub fn undelete(&mut self, dir: &str, name: &str) -> io::Result<()> {
+        self.journaled(|vfs| {
+            vfs.transaction(|vfs| {
+                let dir_id = vfs.find_inode_by_path(dir)?;
+                vfs.undelete_in(dir_id, name)
+            })
+        })
+    }
+
+    fn undelete_in(&mut self, dir_id: u32, name: &str) -> io::Result<()> {
+        self.check_name_free(dir_id, name)?;
+        // Several removed entries may share the name; take the last on disk.
+        let slots = self.dir_slots(dir_id)?;
+        let (slot, mut entry) = slots
+            .into_iter()
+            .enumerate()
+            .rev()
+            .find(|(_, e)| e.is_active != 1 && self.names_match(e.name_bytes(), name.as_bytes()))
+            .ok_or_else(|| {
+                Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No removed entry named '{}'!", name),
+                )
+            })?;
+        let reused = |what: String| Error::other(format!("Cannot undelete '{}': {}!", name, what));
+
+        let inode_id = entry.inode_id;
+        if inode_id == 0 || inode_id >= self.sb.inode_count() {
+            return Err(reused(format!("inode {} has been reused", inode_id)));
+        }
+        let mut inode = self.get_inode(inode_id)?;
+        let allocated = self.is_inode_allocated(inode_id)?;
+        // A soft-removed file still holds its inode and blocks.
+        let charged = if allocated && inode.is_deleted() {
+            inode.flags &= !models::INODE_DELETED;
+            inode.is_valid = 1;
+            self.save_inode(inode_id, inode)?;
+            self.allocated_blocks(&inode)?.len()
+        } else {
+            if allocated {
+                return Err(reused(format!("inode {} has been reused", inode_id)));
+            }
+            if inode.is_valid == 0 {
+                return Err(reused(format!("inode {} is no longer valid", inode_id)));
+            }
+
+            let start = self.sb.data_bitmap_start;
+            let mut bitmap = vec![0u8; (self.sb.inode_table_start - start) as usize];
+            {
+                let mut file = self.file.borrow_mut();
+                file.seek(SeekFrom::Start(start))?;
+                file.read_exact(&mut bitmap)?;
+            }
+            let is_free = |id: u32| {
+                id < self.sb.data_block_count() && bitmap[(id / 8) as usize] & (1 << (id % 8)) == 0
+            };
+
+            let mut blocks: Vec<u32> = inode.direct_blocks.to_vec();
+            if inode.indirect_blocks != 0 {
+                // The pointers are only trustworthy if their block was not reused.
+                if !is_free(inode.indirect_blocks) {
+                    return Err(reused(format!(
+                        "block {} has been reused",
+                        inode.indirect_blocks
+                    )));
+                }
+                let mut pointers = vec![0u8; self.sb.block_size as usize];
+                let mut file = self.file.borrow_mut();
+                file.seek(SeekFrom::Start(self.sb.block_offset(inode.indirect_blocks)))?;
+                file.read_exact(&mut pointers)?;
+                blocks.extend(
+                    pointers
+                        .chunks_exact(4)
+                        .map(|p| u32::from_le_bytes(p.try_into().unwrap())),
+                );
+                blocks.push(inode.indirect_blocks);
+            }
+            blocks.retain(|&id| id != 0);
+            let charged = blocks.len();
+            if inode.xattr_block != 0 {
+                blocks.push(inode.xattr_block);
+            }
+
+            let mut seen = HashSet::new();
+            for &id in &blocks {
+                if !is_free(id) || !seen.insert(id) {
+                    return Err(reused(format!("block {} has been reused", id)));
+                }
+            }
+            for &id in &blocks {
+                bitmap[(id / 8) as usize] |= 1 << (id % 8);
+            }
+            {
+                let mut file = self.file.borrow_mut();
+                file.seek(SeekFrom::Start(start))?;
+                file.write_all(&bitmap)?;
+            }
+            self.set_bit(self.sb.inode_bitmap_start, inode_id)?;
+            charged
+        };
+
         let dir_inode = self.get_inode(dir_id)?;
-        let max_blocks = 10 + (BLOCK_SIZE / 4) as u32;
+        let epb = self.sb.entries_per_block();
+        let block_index = (slot / epb) as u32;
+        let physical_id = self
+            .just_read(&dir_inode, block_index)?
+            .ok_or_else(|| Error::other("Directory block vanished!"))?;
+        entry.is_active = 1;
+        {
+            let mut file = self.file.borrow_mut();
+            let entry_pos = self.sb.block_offset(physical_id) + ((slot % epb) * DIR_SIZE) as u64;
+            file.seek(SeekFrom::Start(entry_pos))?;
+            file.write_all(&entry.to_bytes())?;
+        }
+
+        let mut dir_inode = self.get_inode(dir_id)?;
+        let entry_end =
+            block_index as u64 * self.sb.block_size as u64 + ((slot % epb + 1) * DIR_SIZE) as u64;
+        dir_inode.size = dir_inode.size.max(entry_end);
+        dir_inode.modified_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::other(e.to_string()))?
+            .as_secs();
+        self.save_inode(dir_id, dir_inode)?;
+
+        // Charged once linked again, so the quotas above it can be found.
+        if inode.inode_type == InodeKind::File {
+            quota::charge(
+                &mut self.file.borrow_mut(),
+                &self.sb,
+                inode_id,
+                charged as i64,
+            )?;
+        }
+        self.file.borrow_mut().sync_all()
+    }
+
+    /// Moves the entry at `from` to `to`, which may be in another directory.
+    /// An existing file at `to` is replaced; an existing directory is not.
+    /// A directory cannot be moved inside itself. The moved inode keeps both
+    /// timestamps, except that a directory changing parent is marked modified
+    /// for its rewritten `..`; the directories it left and entered are too.
+    pub fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.journaled(|vfs| vfs.transaction(|vfs| vfs.rename_entry(from, to)))
+    }
+
+    /// Moves the file or directory at `from`, with everything below it, to
+    /// `to`. The same as `rename`: a directory is moved by rewriting its
+    /// entry and its `..`, never by copying, so the cost does not depend on
+    /// the size of the subtree.
+    pub fn move_all(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.rename(from, to)
+    }
+
+    fn rename_entry(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let (src_parent, src_name) = self.resolve_parent(from)?;
+        let src_id = self.find_in_dir(src_parent, src_name)?;
+        let (dst_parent, dst_name) = self.resolve_parent(to)?;
+        validate_name(dst_name)?;
+        let src_inode = self.get_inode(src_id)?;
+        check_mutable(&src_inode)?;
+        let is_dir = src_inode.inode_type == InodeKind::Dir;
+
+        if let Some(dst_id) = self.lookup_in_dir(dst_parent, dst_name)? {
+            if dst_id == src_id {
+                return Ok(());
+            }
+            if self.get_inode(dst_id)?.inode_type == InodeKind::Dir {
+                return Err(Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("Directory '{}' already exists!", to),
+                ));
+            }
+            if is_dir {
+                return Err(Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("Cannot replace file '{}' with a directory!", to),
+                ));
+            }
+            self.remove_entry(dst_parent, dst_name)?;
+        }
+
+        if is_dir {
+            let mut current = dst_parent;
+            while current != 0 {
+                if current == src_id {
+                    return Err(Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Cannot move '{}' inside itself!", from),
+                    ));
+                }
+                current = self.find_in_dir(current, "..")?;
+            }
+        }
+
+        // Counting means walking the whole subtree, so only do it when a
+        // quota needs the number; otherwise moving a directory stays O(1).
+        let blocks = if quota::any_set(&mut self.file.borrow_mut())? {
+            self.charged_blocks(src_id)? as i64
+        } else {
+            0
+        };
+        quota::charge(&mut self.file.borrow_mut(), &self.sb, src_id, -blocks)?;
+        self.add_entry_to_parent(dst_parent, dst_name, src_id)?;
+        self.set_entry_active_status(src_parent, src_name.as_bytes(), 0)?;
+        // Rewriting `..` is the only change to a moved directory's own
+        // contents, so it alone decides whether its `modified_at` moves.
+        if is_dir && src_parent != dst_parent {
+            self.set_entry_active_status(src_id, b"..", 0)?;
+            self.add_entry_to_parent(src_id, "..", dst_parent)?;
+        }
+        quota::charge(&mut self.file.borrow_mut(), &self.sb, src_id, blocks)?;
+        self.file.borrow_mut().sync_all()
+    }
+
+    /// Exchanges the entries at `a` and `b`, like Linux's `RENAME_EXCHANGE`:
+    /// afterwards each name refers to what the other did. Both must exist.
+    /// Only the two entries are rewritten, plus `..` of a directory that ends
+    /// up under a different parent.
+    pub fn rename_swap(&mut self, a: &str, b: &str) -> io::Result<()> {
+        self.journaled(|vfs| vfs.transaction(|vfs| vfs.swap_entries(a, b)))
+    }
+
+    fn swap_entries(&mut self, a: &str, b: &str) -> io::Result<()> {
+        let (a_parent, a_name) = self.resolve_parent(a)?;
+        let a_id = self.find_in_dir(a_parent, a_name)?;
+        let (b_parent, b_name) = self.resolve_parent(b)?;
+        let b_id = self.find_in_dir(b_parent, b_name)?;
+        if a_id == b_id {
+            return Ok(());
+        }
+
+        let mut moved_dirs = Vec::new();
+        for (id, new_parent, path) in [(a_id, b_parent, a), (b_id, a_parent, b)] {
+            let inode = self.get_inode(id)?;
+            check_mutable(&inode)?;
+            if inode.inode_type != InodeKind::Dir {
+                continue;
+            }
+            let mut current = new_parent;
+            while current != 0 {
+                if current == id {
+                    return Err(Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Cannot move '{}' inside itself!", path),
+                    ));
+                }
+                current = self.find_in_dir(current, "..")?;
+            }
+            if a_parent != b_parent {
+                moved_dirs.push((id, new_parent));
+            }
+        }
+
+        let (a_blocks, b_blocks) = if quota::any_set(&mut self.file.borrow_mut())? {
+            (
+                self.charged_blocks(a_id)? as i64,
+                self.charged_blocks(b_id)? as i64,
+            )
+        } else {
+            (0, 0)
+        };
+        quota::charge(&mut self.file.borrow_mut(), &self.sb, a_id, -a_blocks)?;
+        quota::charge(&mut self.file.borrow_mut(), &self.sb, b_id, -b_blocks)?;
+        self.set_entry_inode(a_parent, a_name, b_id)?;
+        self.set_entry_inode(b_parent, b_name, a_id)?;
+        for (dir_id, new_parent) in moved_dirs {
+            self.set_entry_inode(dir_id, "..", new_parent)?;
+        }
+        quota::charge(&mut self.file.borrow_mut(), &self.sb, a_id, a_blocks)?;
+        quota::charge(&mut self.file.borrow_mut(), &self.sb, b_id, b_blocks)?;
+        self.file.borrow_mut().sync_all()
+    }
+
+    /// Points the active entry `name` in `dir_id` at `inode_id` in place and
+    /// marks the directory modified.
+    fn set_entry_inode(&mut self, dir_id: u32, name: &str, inode_id: u32) -> io::Result<()> {
+        let dir_inode = self.get_inode(dir_id)?;
+        for block_index in 0..self.sb.max_file_blocks() {
+            let physical_id = match self.just_read(&dir_inode, block_index)? {
+                Some(id) => id,
+                None => break,
+            };
+
+            let block_pos = self.sb.block_offset(physical_id);
+            let mut block = vec![0u8; self.sb.entries_per_block() * DIR_SIZE];
+            {
+                let mut file = self.file.borrow_mut();
+                file.seek(SeekFrom::Start(block_pos))?;
+                file.read_exact(&mut block)?;
+            }
+
+            let slot = block.chunks_exact(DIR_SIZE).position(|raw| {
+                let entry = DirEntry::from_bytes(raw);
+                entry.is_active == 1 && self.names_match(entry.name_bytes(), name.as_bytes())
+            });
+            if let Some(i) = slot {
+                let entry_pos = block_pos + (i * DIR_SIZE) as u64;
+                let mut entry = DirEntry::from_bytes(&block[i * DIR_SIZE..(i + 1) * DIR_SIZE]);
+                entry.inode_id = inode_id;
+                {
+                    let mut file = self.file.borrow_mut();
+                    file.seek(SeekFrom::Start(entry_pos))?;
+                    file.write_all(&entry.to_bytes())?;
+                }
+
+                let mut dir_inode = self.get_inode(dir_id)?;
+                dir_inode.modified_at = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| Error::other(e.to_string()))?
+                    .as_secs();
+                return self.save_inode(dir_id, dir_inode);
+            }
+        }
+        Err(Error::new(io::ErrorKind::NotFound, "Entry not found!"))
+    }
+
+    /// Copies the file at `from` to a new file at `to`. The copy is created
+    /// now but keeps the source's modification time, like `cp -p` does for
+    /// contents. If the copy fails midway, `to` is removed again.
+    pub fn copy(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.copy_file(from, to, false)
+    }
+
+    /// Like `copy`, but the copy also keeps the source's creation time.
+    pub fn copy_preserve_all(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.copy_file(from, to, true)
+    }
+
+    fn copy_file(&mut self, from: &str, to: &str, keep_created: bool) -> io::Result<()> {
+        let src_id = self.find_inode_by_path(from)?;
+        let src_inode = self.get_inode(src_id)?;
+        if src_inode.inode_type == InodeKind::Dir {
+            return Err(Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("'{}' is a directory!", from),
+            ));
+        }
+        let (dst_parent, dst_name) = self.resolve_parent(to)?;
+        if self.lookup_in_dir(dst_parent, dst_name)?.is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("'{}' already exists!", to),
+            ));
+        }
+
+        let mut src_file = self.handle(src_id)?;
+        let mut dst_file = self.create_file(to)?;
+        let dst_id = dst_file.inode_id;
+        let mut buffer = vec![0u8; self.sb.block_size as usize];
+        let result = (|| loop {
+            let n = src_file.read(&mut buffer)?;
+            if n == 0 {
+                return Ok(());
+            }
+            dst_file.write_all(&buffer[..n])?;
+        })();
+        drop(dst_file);
+        // Never leave a partial copy behind, e.g. when the image fills up.
+        if let Err(e) = result {
+            let _ = self.remove(to);
+            return Err(e);
+        }
+
+        let mut dst_inode = self.get_inode(dst_id)?;
+        dst_inode.modified_at = src_inode.modified_at;
+        if keep_created {
+            dst_inode.created_at = src_inode.created_at;
+        }
+        self.save_inode(dst_id, dst_inode)
+    }
+
+    /// Replaces the contents of `path` all at once: the data goes to a hidden
+    /// file in the same directory first and is renamed over `path` only once
+    /// it is fully written, so `path` never holds partial contents.
+    pub fn write_file_atomic(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        let (parent_id, _) = self.resolve_parent(path)?;
+        let trimmed = path.trim_end_matches('/');
+        let parent_path = &trimmed[..trimmed.rfind('/').map_or(0, |pos| pos + 1)];
+
+        let mut n = 0;
+        let tmp_name = loop {
+            let candidate = format!(".atomic-{}.tmp", n);
+            if self.lookup_in_dir(parent_id, &candidate)?.is_none() {
+                break candidate;
+            }
+            n += 1;
+        };
+        let tmp_path = format!("{}{}", parent_path, tmp_name);
+
+        let result = (|| {
+            let mut file = self.create_file(&tmp_path)?;
+            file.write_all(data)?;
+            drop(file);
+            self.file.borrow_mut().sync_all()?;
+            self.rename(&tmp_path, path)
+        })();
+        if result.is_err() {
+            let _ = self.remove(&tmp_path);
+        }
+        result
+    }
+
+    /// Runs a metadata operation as one journal transaction when the image has
+    /// a journal and the `journal` feature is enabled, so a crash midway either
+    /// loses the whole operation or gets it replayed on the next `open`.
+    fn journaled<T>(&mut self, f: impl FnOnce(&mut Vfs) -> io::Result<T>) -> io::Result<T> {
+        #[cfg(feature = "journal")]
+        if self.sb.journal_blocks > 0 && self.file.borrow_mut().begin_journal() {
+            let result = f(self);
+            let mut disk = self.file.borrow_mut();
+            return match result {
+                Ok(value) => {
+                    disk.commit_journal(
+                        self.sb.journal_start,
+                        self.sb.journal_blocks,
+                        self.sb.block_size,
+                    )?;
+                    Ok(value)
+                }
+                Err(e) => {
+                    disk.abort_journal();
+                    Err(e)
+                }
+            };
+        }
+        f(self)
+    }
+
+    /// Makes the next journal commit stop as if the process died, either right
+    /// before or right after the commit record is written. Only built with the
+    /// `crash-test` feature, for testing recovery.
+    #[cfg(feature = "crash-test")]
+    pub fn simulate_journal_crash(&mut self, after_commit: bool) {
+        let point = if after_commit {
+            disk::CrashPoint::AfterCommit
+        } else {
+            disk::CrashPoint::BeforeCommit
+        };
+        self.file.borrow_mut().set_crash_point(point);
+    }
+
+    pub(crate) fn remove_entry(&mut self, parent_id: u32, name: &str) -> io::Result<RemoveStats> {
+        self.remove_entry_bytes(parent_id, name.as_bytes())
+    }
+
+    fn remove_entry_bytes(&mut self, parent_id: u32, name: &[u8]) -> io::Result<RemoveStats> {
+        let inode_id = self.find_in_dir_bytes(parent_id, name)?;
+        let inode = self.get_inode(inode_id)?;
+        check_mutable(&inode)?;
+
+        // Unlinking while handles are open only drops the name; the last handle
+        // to go away frees the inode, so its id cannot be reused under them.
+        let still_open = match self.open.borrow_mut().get_mut(&inode_id) {
+            Some(state) => {
+                state.unlinked = true;
+                true
+            }
+            None => false,
+        };
+        if still_open {
+            if inode.inode_type == InodeKind::File {
+                let blocks = self.allocated_blocks(&inode)?.len();
+                quota::charge(
+                    &mut self.file.borrow_mut(),
+                    &self.sb,
+                    inode_id,
+                    -(blocks as i64),
+                )?;
+            }
+            self.set_entry_active_status(parent_id, name, 0)?;
+            return Ok(RemoveStats {
+                freed_blocks: 0,
+                size: inode.size,
+            });
+        }
+
+        let freed = self.free_file_blocks(&inode)?;
+        if inode.inode_type == InodeKind::File {
+            quota::charge(
+                &mut self.file.borrow_mut(),
+                &self.sb,
+                inode_id,
+                -(freed as i64),
+            )?;
+        }
+        self.free_bit(self.sb.inode_bitmap_start, inode_id)?;
+        self.set_entry_active_status(parent_id, name, 0)?;
+
+        Ok(RemoveStats {
+            freed_blocks: freed,
+            size: inode.size,
+        })
+    }
+
+    fn free_file_blocks(&mut self, inode: &Inode) -> io::Result<u32> {
+        free_file_blocks(&mut self.file.borrow_mut(), &self.sb, inode)
+    }
+
+    fn allocated_blocks(&mut self, inode: &Inode) -> io::Result<Vec<u32>> {
+        allocated_blocks(&mut self.file.borrow_mut(), &self.sb, inode)
+    }
+
+    pub(crate) fn free_bit(&mut self, start_offset: u64, bit_idx: u32) -> io::Result<()> {
+        let byte_pos = (bit_idx / 8) as u64;
+        let bit_pos = (bit_idx % 8) as u8;
+
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(start_offset + byte_pos))?;
+        let mut byte = [0u8; 1];
+        file.read_exact(&mut byte)?;
+
+        byte[0] &= !(1 << bit_pos);
+
+        file.seek(SeekFrom::Start(start_offset + byte_pos))?;
+        file.write_all(&byte)?;
+        Ok(())
+    }
+
+    fn set_bit(&mut self, start_offset: u64, bit_idx: u32) -> io::Result<()> {
+        let pos = start_offset + (bit_idx / 8) as u64;
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(pos))?;
+        let mut byte = [0u8; 1];
+        file.read_exact(&mut byte)?;
+        byte[0] |= 1 << (bit_idx % 8);
+        file.seek(SeekFrom::Start(pos))?;
+        file.write_all(&byte)
+    }
+
+    fn set_entry_active_status(&mut self, dir_id: u32, name: &[u8], status: u8) -> io::Result<()> {
+        let dir_inode = self.get_inode(dir_id)?;
+        let max_blocks = self.sb.max_file_blocks();
 
         for block_index in 0..max_blocks {
             let physical_id = match self.just_read(&dir_inode, block_index)? {
@@ -611,8 +2666,8 @@ impl Vfs {
                 None => break,
             };
 
-            let block_pos = self.sb.data_blocks_start + (physical_id as u64 * BLOCK_SIZE as u64);
-            for i in 0..(BLOCK_SIZE / DIR_SIZE) {
+            let block_pos = self.sb.block_offset(physical_id);
+            for i in 0..self.sb.entries_per_block() {
                 let entry_pos = block_pos + (i as u64 * DIR_SIZE as u64);
                 let mut file = self.file.borrow_mut();
                 file.seek(SeekFrom::Start(entry_pos))?;
@@ -620,78 +2675,751 @@ impl Vfs {
                 file.read_exact(&mut buf)?;
                 let mut entry = DirEntry::from_bytes(&buf);
 
-                let entry_name = std::str::from_utf8(&entry.name)
-                    .unwrap_or("")
-                    .trim_matches('\0');
-                if entry.is_active == 1 && entry_name == name {
+                if entry.is_active == 1 && self.names_match(entry.name_bytes(), name) {
                     entry.is_active = status;
                     file.seek(SeekFrom::Start(entry_pos))?;
                     file.write_all(&entry.to_bytes())?;
-                    return Ok(());
+                    drop(file);
+                    return self.update_dir_after_removal(dir_id);
                 }
             }
         }
         Err(Error::new(io::ErrorKind::NotFound, "Entry not found!"))
     }
+
+    /// Marks a directory modified and sets its size to the end of its last
+    /// active entry, so it goes back down as entries at the tail are removed.
+    fn update_dir_after_removal(&mut self, dir_id: u32) -> io::Result<()> {
+        let mut dir_inode = self.get_inode(dir_id)?;
+        let mut end = 0;
+        for block_index in 0..self.sb.max_file_blocks() {
+            let physical_id = match self.just_read(&dir_inode, block_index)? {
+                Some(id) => id,
+                None => break,
+            };
+
+            let mut block = vec![0u8; self.sb.block_size as usize];
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.block_offset(physical_id)))?;
+            file.read_exact(&mut block)?;
+            drop(file);
+
+            for (i, raw) in block.chunks_exact(DIR_SIZE).enumerate() {
+                if DirEntry::from_bytes(raw).is_active == 1 {
+                    end = block_index as u64 * self.sb.block_size as u64
+                        + ((i + 1) * DIR_SIZE) as u64;
+                }
+            }
+        }
+        dir_inode.size = dir_inode.size.min(end);
+        dir_inode.modified_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::other(e.to_string()))?
+            .as_secs();
+        self.save_inode(dir_id, dir_inode)
+    }
+
+    /// Block and inode usage of the image.
+    pub fn statfs(&mut self) -> io::Result<FsStats> {
+        let free_blocks = self.free_data_blocks()?;
+        let inode_bitmap = self.inode_bitmap()?;
+
+        let mut table = vec![0u8; self.sb.inode_count() as usize * INODE_SIZE];
+        {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.inode_table_start))?;
+            file.read_exact(&mut table)?;
+        }
+        let (mut file_count, mut dir_count) = (0, 0);
+        for (id, raw) in table.chunks_exact(INODE_SIZE).enumerate() {
+            if !bit_set(&inode_bitmap, self.sb.inode_count(), id as u32) {
+                continue;
+            }
+            let inode = Inode::from_bytes(raw)?;
+            if inode.is_deleted() {
+                continue;
+            }
+            match inode.inode_type {
+                InodeKind::File => file_count += 1,
+                InodeKind::Dir => dir_count += 1,
+                InodeKind::Symlink => {}
+            }
+        }
+        Ok(FsStats {
+            block_size: self.sb.block_size,
+            total_blocks: self.sb.data_block_count() - self.sb.journal_blocks,
+            free_blocks,
+            available_blocks: free_blocks.saturating_sub(self.sb.reserved_blocks),
+            reserved_blocks: self.sb.reserved_blocks,
+            total_inodes: self.sb.inode_count(),
+            free_inodes: count_clear_bits(&inode_bitmap, self.sb.inode_count()),
+            bad_blocks: self.bad_blocks()?.len() as u32,
+            file_count,
+            dir_count,
+        })
+    }
+
+    /// Returns the raw on-disk inode. Prefer [`Vfs::metadata`], which does not
+    /// expose layout details like block pointers.
     pub fn stat(&mut self, path: &str) -> io::Result<Inode> {
         let inode_id = self.find_inode_by_path(path)?;
         self.get_inode(inode_id)
     }
-    pub fn list_long(&mut self, path: &str) -> io::Result<()> {
-        let dir_id = self.find_inode_by_path(path)?;
-        let dir_inode = self.get_inode(dir_id)?;
 
-        if dir_inode.inode_type != 1 {
-            return Err(Error::other("Not a directory!"));
+    /// Kind of the entry at `path`. Unlike `metadata`, a symbolic link as the
+    /// last component is not followed, so it reports `InodeKind::Symlink`.
+    pub fn file_type(&mut self, path: &str) -> io::Result<InodeKind> {
+        let inode_id = self.find_inode_no_follow(path)?;
+        Ok(self.get_inode(inode_id)?.inode_type)
+    }
+
+    /// Follows symbolic links; see `file_type` to detect them.
+    pub fn metadata(&mut self, path: &str) -> io::Result<Metadata> {
+        let inode = self.stat(path)?;
+        Ok(Metadata::from(&inode))
+    }
+
+    /// Physical data block ids backing a file in logical order, direct blocks
+    /// first. Holes inside the file show up as `0`, which is never a data block.
+    pub fn block_map(&mut self, path: &str) -> io::Result<Vec<u32>> {
+        let inode = self.stat(path)?;
+        let mut map = vec![0; inode.size.div_ceil(self.sb.block_size as u64) as usize];
+        for (logical, physical) in self.file_blocks(&inode)? {
+            map[logical as usize] = physical;
+        }
+        Ok(map)
+    }
+
+    /// Counts the contiguous runs of physical blocks backing a file. A file
+    /// whose blocks are all adjacent has a single extent.
+    pub fn fragmentation(&mut self, path: &str) -> io::Result<FragStats> {
+        let inode_id = self.find_inode_by_path(path)?;
+        let inode = self.get_inode(inode_id)?;
+
+        let mut stats = FragStats::default();
+        let mut previous: Option<u32> = None;
+        for (_, physical_id) in self.file_blocks(&inode)? {
+            stats.blocks += 1;
+            match previous {
+                Some(prev) if physical_id == prev + 1 => {}
+                Some(prev) => {
+                    stats.extents += 1;
+                    stats.largest_gap = stats.largest_gap.max(physical_id.abs_diff(prev) - 1);
+                }
+                None => stats.extents += 1,
+            }
+            previous = Some(physical_id);
+        }
+        Ok(stats)
+    }
+
+    /// Moves a file's data into a single contiguous run of blocks. The new
+    /// copy is fully written before the inode is switched over, and the old
+    /// blocks are only freed afterwards, so a crash leaks blocks at worst.
+    /// If no large enough free run exists the file is left untouched.
+    pub fn defragment_file(&mut self, path: &str) -> io::Result<()> {
+        let inode_id = self.find_inode_by_path(path)?;
+        let inode = self.get_inode(inode_id)?;
+        if self.fragmentation(path)?.extents <= 1 {
+            return Ok(());
+        }
+
+        let old_blocks = self.file_blocks(&inode)?;
+        let count = old_blocks.len() as u32;
+        let first_id = match self.allocate_contiguous_blocks(count)? {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let needs_indirect = old_blocks.iter().any(|(index, _)| *index >= 10);
+        let pointer_block = if needs_indirect {
+            match self.allocate_data_block() {
+                Ok(id) => id,
+                Err(e) => {
+                    for id in first_id..first_id + count {
+                        self.free_bit(self.sb.data_bitmap_start, id)?;
+                    }
+                    return Err(e);
+                }
+            }
+        } else {
+            0
+        };
+
+        let mut new_inode = inode;
+        new_inode.direct_blocks = [0; 10];
+        new_inode.indirect_blocks = pointer_block;
+        let mut pointers = vec![0u8; self.sb.block_size as usize];
+        let mut buffer = vec![0u8; self.sb.block_size as usize];
+
+        for ((block_index, old_id), new_id) in old_blocks.iter().zip(first_id..) {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.block_offset(*old_id)))?;
+            file.read_exact(&mut buffer)?;
+            file.seek(SeekFrom::Start(self.sb.block_offset(new_id)))?;
+            file.write_all(&buffer)?;
+
+            if *block_index < 10 {
+                new_inode.direct_blocks[*block_index as usize] = new_id;
+            } else {
+                let slot = (*block_index - 10) as usize * 4;
+                pointers[slot..slot + 4].copy_from_slice(&new_id.to_le_bytes());
+            }
+        }
+
+        if needs_indirect {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.block_offset(pointer_block)))?;
+            file.write_all(&pointers)?;
+        }
+        self.file.borrow_mut().sync_all()?;
+
+        self.save_inode(inode_id, new_inode)?;
+        self.file.borrow_mut().sync_all()?;
+
+        for (_, old_id) in old_blocks {
+            self.free_bit(self.sb.data_bitmap_start, old_id)?;
+        }
+        if inode.indirect_blocks != 0 {
+            self.free_bit(self.sb.data_bitmap_start, inode.indirect_blocks)?;
         }
+        Ok(())
+    }
+
+    fn file_blocks(&mut self, inode: &Inode) -> io::Result<Vec<(u32, u32)>> {
+        let block_count = inode.size.div_ceil(self.sb.block_size as u64) as u32;
+        let mut blocks = Vec::new();
+        for block_index in 0..block_count {
+            if let Some(id) = self.just_read(inode, block_index)? {
+                blocks.push((block_index, id));
+            }
+        }
+        Ok(blocks)
+    }
+    /// Recreates the tree at `src_path` of another image under `dst_path`,
+    /// copying file contents and timestamps. Stops at the first error, such as
+    /// the destination running out of space.
+    pub fn copy_tree_from(
+        &mut self,
+        src_vfs: &mut Vfs,
+        src_path: &str,
+        dst_path: &str,
+    ) -> io::Result<()> {
+        self.copy_tree_visited(src_vfs, src_path, dst_path, &mut HashSet::new())
+    }
+
+    fn copy_tree_visited(
+        &mut self,
+        src_vfs: &mut Vfs,
+        src_path: &str,
+        dst_path: &str,
+        visited: &mut HashSet<u32>,
+    ) -> io::Result<()> {
+        let src_id = src_vfs.find_inode_by_path(src_path)?;
+        let src_inode = src_vfs.get_inode(src_id)?;
+
+        let dst_id = if src_inode.inode_type == InodeKind::Dir {
+            if !visited.insert(src_id) {
+                return Err(Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Directory cycle detected at '{}'!", src_path),
+                ));
+            }
+
+            let existing = match self.find_inode_by_path(dst_path) {
+                Ok(id) if self.get_inode(id)?.inode_type == InodeKind::Dir => Some(id),
+                _ => None,
+            };
+            if existing.is_none() {
+                self.create_dir(dst_path)?;
+            }
+
+            for name in src_vfs.read_dir(src_path)? {
+                if name == "." || name == ".." {
+                    continue;
+                }
+                self.copy_tree_visited(
+                    src_vfs,
+                    &join_path(src_path, &name),
+                    &join_path(dst_path, &name),
+                    visited,
+                )?;
+            }
+
+            match existing {
+                Some(_) => return Ok(()),
+                None => self.find_inode_by_path(dst_path)?,
+            }
+        } else {
+            let mut src_file = src_vfs.open_file(src_path)?;
+            let mut dst_file = self.create_file(dst_path)?;
+            let mut buffer = vec![0u8; self.sb.block_size as usize];
+            loop {
+                let n = src_file.read(&mut buffer)?;
+                if n == 0 {
+                    break;
+                }
+                dst_file.write_all(&buffer[..n])?;
+            }
+            dst_file.inode_id
+        };
+
+        let mut dst_inode = self.get_inode(dst_id)?;
+        dst_inode.created_at = src_inode.created_at;
+        dst_inode.modified_at = src_inode.modified_at;
+        self.save_inode(dst_id, dst_inode)
+    }
+
+    /// Data blocks referenced by more than one valid inode, each with the ids
+    /// of the inodes pointing at it. Pointer and attribute blocks count as
+    /// references too.
+    pub fn check_cross_links(&mut self) -> io::Result<Vec<(u32, Vec<u32>)>> {
+        let mut owners: HashMap<u32, Vec<u32>> = HashMap::new();
+        for inode_id in 0..self.sb.inode_count() {
+            if !self.is_inode_allocated(inode_id)? {
+                continue;
+            }
+            let inode = self.get_inode(inode_id)?;
+            if inode.is_valid != 1 {
+                continue;
+            }
+
+            let mut blocks: Vec<u32> = inode.direct_blocks.to_vec();
+            blocks.push(inode.xattr_block);
+            if inode.indirect_blocks != 0 {
+                blocks.push(inode.indirect_blocks);
+                let mut pointers = vec![0u8; self.sb.block_size as usize];
+                let mut file = self.file.borrow_mut();
+                file.seek(SeekFrom::Start(self.sb.block_offset(inode.indirect_blocks)))?;
+                file.read_exact(&mut pointers)?;
+                blocks.extend(
+                    pointers
+                        .chunks_exact(4)
+                        .map(|p| u32::from_le_bytes(p.try_into().unwrap())),
+                );
+            }
+            for block in blocks.into_iter().filter(|&id| id != 0) {
+                let list = owners.entry(block).or_default();
+                if !list.contains(&inode_id) {
+                    list.push(inode_id);
+                }
+            }
+        }
+
+        let mut shared: Vec<(u32, Vec<u32>)> = owners
+            .into_iter()
+            .filter(|(_, inodes)| inodes.len() > 1)
+            .collect();
+        shared.sort_unstable();
+        Ok(shared)
+    }
+
+    /// Checks one file without scanning the whole image: its inode must be in
+    /// use and of a known type, its size within the maximum, and every block
+    /// it points at, pointer block included, in range, marked used and not
+    /// shared within the file. Blocks past `size` are fine as long as they
+    /// run on from the end without a gap, the way `reserve` leaves them.
+    /// Fails with `InvalidData` describing the first problem found.
+    pub fn verify_file(&mut self, path: &str) -> io::Result<()> {
+        let inode_id = self.find_inode_by_path(path)?;
+        let inode = self.get_inode(inode_id)?;
+        let damaged = |what: String| {
+            Error::new(
+                io::ErrorKind::InvalidData,
+                format!("'{}' is damaged: {}!", path, what),
+            )
+        };
+        if inode.is_valid != 1 || !self.is_inode_allocated(inode_id)? {
+            return Err(damaged(format!("inode {} is not in use", inode_id)));
+        }
+        if inode.size > self.sb.max_file_size() {
+            return Err(damaged(format!(
+                "size {} exceeds the maximum of {}",
+                inode.size,
+                self.sb.max_file_size()
+            )));
+        }
+
+        let mut bitmap =
+            vec![0u8; (self.sb.inode_table_start - self.sb.data_bitmap_start) as usize];
+        {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.data_bitmap_start))?;
+            file.read_exact(&mut bitmap)?;
+        }
+        let mut seen = HashSet::new();
+        let mut check = |vfs: &Vfs, what: String, id: u32| {
+            if vfs.check_block_id(id).is_err() {
+                return Err(damaged(format!(
+                    "{} points at block {}, out of range",
+                    what, id
+                )));
+            }
+            if !bit_set(&bitmap, vfs.sb.data_block_count(), id) {
+                return Err(damaged(format!("{} points at free block {}", what, id)));
+            }
+            if !seen.insert(id) {
+                return Err(damaged(format!("{} reuses block {}", what, id)));
+            }
+            Ok(())
+        };
+
+        let mut pointers = Vec::new();
+        if inode.indirect_blocks != 0 {
+            check(self, "pointer block".to_string(), inode.indirect_blocks)?;
+            let mut buf = vec![0u8; self.sb.block_size as usize];
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start(self.sb.block_offset(inode.indirect_blocks)))?;
+            file.read_exact(&mut buf)?;
+            pointers = buf
+                .chunks_exact(4)
+                .map(|p| u32::from_le_bytes(p.try_into().unwrap()))
+                .collect();
+        }
+        let block_count = inode.size.div_ceil(self.sb.block_size as u64);
+        let mut gap = false;
+        let blocks = inode.direct_blocks.iter().chain(&pointers).enumerate();
+        for (logical, &id) in blocks {
+            if id == 0 {
+                gap |= logical as u64 >= block_count;
+                continue;
+            }
+            let what = format!("block {}", logical);
+            if gap {
+                return Err(damaged(format!(
+                    "{} lies past the end of its {} bytes, after a gap",
+                    what, inode.size
+                )));
+            }
+            check(self, what, id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the ids of directories that can reach themselves, either through
+    /// a subdirectory entry pointing back at an ancestor or through a `..`
+    /// chain that loops instead of ending at the root.
+    pub fn check_cycles(&mut self) -> io::Result<Vec<u32>> {
+        let mut cyclic = HashSet::new();
+        let mut dirs = Vec::new();
+        let mut visited = HashSet::from([0]);
+        let mut stack = vec![(0, vec![0])];
+
+        while let Some((dir_id, ancestors)) = stack.pop() {
+            dirs.push(dir_id);
+            for entry in self.dir_entries(dir_id)? {
+                let name = entry_name(&entry);
+                if name == "." || name == ".." {
+                    continue;
+                }
+                if self.get_inode(entry.inode_id)?.inode_type != InodeKind::Dir {
+                    continue;
+                }
+                if ancestors.contains(&entry.inode_id) {
+                    cyclic.insert(entry.inode_id);
+                } else if visited.insert(entry.inode_id) {
+                    let mut path = ancestors.clone();
+                    path.push(entry.inode_id);
+                    stack.push((entry.inode_id, path));
+                }
+            }
+        }
+
+        for dir_id in dirs {
+            let mut chain = vec![dir_id];
+            let mut current = dir_id;
+            while current != 0 {
+                let parent = match self.find_in_dir(current, "..") {
+                    Ok(id) => id,
+                    Err(_) => break,
+                };
+                if let Some(pos) = chain.iter().position(|&id| id == parent) {
+                    cyclic.extend(&chain[pos..]);
+                    break;
+                }
+                chain.push(parent);
+                current = parent;
+            }
+        }
+
+        let mut cyclic: Vec<u32> = cyclic.into_iter().collect();
+        cyclic.sort_unstable();
+        Ok(cyclic)
+    }
+
+    /// Entries of a directory with their type, size and timestamps, including
+    /// `.` and `..`. See [`print_long_listing`] for a table view.
+    pub fn list_long(&mut self, path: &str) -> io::Result<Vec<LongEntry>> {
+        let dir_id = self.find_inode_by_path(path)?;
+        let mut entries = Vec::new();
+        for entry in self.listed_entries(dir_id)? {
+            let inode = self.get_inode(entry.inode_id)?;
+            let metadata = Metadata::from(&inode);
+            entries.push(LongEntry {
+                name: entry_name(&entry).to_string(),
+                inode_type: inode.inode_type,
+                size: inode.size,
+                created: metadata.created(),
+                modified: metadata.modified(),
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// Prints entries returned by [`Vfs::list_long`] as a table, like `ls -l`.
+pub fn print_long_listing(entries: &[LongEntry]) {
+    println!(
+        "{:<6} {:<10} {:<20} {:<20} {:<}",
+        "Type", "Size", "Created At", "Modified At", "Name"
+    );
+    println!("{}", "-".repeat(90));
+
+    for entry in entries {
+        let created_at = DateTime::<Utc>::from(entry.created).format("%Y-%m-%d %H:%M:%S");
+        let modified_at = DateTime::<Utc>::from(entry.modified).format("%Y-%m-%d %H:%M:%S");
+        let type_str = match entry.inode_type {
+            InodeKind::File => "FILE",
+            InodeKind::Dir => "DIR",
+            InodeKind::Symlink => "LINK",
+        };
 
         println!(
             "{:<6} {:<10} {:<20} {:<20} {:<}",
-            "Type", "Size", "Created At", "Modified At", "Name"
+            type_str, entry.size, created_at, modified_at, entry.name
         );
-        println!("{}", "-".repeat(90));
+    }
+}
 
-        let max_blocks = 10 + (BLOCK_SIZE / 4) as u32;
-        for block_index in 0..max_blocks {
-            let physical_id = match self.just_read(&dir_inode, block_index)? {
-                Some(id) => id,
-                None => break,
-            };
+/// Error payload used when the inode table is full. Check for it with
+/// `err.get_ref().is_some_and(|e| e.is::<NoInodes>())`.
+#[derive(Debug)]
+pub struct NoInodes;
 
-            let block_pos = self.sb.data_blocks_start + (physical_id as u64 * BLOCK_SIZE as u64);
-            for i in 0..(BLOCK_SIZE / DIR_SIZE) {
-                let mut file = self.file.borrow_mut();
-                file.seek(SeekFrom::Start(block_pos + (i as u64 * DIR_SIZE as u64)))?;
-                let mut buf = [0u8; DIR_SIZE];
-                file.read_exact(&mut buf)?;
-                let entry = DirEntry::from_bytes(&buf);
-                drop(file);
+impl std::fmt::Display for NoInodes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("No more free inodes!")
+    }
+}
+
+impl std::error::Error for NoInodes {}
+
+/// Error payload used when a path or a walked tree goes deeper than the
+/// limit set with `Vfs::with_max_depth`.
+#[derive(Debug)]
+pub struct PathTooDeep {
+    pub max_depth: usize,
+}
 
-                if entry.is_active == 1 {
-                    let inode = self.get_inode(entry.inode_id)?;
+impl std::fmt::Display for PathTooDeep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Path is deeper than {} components!", self.max_depth)
+    }
+}
+
+impl std::error::Error for PathTooDeep {}
+
+/// Error payload used when reading or writing a data block failed at the
+/// device. The block has been recorded with `Vfs::mark_bad_block`.
+#[derive(Debug)]
+pub struct BadBlock {
+    pub block_id: u32,
+    pub source: Error,
+}
+
+impl std::fmt::Display for BadBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Block {} is bad: {}", self.block_id, self.source)
+    }
+}
+
+impl std::error::Error for BadBlock {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Frees every block `inode` holds, attribute block included, and returns
+/// how many of them were charged to quotas.
+pub(crate) fn free_file_blocks(file: &mut Disk, sb: &SuperBlock, inode: &Inode) -> io::Result<u32> {
+    let mut blocks = allocated_blocks(file, sb, inode)?;
+    let freed = blocks.len() as u32;
+    // Not part of `freed`: attribute blocks are not charged to quotas.
+    if inode.xattr_block != 0 {
+        blocks.push(inode.xattr_block);
+    }
+    file.clear_bits(sb.data_bitmap_start, &blocks)?;
+    Ok(freed)
+}
+
+/// Every data and pointer block a file holds, whatever its size says, so
+/// blocks reserved past the end count too. These are the blocks charged
+/// to quotas.
+fn allocated_blocks(file: &mut Disk, sb: &SuperBlock, inode: &Inode) -> io::Result<Vec<u32>> {
+    let mut blocks: Vec<u32> = inode
+        .direct_blocks
+        .iter()
+        .copied()
+        .filter(|&id| id != 0)
+        .collect();
+    if inode.indirect_blocks != 0 {
+        collect_pointer_tree(file, sb, inode.indirect_blocks, 1, &mut blocks)?;
+    }
+    Ok(blocks)
+}
+
+/// Adds a pointer block and everything below it to `blocks`. At `depth`
+/// 1 its pointers lead to data blocks; deeper levels lead to further
+/// pointer blocks, as a double-indirect pointer would.
+fn collect_pointer_tree(
+    file: &mut Disk,
+    sb: &SuperBlock,
+    block: u32,
+    depth: u32,
+    blocks: &mut Vec<u32>,
+) -> io::Result<()> {
+    let mut pointer_buf = vec![0u8; sb.block_size as usize];
+    file.seek(SeekFrom::Start(sb.block_offset(block)))?;
+    file.read_exact(&mut pointer_buf)?;
+
+    for chunk in pointer_buf.chunks_exact(4) {
+        let block_ptr = u32::from_le_bytes(chunk.try_into().unwrap());
+        if block_ptr == 0 {
+            continue;
+        }
+        if depth > 1 {
+            collect_pointer_tree(file, sb, block_ptr, depth - 1, blocks)?;
+        } else {
+            blocks.push(block_ptr);
+        }
+    }
+    blocks.push(block);
+    Ok(())
+}
 
-                    let created_at = DateTime::from_timestamp(inode.created_at as i64, 0)
-                        .unwrap_or_default()
-                        .with_timezone(&Utc)
-                        .format("%Y-%m-%d %H:%M:%S");
+fn lock_image(file: &File, shared: bool) -> io::Result<()> {
+    let result = if shared {
+        file.try_lock_shared()
+    } else {
+        file.try_lock()
+    };
+    result.map_err(|e| match e {
+        TryLockError::WouldBlock => Error::new(
+            io::ErrorKind::WouldBlock,
+            "Image is already in use by another process!",
+        ),
+        TryLockError::Error(e) => e,
+    })
+}
 
-                    let modified_at = DateTime::from_timestamp(inode.modified_at as i64, 0)
-                        .unwrap_or_default()
-                        .with_timezone(&Utc)
-                        .format("%Y-%m-%d %H:%M:%S");
+/// The stored name, with invalid UTF-8 replaced so it stays visible.
+fn entry_name(entry: &DirEntry) -> Cow<'_, str> {
+    String::from_utf8_lossy(entry.name_bytes())
+}
 
-                    let type_str = if inode.inode_type == 1 { "DIR" } else { "FILE" };
-                    let name = std::str::from_utf8(&entry.name)
-                        .unwrap_or("")
-                        .trim_matches('\0');
+/// Rejects names that could not be listed or typed back: control characters,
+/// including NUL, and names longer than an entry holds.
+fn validate_name(name: &str) -> io::Result<()> {
+    if name.len() > MAX_NAME_LEN {
+        return Err(Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Name '{}' is {} bytes long, at most {} fit!",
+                name,
+                name.len(),
+                MAX_NAME_LEN
+            ),
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Name {:?} contains control characters!", name),
+        ));
+    }
+    Ok(())
+}
 
-                    println!(
-                        "{:<6} {:<10} {:<20} {:<20} {:<}",
-                        type_str, inode.size, created_at, modified_at, name
-                    );
+/// Shell-style wildcard match: `*` is any run of characters, `?` any one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
                 }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Number of clear bits among the first `limit` bits of `bitmap`.
+pub(crate) fn count_clear_bits(bitmap: &[u8], limit: u32) -> u32 {
+    (0..limit.min(bitmap.len() as u32 * 8))
+        .filter(|&id| bitmap[(id / 8) as usize] & (1 << (id % 8)) == 0)
+        .count() as u32
+}
+
+/// Fails with `PermissionDenied` if `inode` is marked immutable.
+pub(crate) fn check_mutable(inode: &Inode) -> io::Result<()> {
+    if inode.is_immutable() {
+        return Err(Error::new(
+            io::ErrorKind::PermissionDenied,
+            "File is immutable, clear the flag with set_immutable first!",
+        ));
+    }
+    Ok(())
+}
+
+/// Whether bit `id` is set and lies among the first `limit` bits of `bitmap`.
+pub(crate) fn bit_set(bitmap: &[u8], limit: u32, id: u32) -> bool {
+    id < limit
+        && bitmap
+            .get((id / 8) as usize)
+            .is_some_and(|byte| byte & (1 << (id % 8)) != 0)
+}
+
+/// Number of components in `path` below the root.
+fn path_depth(path: &str) -> usize {
+    path.split('/').filter(|s| !s.is_empty()).count()
+}
+
+/// Drops `.` components and lets each `..` cancel the one before it, without
+/// climbing above the root.
+fn normalize_components<'a>(parts: impl Iterator<Item = &'a str>) -> VecDeque<String> {
+    let mut out = VecDeque::new();
+    for part in parts {
+        match part {
+            "." => {}
+            ".." => {
+                out.pop_back();
             }
+            _ => out.push_back(part.to_string()),
         }
-        Ok(())
+    }
+    out
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.ends_with('/') {
+        format!("{}{}", parent, name)
+    } else {
+        format!("{}/{}", parent, name)
     }
 }
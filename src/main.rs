@@ -1,4 +1,4 @@
-use project::Vfs;
+use project::{Vfs, print_long_listing};
 use std::io::{Read, Seek, SeekFrom, Write};
 
 fn main() -> std::io::Result<()> {
@@ -15,7 +15,7 @@ fn main() -> std::io::Result<()> {
     vfs.create_dir("/muzica")?;
 
     println!("\n=== Conținut Root (/) ===");
-    vfs.list_long("/")?;
+    print_long_listing(&vfs.list_long("/")?);
 
     let entries = vfs.read_dir("/documente")?;
     println!("\nConținut /documente: {:?}\n", entries);
@@ -36,7 +36,7 @@ fn main() -> std::io::Result<()> {
     }
 
     println!("\n=== Conținut /documente după creare fișiere ===");
-    vfs.list_long("/documente")?;
+    print_long_listing(&vfs.list_long("/documente")?);
 
     println!("\n--- 5. Testare Citire și Seek ---");
     {
@@ -68,7 +68,7 @@ fn main() -> std::io::Result<()> {
     }
 
     println!("\n=== Conținut final /documente ===");
-    vfs.list_long("/documente")?;
+    print_long_listing(&vfs.list_long("/documente")?);
     println!("\n🎉 --- Test Finalizat cu Succes! ---");
     Ok(())
 }
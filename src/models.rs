@@ -1,9 +1,26 @@
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 pub const BLOCK_SIZE: usize = 4096;
 pub const MAX_NAME_LEN: usize = 32;
 pub const KEY: u64 = u64::from_be_bytes(*b"Moisa%$!");
 pub const INODE_SIZE: usize = 80;
+/// Inode flag: the file cannot be written, truncated, removed or renamed.
+pub const INODE_IMMUTABLE: u8 = 1;
+/// Inode flag: removed with `Vfs::remove_soft` and kept, blocks and all,
+/// until `Vfs::purge_deleted`.
+pub const INODE_DELETED: u8 = 2;
+/// `DirEntry::is_active` of an entry `Vfs::remove_soft` moved to the
+/// trash. Its slot is kept for `undelete` until the inode is purged.
+pub const ENTRY_TRASHED: u8 = 2;
 pub const DIR_SIZE: usize = 40;
-pub const SUPERBLOCK_SIZE: usize = 48;
+pub const SUPERBLOCK_SIZE: usize = 72;
+pub const FORMAT_VERSION: u32 = 1;
+/// Blocks reserved for the write-ahead journal on images created with the
+/// `journal` feature.
+pub const JOURNAL_BLOCKS: u32 = 16;
+/// One inode is reserved for every this many blocks of the image.
+pub const INODES_PER_BLOCKS: u32 = 4;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -15,18 +32,42 @@ pub struct SuperBlock {
     pub data_bitmap_start: u64,
     pub inode_table_start: u64,
     pub data_blocks_start: u64,
+    pub version: u32,
+    /// Byte offset of the journal region, 0 if the image has none.
+    pub journal_start: u64,
+    pub journal_blocks: u32,
+    /// Free data blocks kept back from normal allocations.
+    pub reserved_blocks: u32,
+    /// CRC32 of the fields above as read from disk, 0 on images written
+    /// before checksums. `to_bytes` always writes a freshly computed one.
+    pub checksum: u32,
+}
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InodeKind {
+    File = 0,
+    Dir = 1,
+    Symlink = 2,
 }
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct Inode {
-    pub inode_type: u8,
+    pub inode_type: InodeKind,
     pub is_valid: u8,
     pub size: u64,
     pub created_at: u64,
     pub modified_at: u64,
     pub direct_blocks: [u32; 10],
     pub indirect_blocks: u32,
+    /// Block holding the extended attributes, 0 if there are none.
+    pub xattr_block: u32,
+    /// Bumped each time the inode slot is handed out again, so handles to
+    /// an earlier file in the slot can tell.
+    pub generation: u32,
+    /// `INODE_*` flag bits, such as [`INODE_IMMUTABLE`].
+    pub flags: u8,
 }
 
 #[repr(C)]
@@ -35,9 +76,188 @@ pub struct DirEntry {
     pub inode_id: u32,
     pub name: [u8; MAX_NAME_LEN],
     pub is_active: u8,
+    /// Bytes of `name` in use. 0 on entries written before it was stored,
+    /// whose names end at the first trailing NUL instead.
+    pub name_len: u8,
+}
+
+/// Stable public view of an inode, returned by `Vfs::metadata`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    len: u64,
+    inode_type: InodeKind,
+    created: SystemTime,
+    modified: SystemTime,
+}
+
+/// One row of `Vfs::list_long`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LongEntry {
+    pub name: String,
+    pub inode_type: InodeKind,
+    pub size: u64,
+    pub created: SystemTime,
+    pub modified: SystemTime,
+}
+
+/// Digest algorithms offered by `Vfs::hash_file`. Both are built in and meant
+/// for integrity checks and finding dedup candidates, not for security.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HashAlgo {
+    /// CRC-32 (IEEE), 4 bytes.
+    Crc32,
+    /// 64-bit FNV-1a, 8 bytes.
+    Fnv1a64,
+}
+
+/// A digest being computed, fed one chunk at a time.
+pub(crate) enum HashState {
+    Crc32(u32),
+    Fnv1a64(u64),
+}
+
+impl HashAlgo {
+    pub(crate) fn start(self) -> HashState {
+        match self {
+            HashAlgo::Crc32 => HashState::Crc32(!0),
+            HashAlgo::Fnv1a64 => HashState::Fnv1a64(0xcbf2_9ce4_8422_2325),
+        }
+    }
+}
+
+impl HashState {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            HashState::Crc32(crc) => *crc = crc32_update(*crc, data),
+            HashState::Fnv1a64(hash) => {
+                for &byte in data {
+                    *hash ^= byte as u64;
+                    *hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+                }
+            }
+        }
+    }
+
+    /// The digest, most significant byte first.
+    pub(crate) fn finish(self) -> Vec<u8> {
+        match self {
+            HashState::Crc32(crc) => (!crc).to_be_bytes().to_vec(),
+            HashState::Fnv1a64(hash) => hash.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// When file writes force data to the disk. Syncing is what makes a write
+/// survive a crash or power loss; each sync also costs a round trip to the
+/// device, which dominates bulk loads.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Never sync from file handles and leave it to the OS. Data written
+    /// shortly before a crash may be lost, along with the file's new size.
+    Never,
+    /// Sync only when a handle is flushed. Writes since the last `flush` may
+    /// be lost on a crash.
+    OnFlush,
+    /// Sync the data and the inode on every write, so a write that returned
+    /// is durable.
+    #[default]
+    EveryWrite,
+}
+
+/// Layout of a file's data blocks, returned by `Vfs::fragmentation`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FragStats {
+    pub blocks: u32,
+    pub extents: u32,
+    pub largest_gap: u32,
+}
+
+/// What `Vfs::remove_counted` released.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RemoveStats {
+    pub freed_blocks: u32,
+    pub size: u64,
+}
+
+/// Space and inode usage of an image, returned by `Vfs::statfs`. Block
+/// counts cover the data area only, without the journal.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FsStats {
+    pub block_size: u32,
+    pub total_blocks: u32,
+    pub free_blocks: u32,
+    /// Free blocks normal allocations may still take: `free_blocks` minus
+    /// the reserve.
+    pub available_blocks: u32,
+    pub reserved_blocks: u32,
+    pub total_inodes: u32,
+    pub free_inodes: u32,
+    /// Blocks recorded as bad. They are never counted as free.
+    pub bad_blocks: u32,
+    /// Allocated inodes by type, not counting soft-removed files. The root
+    /// directory counts as a directory.
+    pub file_count: u32,
+    pub dir_count: u32,
+}
+
+impl TryFrom<u8> for InodeKind {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> io::Result<Self> {
+        match value {
+            0 => Ok(InodeKind::File),
+            1 => Ok(InodeKind::Dir),
+            2 => Ok(InodeKind::Symlink),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown inode type {}!", value),
+            )),
+        }
+    }
+}
+
+impl From<InodeKind> for u8 {
+    fn from(kind: InodeKind) -> Self {
+        kind as u8
+    }
 }
 
 impl SuperBlock {
+    /// Number of blocks that fit in the data area after the metadata.
+    pub fn data_block_count(&self) -> u32 {
+        let image_size = self.total_blocks as u64 * self.block_size as u64;
+        (image_size.saturating_sub(self.data_blocks_start) / self.block_size as u64) as u32
+    }
+
+    /// Byte offset of data block `id`.
+    pub fn block_offset(&self, id: u32) -> u64 {
+        self.data_blocks_start + id as u64 * self.block_size as u64
+    }
+
+    pub fn pointers_per_block(&self) -> u32 {
+        self.block_size / 4
+    }
+
+    /// Largest number of data blocks a file can address: the direct blocks
+    /// plus one indirect block full of pointers.
+    pub fn max_file_blocks(&self) -> u32 {
+        10 + self.pointers_per_block()
+    }
+
+    /// Largest size in bytes a file can reach.
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_blocks() as u64 * self.block_size as u64
+    }
+
+    pub fn entries_per_block(&self) -> usize {
+        self.block_size as usize / DIR_SIZE
+    }
+
+    /// Number of slots in the inode table.
+    pub fn inode_count(&self) -> u32 {
+        self.total_blocks / INODES_PER_BLOCKS
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buffer = Vec::with_capacity(SUPERBLOCK_SIZE);
         buffer.extend_from_slice(&self.key.to_le_bytes());
@@ -47,9 +267,21 @@ impl SuperBlock {
         buffer.extend_from_slice(&self.data_bitmap_start.to_le_bytes());
         buffer.extend_from_slice(&self.inode_table_start.to_le_bytes());
         buffer.extend_from_slice(&self.data_blocks_start.to_le_bytes());
+        buffer.extend_from_slice(&self.version.to_le_bytes());
+        buffer.extend_from_slice(&self.journal_start.to_le_bytes());
+        buffer.extend_from_slice(&self.journal_blocks.to_le_bytes());
+        buffer.extend_from_slice(&self.reserved_blocks.to_le_bytes());
+        let checksum = crc32(&buffer);
+        buffer.extend_from_slice(&checksum.to_le_bytes());
         buffer
     }
 
+    /// CRC32 of the current field values, as `to_bytes` would store it.
+    pub fn compute_checksum(&self) -> u32 {
+        let bytes = self.to_bytes();
+        u32::from_le_bytes(bytes[SUPERBLOCK_SIZE - 4..].try_into().unwrap())
+    }
+
     pub fn from_bytes(data: &[u8]) -> Self {
         Self {
             key: u64::from_le_bytes(data[0..8].try_into().unwrap()),
@@ -59,6 +291,11 @@ impl SuperBlock {
             data_bitmap_start: u64::from_le_bytes(data[24..32].try_into().unwrap()),
             inode_table_start: u64::from_le_bytes(data[32..40].try_into().unwrap()),
             data_blocks_start: u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            version: u32::from_le_bytes(data[48..52].try_into().unwrap()),
+            journal_start: u64::from_le_bytes(data[52..60].try_into().unwrap()),
+            journal_blocks: u32::from_le_bytes(data[60..64].try_into().unwrap()),
+            reserved_blocks: u32::from_le_bytes(data[64..68].try_into().unwrap()),
+            checksum: u32::from_le_bytes(data[68..72].try_into().unwrap()),
         }
     }
 }
@@ -67,9 +304,11 @@ impl Inode {
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(INODE_SIZE);
 
-        bytes.push(self.inode_type);
+        bytes.push(self.inode_type as u8);
         bytes.push(self.is_valid);
-        bytes.extend_from_slice(&[0u8; 6]);
+        bytes.push(self.flags);
+        bytes.push(0);
+        bytes.extend_from_slice(&self.generation.to_le_bytes());
 
         bytes.extend_from_slice(&self.size.to_le_bytes());
         bytes.extend_from_slice(&self.created_at.to_le_bytes());
@@ -80,13 +319,14 @@ impl Inode {
         }
 
         bytes.extend_from_slice(&self.indirect_blocks.to_le_bytes());
-        bytes.extend_from_slice(&[0u8; 4]);
+        bytes.extend_from_slice(&self.xattr_block.to_le_bytes());
 
         bytes
     }
-    pub fn from_bytes(data: &[u8]) -> Self {
-        Self {
-            inode_type: data[0],
+    /// Fails with `InvalidData` if the type byte is not a known `InodeKind`.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            inode_type: InodeKind::try_from(data[0])?,
             is_valid: data[1],
             size: u64::from_le_bytes(data[8..16].try_into().unwrap()),
             created_at: u64::from_le_bytes(data[16..24].try_into().unwrap()),
@@ -100,7 +340,40 @@ impl Inode {
                 blocks
             },
             indirect_blocks: u32::from_le_bytes(data[72..76].try_into().unwrap()),
-        }
+            xattr_block: u32::from_le_bytes(data[76..80].try_into().unwrap()),
+            generation: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+            flags: data[2],
+        })
+    }
+
+    pub fn is_immutable(&self) -> bool {
+        self.flags & INODE_IMMUTABLE != 0
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.flags & INODE_DELETED != 0
+    }
+
+    pub fn kind(&self) -> InodeKind {
+        self.inode_type
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.inode_type == InodeKind::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.inode_type == InodeKind::File
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.is_valid != 0
+    }
+
+    /// Time since the inode was created, zero if `now` is before that.
+    pub fn age(&self, now: SystemTime) -> Duration {
+        let created = UNIX_EPOCH + Duration::from_secs(self.created_at);
+        now.duration_since(created).unwrap_or(Duration::ZERO)
     }
 }
 
@@ -110,7 +383,8 @@ impl DirEntry {
         bytes.extend_from_slice(&self.inode_id.to_le_bytes());
         bytes.extend_from_slice(&self.name);
         bytes.push(self.is_active);
-        bytes.extend_from_slice(&[0u8; 3]);
+        bytes.push(self.name_len);
+        bytes.extend_from_slice(&[0u8; 2]);
         bytes
     }
     pub fn from_bytes(data: &[u8]) -> Self {
@@ -121,6 +395,73 @@ impl DirEntry {
             inode_id: u32::from_le_bytes(data[0..4].try_into().unwrap()),
             name,
             is_active: data[36],
+            name_len: data[37],
+        }
+    }
+
+    /// The stored name, without padding.
+    pub fn name_bytes(&self) -> &[u8] {
+        if self.name_len > 0 {
+            return &self.name[..(self.name_len as usize).min(MAX_NAME_LEN)];
+        }
+        let len = self.name.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        &self.name[..len]
+    }
+}
+
+impl Metadata {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.inode_type == InodeKind::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.inode_type == InodeKind::File
+    }
+
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.modified
+    }
+}
+
+impl From<&Inode> for Metadata {
+    fn from(inode: &Inode) -> Self {
+        Self {
+            len: inode.size,
+            inode_type: inode.inode_type,
+            created: UNIX_EPOCH + Duration::from_secs(inode.created_at),
+            modified: UNIX_EPOCH + Duration::from_secs(inode.modified_at),
+        }
+    }
+}
+
+/// CRC-32 (IEEE), as used by zip and Ethernet.
+fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+
+/// Feeds `data` into a running CRC-32 register, before the final inversion.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
         }
     }
+    crc
 }
@@ -0,0 +1,205 @@
+use crate::disk::Disk;
+use crate::models::{DIR_SIZE, DirEntry, INODE_SIZE, Inode, InodeKind, SuperBlock};
+use std::collections::HashSet;
+use std::io::{self, Error, Read, Seek, SeekFrom, Write};
+
+/// Per-directory block limits live in the unused tail of block 0, after the
+/// superblock. Images created before quotas existed have zeros there, which
+/// reads as an empty table.
+const QUOTA_TABLE_START: u64 = 1024;
+const QUOTA_SLOTS: usize = 64;
+const QUOTA_ENTRY_SIZE: usize = 12;
+
+#[derive(Debug, Copy, Clone)]
+struct QuotaEntry {
+    dir_id: u32,
+    max_blocks: u32,
+    used_blocks: u32,
+}
+
+impl QuotaEntry {
+    fn to_bytes(self) -> [u8; QUOTA_ENTRY_SIZE] {
+        let mut bytes = [0u8; QUOTA_ENTRY_SIZE];
+        bytes[0..4].copy_from_slice(&self.dir_id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.max_blocks.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.used_blocks.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(data: &[u8]) -> Self {
+        Self {
+            dir_id: u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            max_blocks: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+            used_blocks: u32::from_le_bytes(data[8..12].try_into().unwrap()),
+        }
+    }
+
+    fn is_free(&self) -> bool {
+        self.max_blocks == 0
+    }
+}
+
+fn read_table(disk: &mut Disk) -> io::Result<Vec<QuotaEntry>> {
+    let mut buffer = [0u8; QUOTA_SLOTS * QUOTA_ENTRY_SIZE];
+    disk.seek(SeekFrom::Start(QUOTA_TABLE_START))?;
+    disk.read_exact(&mut buffer)?;
+    Ok(buffer
+        .chunks_exact(QUOTA_ENTRY_SIZE)
+        .map(QuotaEntry::from_bytes)
+        .collect())
+}
+
+fn write_slot(disk: &mut Disk, slot: usize, entry: QuotaEntry) -> io::Result<()> {
+    disk.seek(SeekFrom::Start(
+        QUOTA_TABLE_START + (slot * QUOTA_ENTRY_SIZE) as u64,
+    ))?;
+    disk.write_all(&entry.to_bytes())
+}
+
+/// Whether any quota is set, so callers can skip counting blocks otherwise.
+pub(crate) fn any_set(disk: &mut Disk) -> io::Result<bool> {
+    Ok(read_table(disk)?.iter().any(|e| !e.is_free()))
+}
+
+/// Sets the limit for `dir_id` with its current usage; a limit of 0 clears it.
+pub(crate) fn set(
+    disk: &mut Disk,
+    dir_id: u32,
+    max_blocks: u32,
+    used_blocks: u32,
+) -> io::Result<()> {
+    let table = read_table(disk)?;
+    let slot = table
+        .iter()
+        .position(|e| !e.is_free() && e.dir_id == dir_id)
+        .or_else(|| table.iter().position(|e| e.is_free()));
+
+    match slot {
+        Some(slot) => write_slot(
+            disk,
+            slot,
+            QuotaEntry {
+                dir_id,
+                max_blocks,
+                used_blocks,
+            },
+        ),
+        None if max_blocks == 0 => Ok(()),
+        None => Err(Error::other("Quota table is full!")),
+    }
+}
+
+/// Adds `delta` blocks to the usage of every quota covering `inode_id`. A
+/// positive delta that would push any of them past its limit changes nothing
+/// and fails with `QuotaExceeded`.
+pub(crate) fn charge(
+    disk: &mut Disk,
+    sb: &SuperBlock,
+    inode_id: u32,
+    delta: i64,
+) -> io::Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+    let table = read_table(disk)?;
+    if table.iter().all(|e| e.is_free()) {
+        return Ok(());
+    }
+
+    let ancestors = match ancestors_of(disk, sb, inode_id)? {
+        Some(ancestors) => ancestors,
+        None => return Ok(()),
+    };
+    let covering: Vec<(usize, QuotaEntry)> = table
+        .into_iter()
+        .enumerate()
+        .filter(|(_, e)| !e.is_free() && ancestors.contains(&e.dir_id))
+        .collect();
+
+    for (_, entry) in &covering {
+        if delta > 0 && entry.used_blocks as i64 + delta > entry.max_blocks as i64 {
+            return Err(Error::new(
+                io::ErrorKind::QuotaExceeded,
+                format!(
+                    "Quota of {} blocks exceeded for directory inode {}!",
+                    entry.max_blocks, entry.dir_id
+                ),
+            ));
+        }
+    }
+    for (slot, mut entry) in covering {
+        entry.used_blocks = (entry.used_blocks as i64 + delta).max(0) as u32;
+        write_slot(disk, slot, entry)?;
+    }
+    Ok(())
+}
+
+/// Directories on the path from the root to the one holding `target`, or
+/// `None` if nothing links to it.
+fn ancestors_of(disk: &mut Disk, sb: &SuperBlock, target: u32) -> io::Result<Option<Vec<u32>>> {
+    let mut visited = HashSet::from([0]);
+    let mut stack = vec![vec![0u32]];
+
+    while let Some(path) = stack.pop() {
+        let dir_id = *path.last().unwrap();
+        for entry in dir_entries(disk, sb, dir_id)? {
+            let name = entry.name_bytes();
+            if name == b"." || name == b".." {
+                continue;
+            }
+            if entry.inode_id == target {
+                return Ok(Some(path));
+            }
+            // A corrupt entry pointing past the inode table leads nowhere.
+            if entry.inode_id >= sb.inode_count() {
+                continue;
+            }
+            if read_inode(disk, sb, entry.inode_id)?.inode_type == InodeKind::Dir
+                && visited.insert(entry.inode_id)
+            {
+                let mut child = path.clone();
+                child.push(entry.inode_id);
+                stack.push(child);
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn read_inode(disk: &mut Disk, sb: &SuperBlock, id: u32) -> io::Result<Inode> {
+    let mut buffer = [0u8; INODE_SIZE];
+    disk.seek(SeekFrom::Start(
+        sb.inode_table_start + (id as u64 * INODE_SIZE as u64),
+    ))?;
+    disk.read_exact(&mut buffer)?;
+    Inode::from_bytes(&buffer)
+}
+
+fn dir_entries(disk: &mut Disk, sb: &SuperBlock, dir_id: u32) -> io::Result<Vec<DirEntry>> {
+    let inode = read_inode(disk, sb, dir_id)?;
+    let mut blocks: Vec<u32> = inode.direct_blocks.to_vec();
+    if inode.indirect_blocks != 0 {
+        let mut pointers = vec![0u8; sb.block_size as usize];
+        disk.seek(SeekFrom::Start(sb.block_offset(inode.indirect_blocks)))?;
+        disk.read_exact(&mut pointers)?;
+        blocks.extend(
+            pointers
+                .chunks_exact(4)
+                .map(|p| u32::from_le_bytes(p.try_into().unwrap())),
+        );
+    }
+
+    let mut entries = Vec::new();
+    let mut block = vec![0u8; sb.block_size as usize];
+    for id in blocks.into_iter().take_while(|&id| id != 0) {
+        disk.seek(SeekFrom::Start(sb.block_offset(id)))?;
+        disk.read_exact(&mut block)?;
+        entries.extend(
+            block
+                .chunks_exact(DIR_SIZE)
+                .map(DirEntry::from_bytes)
+                .filter(|e| e.is_active == 1),
+        );
+    }
+    Ok(entries)
+}
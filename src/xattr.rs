@@ -0,0 +1,148 @@
+use crate::Vfs;
+use std::io::{self, Error, Read, Seek, SeekFrom, Write};
+
+/// Attributes are stored in one data block per inode as a list of
+/// `name_len: u8, value_len: u16, name, value` records, ended by a zero
+/// `name_len`.
+type Attrs = Vec<(String, Vec<u8>)>;
+
+fn encode(attrs: &Attrs, block_size: usize) -> io::Result<Vec<u8>> {
+    let mut block = Vec::with_capacity(block_size);
+    for (name, value) in attrs {
+        block.push(name.len() as u8);
+        block.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        block.extend_from_slice(name.as_bytes());
+        block.extend_from_slice(value);
+    }
+    if block.len() >= block_size {
+        return Err(Error::new(
+            io::ErrorKind::StorageFull,
+            "No space left for extended attributes!",
+        ));
+    }
+    block.resize(block_size, 0);
+    Ok(block)
+}
+
+fn decode(block: &[u8]) -> Attrs {
+    let mut attrs = Vec::new();
+    let mut pos = 0;
+    while pos + 3 <= block.len() && block[pos] != 0 {
+        let name_len = block[pos] as usize;
+        let value_len = u16::from_le_bytes([block[pos + 1], block[pos + 2]]) as usize;
+        let name_start = pos + 3;
+        let value_start = name_start + name_len;
+        if value_start + value_len > block.len() {
+            break;
+        }
+        let name = String::from_utf8_lossy(&block[name_start..value_start]).into_owned();
+        attrs.push((name, block[value_start..value_start + value_len].to_vec()));
+        pos = value_start + value_len;
+    }
+    attrs
+}
+
+impl Vfs {
+    /// Sets an extended attribute, replacing any previous value. Fails with
+    /// `StorageFull` when the inode's attribute block has no room left, and
+    /// with `PermissionDenied` on immutable files.
+    pub fn set_xattr(&mut self, path: &str, name: &str, value: &[u8]) -> io::Result<()> {
+        if name.is_empty() || name.len() > u8::MAX as usize {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                "Attribute name must be 1 to 255 bytes long!",
+            ));
+        }
+        if value.len() > u16::MAX as usize {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                "Attribute value is too long!",
+            ));
+        }
+
+        let inode_id = self.find_inode_by_path(path)?;
+        crate::check_mutable(&self.get_inode(inode_id)?)?;
+        let mut attrs = self.read_xattrs(inode_id)?;
+        match attrs.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_vec(),
+            None => attrs.push((name.to_string(), value.to_vec())),
+        }
+        self.update_xattrs(inode_id, &attrs)
+    }
+
+    pub fn get_xattr(&mut self, path: &str, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let inode_id = self.find_inode_by_path(path)?;
+        Ok(self
+            .read_xattrs(inode_id)?
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v))
+    }
+
+    pub fn list_xattr(&mut self, path: &str) -> io::Result<Vec<String>> {
+        let inode_id = self.find_inode_by_path(path)?;
+        Ok(self
+            .read_xattrs(inode_id)?
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect())
+    }
+
+    pub fn remove_xattr(&mut self, path: &str, name: &str) -> io::Result<()> {
+        let inode_id = self.find_inode_by_path(path)?;
+        crate::check_mutable(&self.get_inode(inode_id)?)?;
+        let mut attrs = self.read_xattrs(inode_id)?;
+        let before = attrs.len();
+        attrs.retain(|(n, _)| n != name);
+        if attrs.len() == before {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                format!("Attribute '{}' does not exist!", name),
+            ));
+        }
+        self.update_xattrs(inode_id, &attrs)
+    }
+
+    fn read_xattrs(&mut self, inode_id: u32) -> io::Result<Attrs> {
+        let inode = self.get_inode(inode_id)?;
+        if inode.xattr_block == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut block = vec![0u8; self.sb.block_size as usize];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(self.sb.block_offset(inode.xattr_block)))?;
+        file.read_exact(&mut block)?;
+        Ok(decode(&block))
+    }
+
+    /// Runs `write_xattrs` as one transaction, so a failure or crash midway
+    /// never leaves the inode pointing at a half-written or leaked block.
+    fn update_xattrs(&mut self, inode_id: u32, attrs: &Attrs) -> io::Result<()> {
+        self.journaled(|vfs| vfs.transaction(|vfs| vfs.write_xattrs(inode_id, attrs)))
+    }
+
+    /// Rewrites the attribute block, allocating it on first use and freeing it
+    /// once the last attribute is gone.
+    fn write_xattrs(&mut self, inode_id: u32, attrs: &Attrs) -> io::Result<()> {
+        let mut inode = self.get_inode(inode_id)?;
+        if attrs.is_empty() {
+            if inode.xattr_block != 0 {
+                self.free_bit(self.sb.data_bitmap_start, inode.xattr_block)?;
+                inode.xattr_block = 0;
+                self.save_inode(inode_id, inode)?;
+            }
+            return self.file.borrow_mut().sync_all();
+        }
+
+        let block = encode(attrs, self.sb.block_size as usize)?;
+        if inode.xattr_block == 0 {
+            inode.xattr_block = self.allocate_data_block()?;
+            self.save_inode(inode_id, inode)?;
+        }
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(self.sb.block_offset(inode.xattr_block)))?;
+        file.write_all(&block)?;
+        file.sync_all()
+    }
+}
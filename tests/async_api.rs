@@ -0,0 +1,34 @@
+#![cfg(feature = "async")]
+
+use project::AsyncVfs;
+
+#[tokio::test]
+async fn test_async_create_write_read() {
+    let path = "test_async_api.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let vfs = AsyncVfs::create(path, 1024 * 1024).await.unwrap();
+        vfs.create_dir("/async").await.unwrap();
+        vfs.write_file("/async/a.txt", b"prima versiune".to_vec())
+            .await
+            .unwrap();
+        vfs.write_file("/async/a.txt", b"a doua".to_vec())
+            .await
+            .unwrap();
+
+        let entries = vfs.read_dir("/async").await.unwrap();
+        assert!(entries.contains(&"a.txt".to_string()));
+        assert_eq!(vfs.read_file("/async/a.txt").await.unwrap(), b"a doua");
+
+        vfs.remove("/async/a.txt").await.unwrap();
+        assert!(vfs.read_file("/async/a.txt").await.is_err());
+        vfs.close().await.unwrap();
+    }
+
+    let reopened = AsyncVfs::open(path).await.unwrap();
+    assert!(reopened.read_dir("/async").await.is_ok());
+    reopened.close().await.unwrap();
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,70 @@
+use project::Vfs;
+use std::io::{ErrorKind, Write};
+
+#[test]
+fn test_allocator_skips_bad_block() {
+    let path = "test_bad_blocks.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let bad;
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let free = vfs.statfs().unwrap().free_blocks;
+        bad = vfs.allocate_data_block().unwrap() + 3;
+        vfs.mark_bad_block(bad).unwrap();
+        vfs.mark_bad_block(bad).unwrap();
+        assert_eq!(vfs.bad_blocks().unwrap(), vec![bad]);
+
+        let stats = vfs.statfs().unwrap();
+        assert_eq!(stats.bad_blocks, 1);
+        assert_eq!(stats.free_blocks, free - 2);
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.bad_blocks().unwrap(), vec![bad]);
+
+    let mut f = vfs.create_file("/big.bin").unwrap();
+    f.write_all(&[1u8; 40_000]).unwrap();
+    drop(f);
+    assert!(!vfs.block_map("/big.bin").unwrap().contains(&bad));
+
+    loop {
+        match vfs.allocate_data_block() {
+            Ok(id) => assert_ne!(id, bad),
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::StorageFull);
+                break;
+            }
+        }
+    }
+    assert_eq!(vfs.statfs().unwrap().free_blocks, 0);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_bad_block_held_by_file_stays_out_after_remove() {
+    let path = "test_bad_blocks_remove.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/a.bin").unwrap();
+    f.write_all(&[2u8; 5000]).unwrap();
+    drop(f);
+    let bad = vfs.block_map("/a.bin").unwrap()[1];
+    vfs.mark_bad_block(bad).unwrap();
+    let free = vfs.statfs().unwrap().free_blocks;
+
+    vfs.remove("/a.bin").unwrap();
+    assert_eq!(vfs.statfs().unwrap().free_blocks, free + 1);
+
+    while let Ok(id) = vfs.allocate_data_block() {
+        assert_ne!(id, bad);
+    }
+    assert_eq!(
+        vfs.mark_bad_block(0).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    std::fs::remove_file(path).ok();
+}
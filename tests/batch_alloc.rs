@@ -0,0 +1,31 @@
+use project::Vfs;
+use std::collections::HashSet;
+use std::io::Write;
+
+#[test]
+fn test_batch_allocation_ids_are_distinct() {
+    let path = "test_batch_alloc.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 2 * 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/lot.bin").unwrap();
+        f.write_all(&vec![9u8; 10 * 4096]).unwrap();
+    }
+    {
+        let mut single = vfs.create_file("/singur.bin").unwrap();
+        single.write_all(b"x").unwrap();
+    }
+
+    let batch = vfs.stat("/lot.bin").unwrap();
+    let ids: HashSet<u32> = batch.direct_blocks.iter().copied().collect();
+    assert_eq!(ids.len(), 10);
+    assert!(!ids.contains(&0));
+    assert_eq!(vfs.fragmentation("/lot.bin").unwrap().extents, 1);
+
+    let single = vfs.stat("/singur.bin").unwrap();
+    assert_ne!(single.direct_blocks[0], 0);
+    assert!(!ids.contains(&single.direct_blocks[0]));
+
+    std::fs::remove_file(path).ok();
+}
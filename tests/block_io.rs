@@ -0,0 +1,63 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_raw_block_round_trip() {
+    let path = "test_block_io.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let free = vfs.statfs().unwrap().free_blocks;
+
+    let id = vfs.allocate_data_block().unwrap();
+    assert_eq!(vfs.statfs().unwrap().free_blocks, free - 1);
+
+    let pattern: Vec<u8> = (0..4096u32).map(|i| (i * 7 % 256) as u8).collect();
+    vfs.write_block(id, &pattern).unwrap();
+    let mut back = vec![0u8; 4096];
+    vfs.read_block(id, &mut back).unwrap();
+    assert_eq!(back, pattern);
+
+    let mut head = [0u8; 16];
+    vfs.read_block(id, &mut head).unwrap();
+    assert_eq!(&head[..], &pattern[..16]);
+
+    // Files never get the block while it is taken.
+    vfs.create_file("/f").unwrap();
+    vfs.write_file_atomic("/f", &[1u8; 8192]).unwrap();
+    assert!(!vfs.block_map("/f").unwrap().contains(&id));
+
+    vfs.free_data_block(id).unwrap();
+    assert_eq!(
+        vfs.free_data_block(id).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_block_access_is_bounds_checked() {
+    let path = "test_block_io_bounds.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut buf = vec![0u8; 4096];
+    for id in [0, vfs.total_blocks(), u32::MAX] {
+        assert_eq!(
+            vfs.read_block(id, &mut buf).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            vfs.write_block(id, &buf).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+    let id = vfs.allocate_data_block().unwrap();
+    assert_eq!(
+        vfs.write_block(id, &[0u8; 4097]).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,31 @@
+use project::Vfs;
+use project::models::BLOCK_SIZE;
+use std::io::{Seek, SeekFrom, Write};
+
+#[test]
+fn test_block_map_of_fresh_file() {
+    let path = "test_block_map.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/date.bin").unwrap();
+        f.write_all(&vec![9u8; 12 * BLOCK_SIZE + 100]).unwrap();
+    }
+    let map = vfs.block_map("/date.bin").unwrap();
+    assert_eq!(map.len(), 13);
+    assert!(map.iter().all(|&id| id != 0));
+    assert!(map.windows(2).all(|w| w[0] < w[1]), "{:?}", map);
+
+    {
+        let mut f = vfs.create_file("/rar.bin").unwrap();
+        f.write_all(b"a").unwrap();
+        f.seek(SeekFrom::Start(2 * BLOCK_SIZE as u64)).unwrap();
+        f.write_all(b"b").unwrap();
+    }
+    let map = vfs.block_map("/rar.bin").unwrap();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[1], 0);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,58 @@
+use project::Vfs;
+use std::io::{Read, Write};
+
+#[test]
+fn test_image_uses_stored_block_size() {
+    let path = "test_block_size.vfs";
+    let _ = std::fs::remove_file(path);
+
+    // Needs the indirect block once past 10 * 1024 bytes.
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    {
+        let mut vfs = Vfs::create_with_block_size(path, 1024 * 1024, 1024).unwrap();
+        assert_eq!(vfs.block_size(), 1024);
+        assert_eq!(vfs.total_blocks(), 1024);
+        assert_eq!(vfs.data_blocks_start() % 1024, 0);
+
+        vfs.create_dir("/dir").unwrap();
+        let mut f = vfs.create_file("/dir/small.txt").unwrap();
+        f.write_all(&[7u8; 3000]).unwrap();
+        drop(f);
+        assert_eq!(vfs.block_map("/dir/small.txt").unwrap().len(), 3);
+
+        let mut f = vfs.create_file("/big.bin").unwrap();
+        f.write_all(&data).unwrap();
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.block_size(), 1024);
+    assert!(
+        vfs.read_dir("/dir")
+            .unwrap()
+            .contains(&"small.txt".to_string())
+    );
+
+    let mut buf = Vec::new();
+    vfs.open_file("/big.bin")
+        .unwrap()
+        .read_to_end(&mut buf)
+        .unwrap();
+    assert_eq!(buf, data);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_create_rejects_invalid_block_size() {
+    let path = "test_block_size_invalid.vfs";
+    let _ = std::fs::remove_file(path);
+
+    for block_size in [0, 1000, 256, 1 << 17] {
+        let err = Vfs::create_with_block_size(path, 1024 * 1024, block_size)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    std::fs::remove_file(path).ok();
+}
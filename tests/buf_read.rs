@@ -0,0 +1,30 @@
+use project::Vfs;
+use std::io::{BufRead, Seek, SeekFrom, Write};
+
+#[test]
+fn test_lines_without_bufreader() {
+    let path = "test_buf_read.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let expected: Vec<String> = (0..500).map(|i| format!("linia numarul {}", i)).collect();
+    {
+        let mut f = vfs.create_file("/text.txt").unwrap();
+        f.write_all(expected.join("\n").as_bytes()).unwrap();
+    }
+
+    let f = vfs.open_file("/text.txt").unwrap();
+    let lines: Vec<String> = f.lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines, expected);
+
+    let mut f = vfs.open_file("/text.txt").unwrap();
+    let mut first = String::new();
+    f.read_line(&mut first).unwrap();
+    assert_eq!(first, "linia numarul 0\n");
+    f.seek(SeekFrom::Start(0)).unwrap();
+    let mut again = String::new();
+    f.read_line(&mut again).unwrap();
+    assert_eq!(again, first);
+
+    std::fs::remove_file(path).ok();
+}
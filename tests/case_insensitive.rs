@@ -0,0 +1,59 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_lookup_ignores_case() {
+    let path = "test_case_lookup.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024)
+        .unwrap()
+        .with_case_insensitive(true);
+    vfs.create_dir("/Docs").unwrap();
+    {
+        let mut f = vfs.create_file("/Docs/readme.txt").unwrap();
+        f.write_all(b"citeste-ma").unwrap();
+    }
+
+    let mut content = String::new();
+    vfs.open_file("/DOCS/README.TXT")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "citeste-ma");
+    assert!(
+        vfs.read_dir("/docs")
+            .unwrap()
+            .contains(&"readme.txt".to_string())
+    );
+
+    vfs.remove("/docs/ReadMe.txt").unwrap();
+    assert!(vfs.open_file("/Docs/readme.txt").is_err());
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_create_rejects_case_only_difference() {
+    let path = "test_case_collision.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024)
+        .unwrap()
+        .with_case_insensitive(true);
+    vfs.create_file("/readme.txt").unwrap();
+    assert_eq!(
+        vfs.create_file("/README.TXT").err().unwrap().kind(),
+        ErrorKind::AlreadyExists
+    );
+    assert_eq!(
+        vfs.create_dir("/ReadMe.Txt").unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+
+    let mut vfs = vfs.with_case_insensitive(false);
+    assert!(vfs.open_file("/README.TXT").is_err());
+    vfs.create_file("/README.TXT").unwrap();
+
+    std::fs::remove_file(path).ok();
+}
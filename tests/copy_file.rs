@@ -0,0 +1,43 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_failed_copy_leaves_no_partial_file() {
+    let path = "test_copy_file.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let free = vfs.statfs().unwrap().free_blocks as usize;
+    let bs = vfs.block_size() as usize;
+    let data = vec![5u8; (free * 2 / 3) * bs];
+    vfs.create_file("/big.bin")
+        .unwrap()
+        .write_all(&data)
+        .unwrap();
+    let before = vfs.statfs().unwrap();
+
+    let err = vfs.copy("/big.bin", "/copy.bin").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::StorageFull);
+    assert_eq!(
+        vfs.open_file("/copy.bin").err().unwrap().kind(),
+        ErrorKind::NotFound
+    );
+    let after = vfs.statfs().unwrap();
+    assert_eq!(after.free_blocks, before.free_blocks);
+    assert_eq!(after.free_inodes, before.free_inodes);
+
+    // The source is untouched and a copy that fits still works.
+    vfs.create_file("/small.txt")
+        .unwrap()
+        .write_all(b"mic")
+        .unwrap();
+    vfs.copy("/small.txt", "/copy.txt").unwrap();
+    let mut text = String::new();
+    vfs.open_file("/copy.txt")
+        .unwrap()
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(text, "mic");
+
+    std::fs::remove_file(path).ok();
+}
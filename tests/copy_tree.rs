@@ -0,0 +1,70 @@
+use project::Vfs;
+use std::io::{Read, Write};
+
+#[test]
+fn test_copy_tree_between_images() {
+    let src_path = "test_copy_tree_src.vfs";
+    let dst_path = "test_copy_tree_dst.vfs";
+    let _ = std::fs::remove_file(src_path);
+    let _ = std::fs::remove_file(dst_path);
+
+    let mut src = Vfs::create(src_path, 2 * 1024 * 1024).unwrap();
+    src.create_dir("/proiect").unwrap();
+    src.create_dir("/proiect/src").unwrap();
+    {
+        let mut f = src.create_file("/proiect/README").unwrap();
+        f.write_all(b"citeste-ma").unwrap();
+        let mut f = src.create_file("/proiect/src/main.rs").unwrap();
+        f.write_all(&vec![b'x'; 10_000]).unwrap();
+    }
+    let readme_id = src.find_inode_by_path("/proiect/README").unwrap();
+    let mut inode = src.get_inode(readme_id).unwrap();
+    inode.created_at = 1_000;
+    inode.modified_at = 2_000;
+    src.save_inode(readme_id, inode).unwrap();
+
+    let mut dst = Vfs::create(dst_path, 2 * 1024 * 1024).unwrap();
+    dst.create_dir("/backup").unwrap();
+    dst.copy_tree_from(&mut src, "/proiect", "/backup/proiect")
+        .unwrap();
+
+    let mut names = dst.read_dir("/backup/proiect").unwrap();
+    names.sort();
+    assert_eq!(names, vec![".", "..", "README", "src"]);
+
+    let mut content = Vec::new();
+    dst.open_file("/backup/proiect/src/main.rs")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content, vec![b'x'; 10_000]);
+
+    let copied = dst.stat("/backup/proiect/README").unwrap();
+    assert_eq!(copied.size, 10);
+    assert_eq!(copied.created_at, 1_000);
+    assert_eq!(copied.modified_at, 2_000);
+
+    std::fs::remove_file(src_path).ok();
+    std::fs::remove_file(dst_path).ok();
+}
+
+#[test]
+fn test_copy_tree_reports_full_destination() {
+    let src_path = "test_copy_tree_full_src.vfs";
+    let dst_path = "test_copy_tree_full_dst.vfs";
+    let _ = std::fs::remove_file(src_path);
+    let _ = std::fs::remove_file(dst_path);
+
+    let mut src = Vfs::create(src_path, 4 * 1024 * 1024).unwrap();
+    src.create_dir("/mare").unwrap();
+    {
+        let mut f = src.create_file("/mare/blob.bin").unwrap();
+        f.write_all(&vec![1u8; 2 * 1024 * 1024]).unwrap();
+    }
+
+    let mut dst = Vfs::create(dst_path, 256 * 1024).unwrap();
+    assert!(dst.copy_tree_from(&mut src, "/mare", "/mare").is_err());
+
+    std::fs::remove_file(src_path).ok();
+    std::fs::remove_file(dst_path).ok();
+}
@@ -0,0 +1,85 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_create_file_over_directory_fails() {
+    let path = "test_create_collision_file.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/documente").unwrap();
+    vfs.create_file("/documente/a.txt").unwrap();
+    let free = vfs.statfs().unwrap();
+
+    let err = vfs.create_file("/documente").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert!(vfs.stat("/documente").unwrap().inode_type == project::models::InodeKind::Dir);
+    assert_eq!(
+        vfs.read_dir("/documente").unwrap(),
+        vec![".", "..", "a.txt"]
+    );
+    // Nothing was allocated for the rejected file.
+    assert_eq!(vfs.statfs().unwrap(), free);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_create_over_existing_file_fails() {
+    let path = "test_create_collision_dir.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/notes")
+        .unwrap()
+        .write_all(b"keep me")
+        .unwrap();
+
+    assert_eq!(
+        vfs.create_dir("/notes").unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+    assert_eq!(
+        vfs.create_file("/notes").err().unwrap().kind(),
+        ErrorKind::AlreadyExists
+    );
+    let names = vfs.read_dir("/").unwrap();
+    assert_eq!(names.iter().filter(|n| *n == "notes").count(), 1);
+
+    let mut text = String::new();
+    vfs.open_file("/notes")
+        .unwrap()
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(text, "keep me");
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_names_longer_than_an_entry_are_rejected() {
+    let path = "test_create_collision_long.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let base = "x".repeat(32);
+    vfs.create_file(&format!("/{}", base)).unwrap();
+    for name in [format!("{}A", base), format!("{}B", base)] {
+        let long = format!("/{}", name);
+        assert_eq!(
+            vfs.create_file(&long).err().unwrap().kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            vfs.create_dir(&long).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            vfs.rename(&format!("/{}", base), &long).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", "..", base.as_str()]);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,45 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_create_dirs_mixes_new_and_existing() {
+    let path = "test_create_dirs.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/etc").unwrap();
+    vfs.create_file("/etc/hosts").unwrap();
+
+    vfs.create_dirs(["/etc", "/etc/app/conf.d", "/var/log/", "/var//lib"])
+        .unwrap();
+    // Running it again changes nothing.
+    vfs.create_dirs(vec![String::from("/etc/app"), String::from("/var/log")])
+        .unwrap();
+
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", "..", "etc", "var"]);
+    assert_eq!(
+        vfs.read_dir("/etc").unwrap(),
+        vec![".", "..", "hosts", "app"]
+    );
+    assert_eq!(vfs.read_dir("/etc/app").unwrap(), vec![".", "..", "conf.d"]);
+    assert_eq!(vfs.read_dir("/var").unwrap(), vec![".", "..", "log", "lib"]);
+    assert!(vfs.open_file("/etc/app/conf.d").unwrap().is_dir().unwrap());
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_create_dirs_stops_at_first_failure() {
+    let path = "test_create_dirs_fail.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/file").unwrap();
+
+    let err = vfs.create_dirs(["/a", "/file/sub", "/b"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotADirectory);
+    assert!(err.to_string().contains("'/file/sub'"), "{}", err);
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", "..", "file", "a"]);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,31 @@
+use project::Vfs;
+
+#[test]
+fn test_created_id_matches_path_resolution() {
+    let path = "test_create_file_id.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let (id, other);
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/dir").unwrap();
+        let (new_id, file) = vfs.create_file_with_id("/dir/a.txt").unwrap();
+        id = new_id;
+        assert_eq!(file.inode_id, id);
+        drop(file);
+        assert_eq!(vfs.find_inode_by_path("/dir/a.txt").unwrap(), id);
+
+        other = vfs.create_file("/b.txt").unwrap().inode_id;
+        assert_ne!(other, id);
+        assert_eq!(vfs.find_inode_by_path("/b.txt").unwrap(), other);
+
+        vfs.rename("/dir/a.txt", "/moved.txt").unwrap();
+        assert_eq!(vfs.find_inode_by_path("/moved.txt").unwrap(), id);
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.find_inode_by_path("/moved.txt").unwrap(), id);
+    assert_eq!(vfs.open_file("/b.txt").unwrap().inode_id, other);
+
+    std::fs::remove_file(path).ok();
+}
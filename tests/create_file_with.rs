@@ -0,0 +1,44 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+#[test]
+fn test_create_file_with_initial_contents() {
+    let path = "test_create_file_with.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let data: Vec<u8> = (0..8192u32).map(|i| (i % 251) as u8).collect();
+    let mut f = vfs.create_file_with("/init.bin", &data).unwrap();
+    assert_eq!(f.position(), 8192);
+    assert_eq!(f.len().unwrap(), 8192);
+
+    f.seek(SeekFrom::Start(0)).unwrap();
+    let mut back = Vec::new();
+    f.read_to_end(&mut back).unwrap();
+    assert_eq!(back, data);
+    drop(f);
+
+    let err = vfs.create_file_with("/init.bin", b"again").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_create_file_with_cleans_up_when_out_of_space() {
+    let path = "test_create_file_with_full.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 256 * 1024).unwrap();
+    let before = vfs.statfs().unwrap();
+
+    let too_big = vec![1u8; 512 * 1024];
+    assert!(vfs.create_file_with("/big.bin", &too_big).is_err());
+    assert!(!vfs.read_dir("/").unwrap().contains(&"big.bin".to_string()));
+
+    let after = vfs.statfs().unwrap();
+    assert_eq!(after.free_blocks, before.free_blocks);
+    assert_eq!(after.free_inodes, before.free_inodes);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,33 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_create_new_refuses_existing_image() {
+    let path = "test_create_new.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create_new(path, 1024 * 1024).unwrap();
+        let mut f = vfs.create_file("/keep.txt").unwrap();
+        f.write_all(b"important").unwrap();
+    }
+    let before = std::fs::read(path).unwrap();
+
+    let err = Vfs::create_new(path, 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read(path).unwrap(), before);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let mut buf = String::new();
+    vfs.open_file("/keep.txt")
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "important");
+    drop(vfs);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", ".."]);
+
+    std::fs::remove_file(path).ok();
+}
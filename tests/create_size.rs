@@ -0,0 +1,41 @@
+use project::Vfs;
+use project::models::BLOCK_SIZE;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_create_rejects_undersized_image() {
+    let path = "test_create_small.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let err = Vfs::create(path, 4096).err().expect("4 KiB is too small");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("too small"), "{}", err);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_create_floors_unaligned_size() {
+    let path = "test_create_unaligned.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024 + 1000).unwrap();
+    assert_eq!(vfs.total_blocks(), 256);
+    assert_eq!(
+        std::fs::metadata(path).unwrap().len(),
+        256 * BLOCK_SIZE as u64
+    );
+
+    {
+        let mut f = vfs.create_file("/a.txt").unwrap();
+        f.write_all(b"aliniat").unwrap();
+    }
+    let mut content = String::new();
+    vfs.open_file("/a.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "aliniat");
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,40 @@
+use project::Vfs;
+use project::models::INODE_SIZE;
+use std::io::Write;
+
+#[test]
+fn test_shared_data_block_is_reported() {
+    let path = "test_cross_links.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let (table_start, shared_block) = {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let mut a = vfs.create_file("/a.txt").unwrap();
+        a.write_all(b"first").unwrap();
+        drop(a);
+        let mut b = vfs.create_file("/b.txt").unwrap();
+        b.write_all(b"second").unwrap();
+        drop(b);
+
+        assert!(vfs.check_cross_links().unwrap().is_empty());
+        (
+            vfs.inode_table_start(),
+            vfs.stat("/a.txt").unwrap().direct_blocks[0],
+        )
+    };
+
+    // Point b.txt (inode 2) at a.txt's first block.
+    let mut image = std::fs::read(path).unwrap();
+    let pos = table_start as usize + 2 * INODE_SIZE + 32;
+    image[pos..pos + 4].copy_from_slice(&shared_block.to_le_bytes());
+    std::fs::write(path, image).unwrap();
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.stat("/b.txt").unwrap().direct_blocks[0], shared_block);
+    assert_eq!(
+        vfs.check_cross_links().unwrap(),
+        vec![(shared_block, vec![1, 2])]
+    );
+
+    std::fs::remove_file(path).ok();
+}
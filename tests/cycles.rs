@@ -0,0 +1,35 @@
+use project::Vfs;
+use project::models::BLOCK_SIZE;
+use std::io::{Seek, SeekFrom, Write};
+
+#[test]
+fn test_check_cycles_reports_looping_parent_chain() {
+    let path = "test_cycles.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let (a_id, b_id, dotdot_pos);
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/a").unwrap();
+        vfs.create_dir("/a/b").unwrap();
+        assert!(vfs.check_cycles().unwrap().is_empty());
+
+        a_id = vfs.find_inode_by_path("/a").unwrap();
+        b_id = vfs.find_inode_by_path("/a/b").unwrap();
+        let a_block = vfs.stat("/a").unwrap().direct_blocks[0];
+        // `..` is the second entry of a fresh directory.
+        dotdot_pos = vfs.data_blocks_start() + a_block as u64 * BLOCK_SIZE as u64 + 40;
+    }
+    {
+        let mut raw = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        raw.seek(SeekFrom::Start(dotdot_pos)).unwrap();
+        raw.write_all(&b_id.to_le_bytes()).unwrap();
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let cyclic = vfs.check_cycles().unwrap();
+    assert!(cyclic.contains(&a_id));
+    assert!(cyclic.contains(&b_id));
+
+    std::fs::remove_file(path).ok();
+}
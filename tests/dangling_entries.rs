@@ -0,0 +1,69 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+fn point_entry_at(path: &str, name: &[u8], inode_id: u32) {
+    let mut image = std::fs::read(path).unwrap();
+    let pos = image.windows(name.len()).position(|w| w == name).unwrap();
+    image[pos - 4..pos].copy_from_slice(&inode_id.to_le_bytes());
+    std::fs::write(path, image).unwrap();
+}
+
+#[test]
+fn test_listings_skip_entries_with_unallocated_inodes() {
+    let path = "test_dangling_entries.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/keep.txt").unwrap();
+        vfs.create_file("/ghost.txt").unwrap();
+        vfs.create_file("/far.txt").unwrap();
+    }
+    // A free inode slot, and one past the end of the inode table.
+    point_entry_at(path, b"ghost.txt", 50);
+    point_entry_at(path, b"far.txt", 60_000);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", "..", "keep.txt"]);
+    assert_eq!(vfs.read_dir_lossy("/").unwrap().len(), 3);
+    assert_eq!(vfs.list_long("/").unwrap().len(), 3);
+    let lazy: Vec<String> = vfs.entries("/").unwrap().map(Result::unwrap).collect();
+    assert_eq!(lazy, vec![".", "..", "keep.txt"]);
+
+    assert_eq!(
+        vfs.open_file("/ghost.txt").err().unwrap().kind(),
+        ErrorKind::NotFound
+    );
+    // The raw view still shows the slots for repair tools.
+    assert_eq!(
+        vfs.read_dir_raw("/")
+            .unwrap()
+            .iter()
+            .filter(|e| e.is_active == 1)
+            .count(),
+        5
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_entry_count_skips_entries_with_unallocated_inodes() {
+    let path = "test_dangling_count.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/docs").unwrap();
+        vfs.create_file("/docs/ghost.txt").unwrap();
+        vfs.create_file("/keep.txt").unwrap();
+    }
+    point_entry_at(path, b"ghost.txt", 50);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.entry_count("/docs").unwrap(), 0);
+    assert!(vfs.is_empty_dir("/docs").unwrap());
+    assert_eq!(vfs.entry_count("/").unwrap(), 2);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,37 @@
+use project::Vfs;
+use std::io::{Read, Write};
+
+#[test]
+fn test_defragment_restores_single_extent() {
+    let path = "test_defragment.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    let mut a = vfs.create_file("/a.bin").unwrap();
+    let mut b = vfs.create_file("/b.bin").unwrap();
+    let mut expected = Vec::new();
+    for i in 0..14u8 {
+        let chunk = vec![i; 4096];
+        a.write_all(&chunk).unwrap();
+        b.write_all(&[0xEE; 4096]).unwrap();
+        expected.extend_from_slice(&chunk);
+    }
+    a.write_all(b"tail").unwrap();
+    expected.extend_from_slice(b"tail");
+    assert!(vfs.fragmentation("/a.bin").unwrap().extents > 1);
+
+    vfs.defragment_file("/a.bin").unwrap();
+
+    let stats = vfs.fragmentation("/a.bin").unwrap();
+    assert_eq!(stats.extents, 1);
+    assert_eq!(stats.blocks, 15);
+
+    let mut content = Vec::new();
+    vfs.open_file("/a.bin")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content, expected);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,37 @@
+use project::Vfs;
+
+#[test]
+fn test_many_inserts_fill_slots_in_order() {
+    let path = "test_dir_insert.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    vfs.create_dir("/d").unwrap();
+    // Spans three 4096-byte directory blocks.
+    for i in 0..250 {
+        vfs.create_file(&format!("/d/f{:03}", i)).unwrap();
+    }
+
+    let mut expected: Vec<String> = vec![".".into(), "..".into()];
+    expected.extend((0..250).map(|i| format!("f{:03}", i)));
+    assert_eq!(vfs.read_dir("/d").unwrap(), expected);
+    let size = vfs.stat("/d").unwrap().size;
+    // 102 entries per block; the 16-byte tail of each block stays unused.
+    assert_eq!(size, 2 * 4096 + 48 * 40);
+
+    // Freed slots are reused first, in the block they live in.
+    vfs.remove("/d/f010").unwrap();
+    vfs.remove("/d/f150").unwrap();
+    vfs.create_file("/d/new_a").unwrap();
+    vfs.create_file("/d/new_b").unwrap();
+    expected[12] = "new_a".into();
+    expected[152] = "new_b".into();
+    assert_eq!(vfs.read_dir("/d").unwrap(), expected);
+    assert_eq!(vfs.stat("/d").unwrap().size, size);
+
+    vfs.create_file("/d/tail").unwrap();
+    assert_eq!(vfs.read_dir("/d").unwrap().last().unwrap(), "tail");
+    assert_eq!(vfs.stat("/d").unwrap().size, size + 40);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,30 @@
+use project::Vfs;
+
+#[test]
+fn test_dir_size_shrinks_on_removal() {
+    let path = "test_dir_size.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/d").unwrap();
+    let initial = vfs.stat("/d").unwrap().size;
+
+    for i in 0..5 {
+        vfs.create_file(&format!("/d/f{}", i)).unwrap();
+    }
+    let full = vfs.stat("/d").unwrap().size;
+    assert!(full > initial);
+
+    // A hole in the middle keeps the size; removing the tail shrinks it.
+    vfs.remove("/d/f2").unwrap();
+    assert_eq!(vfs.stat("/d").unwrap().size, full);
+    vfs.remove("/d/f4").unwrap();
+    vfs.remove("/d/f3").unwrap();
+    assert!(vfs.stat("/d").unwrap().size < full);
+
+    vfs.remove("/d/f1").unwrap();
+    vfs.remove("/d/f0").unwrap();
+    assert_eq!(vfs.stat("/d").unwrap().size, initial);
+
+    std::fs::remove_file(path).ok();
+}
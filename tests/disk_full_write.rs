@@ -0,0 +1,42 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_reported_bytes_are_what_landed() {
+    let path = "test_disk_full_write.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/data.bin").unwrap();
+    // Room for the ten direct blocks and the pointer block, but not for the
+    // first block behind it.
+    while vfs.statfs().unwrap().free_blocks > 11 {
+        vfs.allocate_data_block().unwrap();
+    }
+
+    let data: Vec<u8> = (0..60_000u32).map(|i| (i % 251) as u8).collect();
+    let mut written = 0;
+    let err = loop {
+        match f.write(&data[written..]) {
+            Ok(n) => written += n,
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(err.kind(), ErrorKind::StorageFull);
+    assert_eq!(written, 10 * 4096);
+    assert_eq!(f.len().unwrap(), written as u64);
+    drop(f);
+
+    // The pointer block taken for the failed write was given back.
+    assert_eq!(vfs.statfs().unwrap().free_blocks, 1);
+    assert_eq!(vfs.block_map("/data.bin").unwrap().len(), 10);
+
+    let mut back = Vec::new();
+    vfs.open_file("/data.bin")
+        .unwrap()
+        .read_to_end(&mut back)
+        .unwrap();
+    assert_eq!(back, &data[..written]);
+
+    std::fs::remove_file(path).ok();
+}
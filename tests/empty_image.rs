@@ -0,0 +1,18 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_open_rejects_too_small_file() {
+    let path = "test_empty_image.vfs";
+    std::fs::write(path, b"").unwrap();
+
+    let err = Vfs::open(path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("file too small"), "{}", err);
+
+    std::fs::write(path, [0u8; 16]).unwrap();
+    let err = Vfs::open(path).err().unwrap();
+    assert!(err.to_string().contains("file too small"), "{}", err);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,57 @@
+use project::Vfs;
+
+#[test]
+fn test_entries_reads_only_what_is_needed() {
+    let path = "test_entries_iter.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    vfs.create_dir("/big").unwrap();
+    for i in 0..250 {
+        vfs.create_file(&format!("/big/f{}", i)).unwrap();
+    }
+
+    let before = vfs.io_calls();
+    let first = vfs.entries("/big").unwrap().next().unwrap().unwrap();
+    let lazy_calls = vfs.io_calls() - before;
+    assert_eq!(first, ".");
+
+    let before = vfs.io_calls();
+    let all = vfs.read_dir("/big").unwrap();
+    let full_calls = vfs.io_calls() - before;
+    assert!(lazy_calls < full_calls, "{} vs {}", lazy_calls, full_calls);
+
+    let lazy: Vec<String> = vfs
+        .entries("/big")
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(lazy, all);
+    assert_eq!(lazy.len(), 252);
+
+    let found = vfs
+        .entries("/big")
+        .unwrap()
+        .find(|name| name.as_ref().map_or(true, |n| n == "f3"));
+    assert_eq!(found.unwrap().unwrap(), "f3");
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_entries_skips_removed_and_rejects_files() {
+    let path = "test_entries_iter_removed.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/a").unwrap();
+    vfs.create_file("/b").unwrap();
+    vfs.remove("/a").unwrap();
+
+    let names: Vec<String> = vfs.entries("/").unwrap().map(Result::unwrap).collect();
+    assert_eq!(names, vec![".", "..", "b"]);
+    assert!(vfs.entries("/b").is_err());
+    assert!(vfs.entries("/missing").is_err());
+
+    std::fs::remove_file(path).ok();
+}
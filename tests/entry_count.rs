@@ -0,0 +1,34 @@
+use project::Vfs;
+
+#[test]
+fn test_entry_count_empty_dir() {
+    let path = "test_entry_count_empty.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/gol").unwrap();
+    assert_eq!(vfs.entry_count("/gol").unwrap(), 0);
+    assert!(vfs.is_empty_dir("/gol").unwrap());
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_entry_count_with_children() {
+    let path = "test_entry_count_children.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/plin").unwrap();
+    vfs.create_file("/plin/a.txt").unwrap();
+    vfs.create_file("/plin/b.txt").unwrap();
+    vfs.create_dir("/plin/sub").unwrap();
+    assert_eq!(vfs.entry_count("/plin").unwrap(), 3);
+    assert!(!vfs.is_empty_dir("/plin").unwrap());
+
+    vfs.remove("/plin/a.txt").unwrap();
+    assert_eq!(vfs.entry_count("/plin").unwrap(), 2);
+    assert!(vfs.entry_count("/plin/b.txt").is_err());
+
+    std::fs::remove_file(path).ok();
+}
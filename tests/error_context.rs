@@ -0,0 +1,31 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_not_found_names_path_and_component() {
+    let path = "test_error_context.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+    vfs.create_dir("/a/b").unwrap();
+
+    let err = vfs.find_inode_by_path("/a/b/x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert_eq!(
+        err.to_string(),
+        "Path '/a/b/x' not found: component 'x' missing in '/a/b'!"
+    );
+
+    let err = vfs.open_file("/a/missing/x").err().unwrap();
+    assert!(err.to_string().contains("'/a/missing/x'"), "{}", err);
+    assert!(
+        err.to_string()
+            .contains("component 'missing' missing in '/a'")
+    );
+
+    let err = vfs.read_dir("/nope").unwrap_err();
+    assert!(err.to_string().contains("missing in '/'"), "{}", err);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,64 @@
+use project::Vfs;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_image_inside_container_file() {
+    let path = "test_file_handle.bin";
+    let _ = std::fs::remove_file(path);
+
+    let header = b"CONTAINER HEADER v1\n".repeat(40);
+    let base = header.len() as u64;
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        file.write_all(&header).unwrap();
+
+        let mut vfs = Vfs::create_in_handle(file, 1024 * 1024).unwrap();
+        vfs.create_dir("/docs").unwrap();
+        vfs.create_file("/docs/data.bin")
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+    }
+
+    let raw = std::fs::read(path).unwrap();
+    assert_eq!(&raw[..header.len()], &header[..]);
+    assert_eq!(raw.len() as u64, base + 1024 * 1024);
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    file.seek(SeekFrom::Start(base)).unwrap();
+    let mut vfs = Vfs::open_file_handle(file).unwrap();
+    assert!(
+        vfs.read_dir("/docs")
+            .unwrap()
+            .contains(&"data.bin".to_string())
+    );
+    let mut back = Vec::new();
+    vfs.open_file("/docs/data.bin")
+        .unwrap()
+        .read_to_end(&mut back)
+        .unwrap();
+    assert_eq!(back, data);
+    drop(vfs);
+
+    // Opened at the wrong offset, the container is not an image.
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    assert!(Vfs::open_file_handle(file).is_err());
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,30 @@
+use project::Vfs;
+use project::models::InodeKind;
+use std::io::Write;
+
+#[test]
+fn test_handle_reports_len_and_kind() {
+    let path = "test_file_len.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+
+    let mut f = vfs.create_file("/docs/a.txt").unwrap();
+    assert_eq!(f.len().unwrap(), 0);
+    assert!(f.is_empty().unwrap());
+    f.write_all(&[1u8; 5000]).unwrap();
+    assert_eq!(f.len().unwrap(), 5000);
+    assert!(!f.is_dir().unwrap());
+    assert_eq!(f.inode_kind().unwrap(), InodeKind::File);
+    drop(f);
+
+    let f = vfs.open_file("/docs/a.txt").unwrap();
+    assert_eq!(f.len().unwrap(), 5000);
+
+    let dir = vfs.open_file("/docs").unwrap();
+    assert!(dir.is_dir().unwrap());
+    assert_eq!(dir.inode_kind().unwrap(), InodeKind::Dir);
+
+    std::fs::remove_file(path).ok();
+}
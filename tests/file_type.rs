@@ -0,0 +1,32 @@
+use project::Vfs;
+use project::models::InodeKind;
+use std::io::ErrorKind;
+
+#[test]
+fn test_file_type_does_not_follow_trailing_symlink() {
+    let path = "test_file_type.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+    vfs.create_file("/docs/a.txt").unwrap();
+    vfs.symlink("/docs/a.txt", "/file_link").unwrap();
+    vfs.symlink("docs", "/dir_link").unwrap();
+
+    assert_eq!(vfs.file_type("/").unwrap(), InodeKind::Dir);
+    assert_eq!(vfs.file_type("/docs").unwrap(), InodeKind::Dir);
+    assert_eq!(vfs.file_type("/docs/a.txt").unwrap(), InodeKind::File);
+
+    assert_eq!(vfs.file_type("/file_link").unwrap(), InodeKind::Symlink);
+    assert!(vfs.metadata("/file_link").unwrap().is_file());
+    assert_eq!(vfs.file_type("/dir_link").unwrap(), InodeKind::Symlink);
+    assert!(vfs.metadata("/dir_link").unwrap().is_dir());
+
+    // Links before the last component are still followed.
+    assert_eq!(vfs.file_type("/dir_link/a.txt").unwrap(), InodeKind::File);
+
+    let err = vfs.file_type("/missing").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,38 @@
+use project::Vfs;
+use project::models::InodeKind;
+use std::io::Write;
+
+#[test]
+fn test_find_directories_and_large_files() {
+    let path = "test_find.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+    vfs.create_dir("/a/b").unwrap();
+    vfs.create_dir("/a/b/c").unwrap();
+    vfs.create_dir("/d").unwrap();
+    vfs.create_file("/a/small.txt").unwrap();
+    {
+        let mut f = vfs.create_file("/a/b/big.bin").unwrap();
+        f.write_all(&[0u8; 10_000]).unwrap();
+    }
+
+    let mut dirs = vfs
+        .find("/", |_, inode| inode.inode_type == InodeKind::Dir)
+        .unwrap();
+    dirs.sort();
+    assert_eq!(dirs, vec!["/a", "/a/b", "/a/b/c", "/d"]);
+
+    let big = vfs
+        .find("/a", |_, inode| {
+            inode.inode_type == InodeKind::File && inode.size > 4096
+        })
+        .unwrap();
+    assert_eq!(big, vec!["/a/b/big.bin"]);
+
+    let by_name = vfs.find("/", |p, _| p.ends_with(".txt")).unwrap();
+    assert_eq!(by_name, vec!["/a/small.txt"]);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,24 @@
+use project::Vfs;
+use std::io::Write;
+
+#[test]
+fn test_interleaved_writes_fragment_a_file() {
+    let path = "test_fragmentation.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 2 * 1024 * 1024).unwrap();
+    let mut a = vfs.create_file("/a.bin").unwrap();
+    let mut b = vfs.create_file("/b.bin").unwrap();
+    let block = vec![7u8; 4096];
+    for _ in 0..4 {
+        a.write_all(&block).unwrap();
+        b.write_all(&block).unwrap();
+    }
+
+    let stats = vfs.fragmentation("/a.bin").unwrap();
+    assert_eq!(stats.blocks, 4);
+    assert!(stats.extents > 1, "{:?}", stats);
+    assert!(stats.largest_gap >= 1, "{:?}", stats);
+
+    std::fs::remove_file(path).ok();
+}
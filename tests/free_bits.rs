@@ -0,0 +1,75 @@
+use project::Vfs;
+use std::io::Write;
+
+fn data_bitmap(path: &str, start: u64, end: u64) -> Vec<u8> {
+    std::fs::read(path).unwrap()[start as usize..end as usize].to_vec()
+}
+
+fn is_set(bitmap: &[u8], id: u32) -> bool {
+    bitmap[(id / 8) as usize] & (1 << (id % 8)) != 0
+}
+
+#[test]
+fn test_removing_large_file_frees_exactly_its_blocks() {
+    let path = "test_free_bits.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let sb = {
+        let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+        vfs.create_dir("/keep").unwrap();
+        vfs.create_file("/keep/small.txt")
+            .unwrap()
+            .write_all(b"stays")
+            .unwrap();
+        vfs.superblock()
+    };
+    let (start, end) = (sb.data_bitmap_start, sb.inode_table_start);
+    let before = data_bitmap(path, start, end);
+
+    let blocks = {
+        let mut vfs = Vfs::open(path).unwrap();
+        let mut f = vfs.create_file("/big.bin").unwrap();
+        f.write_all(&vec![7u8; 300 * sb.block_size as usize])
+            .unwrap();
+        drop(f);
+        vfs.block_map("/big.bin").unwrap()
+    };
+    assert_eq!(blocks.len(), 300);
+    let during = data_bitmap(path, start, end);
+    assert!(blocks.iter().all(|&id| id != 0 && is_set(&during, id)));
+    assert!(blocks.iter().all(|&id| !is_set(&before, id)));
+
+    {
+        let mut vfs = Vfs::open(path).unwrap();
+        vfs.remove("/big.bin").unwrap();
+    }
+    // The data blocks and the pointer block are cleared, nothing else.
+    assert_eq!(data_bitmap(path, start, end), before);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_set_len_frees_exactly_the_cut_blocks() {
+    let path = "test_free_bits_set_len.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    let bs = vfs.block_size() as usize;
+    let sb = vfs.superblock();
+    let mut f = vfs.create_file("/big.bin").unwrap();
+    f.write_all(&vec![1u8; 200 * bs]).unwrap();
+    drop(f);
+    let blocks = vfs.block_map("/big.bin").unwrap();
+    drop(vfs);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    vfs.truncate_file("/big.bin", 5 * bs as u64).unwrap();
+    drop(vfs);
+
+    let bitmap = data_bitmap(path, sb.data_bitmap_start, sb.inode_table_start);
+    assert!(blocks[..5].iter().all(|&id| is_set(&bitmap, id)));
+    assert!(blocks[5..].iter().all(|&id| !is_set(&bitmap, id)));
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,66 @@
+#![cfg(all(feature = "fuse", target_os = "linux"))]
+
+use project::Vfs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_mount_lists_root_and_unmounts() {
+    if !Path::new("/dev/fuse").exists() {
+        println!("/dev/fuse is missing, skipping");
+        return;
+    }
+
+    let path = "test_fuse.vfs";
+    let mountpoint = "test_fuse_mnt";
+    let _ = std::fs::remove_file(path);
+    std::fs::create_dir_all(mountpoint).unwrap();
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/docs").unwrap();
+        let mut f = vfs.create_file("/salut.txt").unwrap();
+        f.write_all(b"din fuse").unwrap();
+    }
+
+    let server = thread::spawn(move || {
+        let mut vfs = Vfs::open(path)?;
+        vfs.mount(mountpoint)
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut names = Vec::new();
+    while Instant::now() < deadline && !server.is_finished() {
+        if let Ok(entries) = std::fs::read_dir(mountpoint) {
+            names = entries
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            if !names.is_empty() {
+                break;
+            }
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    if server.is_finished() {
+        let result = server.join().unwrap();
+        println!("mount unavailable here ({:?}), skipping", result.err());
+        std::fs::remove_file(path).ok();
+        std::fs::remove_dir(mountpoint).ok();
+        return;
+    }
+
+    names.sort();
+    let content = std::fs::read(Path::new(mountpoint).join("salut.txt"));
+    Command::new("umount").arg(mountpoint).status().unwrap();
+    server.join().unwrap().unwrap();
+
+    assert_eq!(names, vec!["docs", "salut.txt"]);
+    assert_eq!(content.unwrap(), b"din fuse");
+
+    std::fs::remove_file(path).ok();
+    std::fs::remove_dir(mountpoint).ok();
+}
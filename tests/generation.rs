@@ -0,0 +1,43 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_handle_to_reused_inode_is_stale() {
+    let path = "test_generation.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let old_id = {
+        let mut f = vfs.create_file("/old.txt").unwrap();
+        f.write_all(b"old contents").unwrap();
+        f.inode_id
+    };
+    let old_generation = vfs.stat("/old.txt").unwrap().generation;
+
+    // A reader on the same image does not hold the writer's inodes open.
+    let mut reader = Vfs::open_readonly(path).unwrap();
+    let mut stale = reader.open_file("/old.txt").unwrap();
+
+    vfs.remove("/old.txt").unwrap();
+    let mut f = vfs.create_file("/new.txt").unwrap();
+    assert_eq!(f.inode_id, old_id);
+    f.write_all(b"new").unwrap();
+    assert_eq!(vfs.stat("/new.txt").unwrap().generation, old_generation + 1);
+
+    let err = stale.read(&mut [0u8; 16]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::StaleNetworkFileHandle);
+    assert_eq!(
+        stale.len().unwrap_err().kind(),
+        ErrorKind::StaleNetworkFileHandle
+    );
+
+    let mut text = String::new();
+    reader
+        .open_file("/new.txt")
+        .unwrap()
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(text, "new");
+
+    std::fs::remove_file(path).ok();
+}
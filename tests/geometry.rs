@@ -0,0 +1,21 @@
+use project::Vfs;
+use project::models::{BLOCK_SIZE, KEY};
+
+#[test]
+fn test_geometry_getters_match_superblock() {
+    let path = "test_geometry.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let sb = vfs.superblock();
+
+    assert_eq!(sb.key, KEY);
+    assert_eq!(vfs.block_size(), BLOCK_SIZE as u32);
+    assert_eq!(vfs.total_blocks(), 256);
+    assert_eq!(vfs.data_blocks_start(), sb.data_blocks_start);
+    assert_eq!(vfs.inode_table_start(), sb.inode_table_start);
+    assert!(vfs.inode_table_start() < vfs.data_blocks_start());
+    assert_eq!(vfs.data_blocks_start() % BLOCK_SIZE as u64, 0);
+
+    std::fs::remove_file(path).ok();
+}
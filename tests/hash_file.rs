@@ -0,0 +1,77 @@
+use project::{HashAlgo, Vfs};
+use std::io::{ErrorKind, Write};
+
+/// Table-driven CRC-32, independent of the bitwise one in the library.
+fn reference_crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, slot) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *slot = c;
+    }
+    !data.iter().fold(!0u32, |crc, &b| {
+        table[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+fn reference_fnv1a64(data: &[u8]) -> u64 {
+    data.iter().fold(14695981039346656037u64, |h, &b| {
+        (h ^ b as u64).wrapping_mul(1099511628211)
+    })
+}
+
+#[test]
+fn test_hash_file_matches_reference_digests() {
+    let path = "test_hash_file.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/check.txt")
+        .unwrap()
+        .write_all(b"123456789")
+        .unwrap();
+    // The standard check values for both algorithms.
+    assert_eq!(
+        vfs.hash_file("/check.txt", HashAlgo::Crc32).unwrap(),
+        0xCBF4_3926u32.to_be_bytes()
+    );
+    vfs.create_file("/a.txt").unwrap().write_all(b"a").unwrap();
+    assert_eq!(
+        vfs.hash_file("/a.txt", HashAlgo::Fnv1a64).unwrap(),
+        0xaf63_dc4c_8601_ec8cu64.to_be_bytes()
+    );
+
+    let data: Vec<u8> = (0..70_000u32).map(|i| (i * 31 % 253) as u8).collect();
+    vfs.create_file("/big.bin")
+        .unwrap()
+        .write_all(&data)
+        .unwrap();
+    assert_eq!(
+        vfs.hash_file("/big.bin", HashAlgo::Crc32).unwrap(),
+        reference_crc32(&data).to_be_bytes()
+    );
+    assert_eq!(
+        vfs.hash_file("/big.bin", HashAlgo::Fnv1a64).unwrap(),
+        reference_fnv1a64(&data).to_be_bytes()
+    );
+
+    vfs.create_file("/empty").unwrap();
+    assert_eq!(
+        vfs.hash_file("/empty", HashAlgo::Crc32).unwrap(),
+        vec![0, 0, 0, 0]
+    );
+
+    vfs.create_dir("/dir").unwrap();
+    assert_eq!(
+        vfs.hash_file("/dir", HashAlgo::Crc32).unwrap_err().kind(),
+        ErrorKind::IsADirectory
+    );
+
+    std::fs::remove_file(path).ok();
+}
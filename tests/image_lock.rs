@@ -0,0 +1,27 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_image_cannot_be_opened_twice() {
+    let path = "test_image_lock.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let _vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let err = Vfs::open(path).err().expect("second open must fail");
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(Vfs::open_shared(path).is_err());
+    }
+
+    {
+        let mut reader_a = Vfs::open_shared(path).unwrap();
+        let mut reader_b = Vfs::open_shared(path).unwrap();
+        assert!(reader_a.read_dir("/").is_ok());
+        assert!(reader_b.read_dir("/").is_ok());
+        assert!(Vfs::open(path).is_err());
+    }
+
+    let _vfs = Vfs::open(path).unwrap();
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,61 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_immutable_blocks_changes_until_cleared() {
+    let path = "test_immutable.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/important.txt")
+            .unwrap()
+            .write_all(b"keep me")
+            .unwrap();
+        vfs.create_file("/other.txt").unwrap();
+        vfs.set_immutable("/important.txt", true).unwrap();
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert!(vfs.stat("/important.txt").unwrap().is_immutable());
+
+    let mut f = vfs.open_file("/important.txt").unwrap();
+    assert_eq!(
+        f.write(b"x").unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+    assert_eq!(
+        f.set_len(0).unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+    drop(f);
+    for err in [
+        vfs.remove("/important.txt").unwrap_err(),
+        vfs.truncate_file("/important.txt", 1).unwrap_err(),
+        vfs.rename("/important.txt", "/moved.txt").unwrap_err(),
+        vfs.rename("/other.txt", "/important.txt").unwrap_err(),
+        vfs.rename_swap("/other.txt", "/important.txt").unwrap_err(),
+    ] {
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{}", err);
+    }
+
+    let mut s = String::new();
+    vfs.open_file("/important.txt")
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, "keep me");
+    assert!(vfs.open_file("/other.txt").is_ok());
+
+    vfs.set_immutable("/important.txt", false).unwrap();
+    assert!(!vfs.stat("/important.txt").unwrap().is_immutable());
+    vfs.open_file("/important.txt")
+        .unwrap()
+        .write_all(b"edited")
+        .unwrap();
+    vfs.rename("/important.txt", "/moved.txt").unwrap();
+    vfs.remove("/moved.txt").unwrap();
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", "..", "other.txt"]);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,82 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_out_of_range_inode_ids_are_rejected() {
+    let path = "test_inode_bounds.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let count = vfs.superblock().inode_count();
+    assert!(vfs.get_inode(count - 1).is_ok());
+
+    for id in [count, count + 1, u32::MAX] {
+        let err = vfs.get_inode(id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("out of range"), "{}", err);
+    }
+    let root = vfs.get_inode(0).unwrap();
+    assert_eq!(
+        vfs.save_inode(count, root).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_corrupted_entry_id_gives_clean_error() {
+    let path = "test_inode_bounds_entry.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/victim.txt").unwrap();
+    }
+    let mut image = std::fs::read(path).unwrap();
+    let pos = image.windows(10).position(|w| w == b"victim.txt").unwrap();
+    image[pos - 4..pos].copy_from_slice(&60_000u32.to_le_bytes());
+    std::fs::write(path, image).unwrap();
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let err = vfs.stat("/victim.txt").unwrap_err();
+    assert!(
+        matches!(err.kind(), ErrorKind::InvalidData | ErrorKind::NotFound),
+        "{:?}",
+        err
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_huge_entry_id_is_skipped_by_listings() {
+    let path = "test_inode_bounds_listing.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/dir").unwrap();
+        vfs.create_file("/dir/good.txt").unwrap();
+        vfs.create_file("/dir/huge.txt").unwrap();
+    }
+    let mut image = std::fs::read(path).unwrap();
+    let pos = image.windows(8).position(|w| w == b"huge.txt").unwrap();
+    image[pos - 4..pos].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(path, image).unwrap();
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.read_dir("/dir").unwrap(), vec![".", "..", "good.txt"]);
+    assert_eq!(vfs.list_long("/dir").unwrap().len(), 3);
+    assert_eq!(vfs.entries("/dir").unwrap().count(), 3);
+
+    let err = vfs.open_file("/dir/huge.txt").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("huge.txt"), "{}", err);
+    assert!(vfs.open_file("/dir/good.txt").is_ok());
+    // Nothing valid links to the real inode of huge.txt any more, so opening
+    // the image reclaimed it.
+    assert_eq!(vfs.statfs().unwrap().file_count, 1);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,27 @@
+use project::{NoInodes, Vfs};
+use std::io::ErrorKind;
+
+#[test]
+fn test_inode_table_exhaustion_is_reported() {
+    let path = "test_inode_exhaustion.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 64 * 4096).unwrap();
+    let slots = vfs.superblock().inode_count();
+
+    let mut created = 0;
+    let err = loop {
+        match vfs.create_file(&format!("/f{}", created)) {
+            Ok(_) => created += 1,
+            Err(e) => break e,
+        }
+        assert!(created < slots, "allocated past the inode table");
+    };
+    assert_eq!(created, slots - 1);
+    assert_eq!(err.kind(), ErrorKind::StorageFull);
+    assert!(err.get_ref().is_some_and(|e| e.is::<NoInodes>()));
+
+    assert_eq!(vfs.read_dir("/").unwrap().len(), created as usize + 2);
+
+    std::fs::remove_file(path).ok();
+}
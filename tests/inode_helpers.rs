@@ -0,0 +1,54 @@
+use project::models::{INODE_SIZE, Inode, InodeKind};
+use std::io::ErrorKind;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn crafted(kind: InodeKind, is_valid: u8, created_at: u64) -> Inode {
+    let mut bytes = [0u8; INODE_SIZE];
+    bytes[0] = kind as u8;
+    bytes[1] = is_valid;
+    bytes[16..24].copy_from_slice(&created_at.to_le_bytes());
+    Inode::from_bytes(&bytes).unwrap()
+}
+
+#[test]
+fn test_inode_kind_helpers() {
+    let file = crafted(InodeKind::File, 1, 0);
+    assert_eq!(file.kind(), InodeKind::File);
+    assert!(file.is_file());
+    assert!(!file.is_dir());
+    assert!(file.is_valid());
+
+    let dir = crafted(InodeKind::Dir, 0, 0);
+    assert_eq!(dir.kind(), InodeKind::Dir);
+    assert!(dir.is_dir());
+    assert!(!dir.is_file());
+    assert!(!dir.is_valid());
+
+    let link = crafted(InodeKind::Symlink, 1, 0);
+    assert_eq!(link.kind(), InodeKind::Symlink);
+    assert!(!link.is_file());
+    assert!(!link.is_dir());
+}
+
+#[test]
+fn test_inode_age() {
+    let inode = crafted(InodeKind::File, 1, 1_000);
+    let now = UNIX_EPOCH + Duration::from_secs(1_090);
+    assert_eq!(inode.age(now), Duration::from_secs(90));
+
+    // A clock behind the creation time gives zero rather than panicking.
+    let earlier = UNIX_EPOCH + Duration::from_secs(500);
+    assert_eq!(inode.age(earlier), Duration::ZERO);
+}
+
+#[test]
+fn test_unknown_inode_type_is_rejected() {
+    let mut bytes = [0u8; INODE_SIZE];
+    bytes[0] = 7;
+    assert_eq!(
+        Inode::from_bytes(&bytes).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert!(InodeKind::try_from(7).is_err());
+    assert_eq!(InodeKind::try_from(1).unwrap(), InodeKind::Dir);
+}
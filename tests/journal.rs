@@ -0,0 +1,47 @@
+#![cfg(feature = "journal")]
+
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_corrupt_commit_record_is_rejected() {
+    let path = "test_journal_corrupt.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let (start, block_size) = {
+        let vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let sb = vfs.superblock();
+        (sb.journal_start as usize, sb.block_size as usize)
+    };
+    let write_commit = |count: u32, payload: &[u8]| {
+        let mut image = std::fs::read(path).unwrap();
+        image[start..start + 8].copy_from_slice(b"1LNRJSFV");
+        image[start + 8..start + 12].copy_from_slice(&count.to_le_bytes());
+        image[start + 12..start + 16].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        image[start + block_size..start + block_size + payload.len()].copy_from_slice(payload);
+        std::fs::write(path, image).unwrap();
+    };
+
+    // More records than the payload holds.
+    write_commit(3, &[0u8; 4]);
+    let err = Vfs::open(path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    // A write past the end of the image.
+    let mut record = Vec::new();
+    record.extend_from_slice(&(u64::MAX - 2).to_le_bytes());
+    record.extend_from_slice(&4u32.to_le_bytes());
+    record.extend_from_slice(b"boom");
+    write_commit(1, &record);
+    let err = Vfs::open(path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    // A payload length larger than the journal.
+    let mut image = std::fs::read(path).unwrap();
+    image[start + 12..start + 16].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(path, image).unwrap();
+    let err = Vfs::open(path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    std::fs::remove_file(path).ok();
+}
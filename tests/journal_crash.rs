@@ -0,0 +1,75 @@
+#![cfg(feature = "crash-test")]
+
+use project::Vfs;
+use std::io::{Read, Write};
+
+#[test]
+fn test_crash_before_commit_rolls_back() {
+    let path = "test_journal_rollback.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let file_id;
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let mut f = vfs.create_file("/pastrat.txt").unwrap();
+        f.write_all(b"nu se pierde").unwrap();
+        file_id = f.inode_id;
+        drop(f);
+
+        vfs.simulate_journal_crash(false);
+        assert!(vfs.remove("/pastrat.txt").is_err());
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let mut content = String::new();
+    vfs.open_file("/pastrat.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "nu se pierde");
+    // The bitmap still holds the inode, so it is not handed out again.
+    assert_ne!(vfs.create_file("/altul.txt").unwrap().inode_id, file_id);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_crash_after_commit_is_replayed() {
+    let path = "test_journal_replay.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let file_id;
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        file_id = vfs.create_file("/sters.txt").unwrap().inode_id;
+
+        vfs.simulate_journal_crash(true);
+        assert!(vfs.remove("/sters.txt").is_err());
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert!(vfs.open_file("/sters.txt").is_err());
+    assert_eq!(vfs.create_file("/nou.txt").unwrap().inode_id, file_id);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_simulated_crash_fires_once() {
+    let path = "test_journal_crash_once.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/a.txt").unwrap();
+    vfs.simulate_journal_crash(false);
+    assert!(vfs.remove("/a.txt").is_err());
+    vfs.remove("/a.txt").unwrap();
+    vfs.create_file("/b.txt").unwrap();
+    drop(vfs);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert!(vfs.open_file("/a.txt").is_err());
+    vfs.open_file("/b.txt").unwrap();
+
+    std::fs::remove_file(path).ok();
+}
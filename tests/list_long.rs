@@ -0,0 +1,33 @@
+use project::Vfs;
+use project::models::InodeKind;
+use std::io::Write;
+
+#[test]
+fn test_list_long_returns_entries() {
+    let path = "test_list_long.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+    vfs.create_dir("/docs/poze").unwrap();
+    {
+        let mut f = vfs.create_file("/docs/note.txt").unwrap();
+        f.write_all(b"12345").unwrap();
+    }
+
+    let entries = vfs.list_long("/docs").unwrap();
+    let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, vec![".", "..", "poze", "note.txt"]);
+
+    let note = &entries[3];
+    assert_eq!(note.inode_type, InodeKind::File);
+    assert_eq!(note.size, 5);
+    assert!(note.modified >= note.created);
+    assert_eq!(
+        note.modified,
+        vfs.metadata("/docs/note.txt").unwrap().modified()
+    );
+    assert_eq!(entries[2].inode_type, InodeKind::Dir);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,32 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_second_exclusive_lock_is_refused() {
+    let path = "test_locking.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/shared.txt").unwrap();
+
+    let mut first = vfs.open_file("/shared.txt").unwrap();
+    let mut second = vfs.open_file("/shared.txt").unwrap();
+
+    first.lock_exclusive().unwrap();
+    let err = second.lock_exclusive().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    assert_eq!(
+        second.lock_shared().unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+
+    first.unlock().unwrap();
+    second.lock_shared().unwrap();
+    first.lock_shared().unwrap();
+    assert!(first.lock_exclusive().is_err());
+
+    drop(second);
+    first.lock_exclusive().unwrap();
+
+    std::fs::remove_file(path).ok();
+}
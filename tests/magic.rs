@@ -0,0 +1,51 @@
+use project::Vfs;
+use project::models::KEY;
+use std::io::{ErrorKind, Read, Write};
+
+const FORK_MAGIC: u64 = u64::from_be_bytes(*b"ForkFS01");
+
+#[test]
+fn test_custom_magic_needs_open_with_magic() {
+    let path = "test_magic.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create_with_magic(path, 1024 * 1024, FORK_MAGIC).unwrap();
+        assert_eq!(vfs.superblock().key, FORK_MAGIC);
+        vfs.create_file("/a.txt")
+            .unwrap()
+            .write_all(b"fork")
+            .unwrap();
+    }
+
+    let err = Vfs::open(path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = Vfs::open_with_magic(path, FORK_MAGIC + 1).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let mut vfs = Vfs::open_with_magic(path, FORK_MAGIC).unwrap();
+    let mut s = String::new();
+    vfs.open_file("/a.txt")
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, "fork");
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_default_image_rejects_custom_magic() {
+    let path = "test_magic_default.vfs";
+    let _ = std::fs::remove_file(path);
+
+    Vfs::create(path, 1024 * 1024).unwrap();
+    assert!(Vfs::open_with_magic(path, KEY).is_ok());
+    let err = Vfs::open_with_magic(path, FORK_MAGIC).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let err = Vfs::create_with_magic(path, 1024 * 1024, 0).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,56 @@
+use project::{PathTooDeep, Vfs};
+use std::io::ErrorKind;
+
+fn is_too_deep(err: &std::io::Error) -> bool {
+    err.kind() == ErrorKind::InvalidInput && err.get_ref().is_some_and(|e| e.is::<PathTooDeep>())
+}
+
+#[test]
+fn test_paths_deeper_than_limit_are_rejected() {
+    let path = "test_max_depth.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap().with_max_depth(4);
+    let mut dir = String::new();
+    for i in 0..4 {
+        dir.push_str(&format!("/d{}", i));
+        vfs.create_dir(&dir).unwrap();
+    }
+    vfs.find_inode_by_path(&dir).unwrap();
+
+    let too_deep = format!("{}/d4", dir);
+    assert!(is_too_deep(&vfs.create_dir(&too_deep).unwrap_err()));
+    assert!(is_too_deep(&vfs.create_file(&too_deep).err().unwrap()));
+    assert!(is_too_deep(&vfs.find_inode_by_path(&too_deep).unwrap_err()));
+    let err = vfs.find_inode_by_path("/a/b/c/d/e/f").unwrap_err();
+    assert!(is_too_deep(&err));
+    assert!(err.to_string().contains("4"), "{}", err);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_walks_stop_at_limit() {
+    let path = "test_max_depth_walk.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let mut dir = String::new();
+        for i in 0..6 {
+            dir.push_str(&format!("/d{}", i));
+            vfs.create_dir(&dir).unwrap();
+        }
+    }
+
+    let mut vfs = Vfs::open(path).unwrap().with_max_depth(3);
+    assert!(is_too_deep(&vfs.find("/", |_, _| true).unwrap_err()));
+    let err = vfs.remove_glob_recursive("/", "d0").unwrap_err();
+    assert!(is_too_deep(&err));
+
+    let mut vfs = vfs.with_max_depth(project::DEFAULT_MAX_DEPTH);
+    assert_eq!(vfs.find("/", |_, _| true).unwrap().len(), 6);
+    assert_eq!(vfs.remove_glob_recursive("/", "d0").unwrap(), 1);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,30 @@
+use project::Vfs;
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+
+#[test]
+fn test_max_file_size_and_pre_write_rejection() {
+    let path = "test_max_file_size.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 8 * 1024 * 1024).unwrap();
+    assert_eq!(vfs.max_file_size(), (10 + 4096 / 4) * 4096);
+
+    let max = vfs.max_file_size();
+    let mut f = vfs.create_file("/big.bin").unwrap();
+    f.seek(SeekFrom::Start(max - 10)).unwrap();
+    let err = f.write(&[1u8; 11]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::FileTooLarge);
+    assert!(err.to_string().contains(&max.to_string()), "{}", err);
+    drop(f);
+
+    // Nothing was allocated for the refused write.
+    assert_eq!(vfs.stat("/big.bin").unwrap().size, 0);
+    assert!(vfs.block_map("/big.bin").unwrap().is_empty());
+
+    let mut f = vfs.open_file("/big.bin").unwrap();
+    f.seek(SeekFrom::Start(max - 10)).unwrap();
+    f.write_all(&[1u8; 10]).unwrap();
+    assert_eq!(f.len().unwrap(), max);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,37 @@
+use project::Vfs;
+use std::io::Write;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn test_metadata_reports_kind_and_times() {
+    let path = "test_metadata.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+    {
+        let mut f = vfs.create_file("/docs/a.txt").unwrap();
+        f.write_all(b"hello").unwrap();
+    }
+
+    let dir = vfs.metadata("/docs").unwrap();
+    assert!(dir.is_dir());
+    assert!(!dir.is_file());
+
+    let file = vfs.metadata("/docs/a.txt").unwrap();
+    assert!(file.is_file());
+    assert!(!file.is_dir());
+    assert_eq!(file.len(), 5);
+
+    let inode = vfs.stat("/docs/a.txt").unwrap();
+    assert_eq!(
+        file.created(),
+        UNIX_EPOCH + Duration::from_secs(inode.created_at)
+    );
+    assert_eq!(
+        file.modified(),
+        UNIX_EPOCH + Duration::from_secs(inode.modified_at)
+    );
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "mmap")]
+
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+fn directory_workload(vfs: &mut Vfs) -> Vec<String> {
+    vfs.create_dir("/dir").unwrap();
+    for i in 0..60 {
+        let mut f = vfs.create_file(&format!("/dir/f{}.txt", i)).unwrap();
+        f.write_all(format!("fisier {}", i).as_bytes()).unwrap();
+    }
+    for i in 0..60 {
+        vfs.open_file(&format!("/dir/f{}.txt", i)).unwrap();
+    }
+    vfs.read_dir("/dir").unwrap()
+}
+
+#[test]
+fn test_mmap_matches_file_backend_with_fewer_syscalls() {
+    let plain_path = "test_mmap_plain.vfs";
+    let mapped_path = "test_mmap_mapped.vfs";
+    let _ = std::fs::remove_file(plain_path);
+    let _ = std::fs::remove_file(mapped_path);
+
+    let mut plain = Vfs::create(plain_path, 2 * 1024 * 1024).unwrap();
+    let plain_start = plain.io_calls();
+    let plain_entries = directory_workload(&mut plain);
+    let plain_calls = plain.io_calls() - plain_start;
+
+    let mut mapped = Vfs::create(mapped_path, 2 * 1024 * 1024).unwrap();
+    mapped.enable_mmap().unwrap();
+    let mapped_start = mapped.io_calls();
+    let mapped_entries = directory_workload(&mut mapped);
+    let mapped_calls = mapped.io_calls() - mapped_start;
+
+    assert_eq!(plain_entries, mapped_entries);
+    assert!(
+        mapped_calls * 10 < plain_calls,
+        "mmap: {}, file: {}",
+        mapped_calls,
+        plain_calls
+    );
+    drop(mapped);
+
+    let mut reopened = Vfs::open(mapped_path).unwrap();
+    let mut content = String::new();
+    reopened
+        .open_file("/dir/f42.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "fisier 42");
+
+    std::fs::remove_file(plain_path).ok();
+    std::fs::remove_file(mapped_path).ok();
+}
+
+#[test]
+fn test_mmap_refuses_unlocked_images() {
+    let path = "test_mmap_readonly.vfs";
+    let _ = std::fs::remove_file(path);
+
+    drop(Vfs::create(path, 1024 * 1024).unwrap());
+    let mut vfs = Vfs::open_readonly(path).unwrap();
+    let err = vfs.enable_mmap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert!(vfs.read_dir("/").is_ok());
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,65 @@
+use project::Vfs;
+use std::io::{Read, Write};
+
+#[test]
+fn test_move_all_reparents_deep_tree() {
+    let path = "test_move_all.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    vfs.create_dir("/old").unwrap();
+    vfs.create_dir("/new").unwrap();
+    let mut dir = String::from("/old/tree");
+    let mut files = Vec::new();
+    for depth in 0..12 {
+        vfs.create_dir(&dir).unwrap();
+        let file = format!("{}/f{}.txt", dir, depth);
+        vfs.create_file(&file)
+            .unwrap()
+            .write_all(file.as_bytes())
+            .unwrap();
+        files.push(file);
+        dir = format!("{}/d{}", dir, depth);
+    }
+    let root_id = vfs.find_inode_by_path("/old/tree").unwrap();
+
+    vfs.move_all("/old/tree", "/new/tree").unwrap();
+
+    assert_eq!(vfs.find_inode_by_path("/new/tree").unwrap(), root_id);
+    assert!(vfs.find_inode_by_path("/old/tree").is_err());
+    assert_eq!(vfs.read_dir("/old").unwrap(), vec![".", ".."]);
+    for old in &files {
+        let new = old.replacen("/old/", "/new/", 1);
+        let mut s = String::new();
+        vfs.open_file(&new).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(&s, old);
+        assert!(vfs.open_file(old).is_err());
+    }
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_move_cost_does_not_depend_on_subtree_size() {
+    let path = "test_move_all_cost.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    for dir in ["/a", "/b", "/a/small", "/a/big"] {
+        vfs.create_dir(dir).unwrap();
+    }
+    for i in 0..150 {
+        vfs.create_file(&format!("/a/big/f{}", i)).unwrap();
+    }
+
+    let before = vfs.io_calls();
+    vfs.move_all("/a/small", "/b/small").unwrap();
+    let small = vfs.io_calls() - before;
+
+    let before = vfs.io_calls();
+    vfs.move_all("/a/big", "/b/big").unwrap();
+    let big = vfs.io_calls() - before;
+    assert!(big <= small + small / 4, "{} vs {}", big, small);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,58 @@
+use project::Vfs;
+
+/// Offset of the entry whose name starts with `name` in the raw image.
+fn entry_offset(image: &[u8], name: &[u8]) -> usize {
+    image.windows(name.len()).position(|w| w == name).unwrap() - 4
+}
+
+#[test]
+fn test_stored_length_ignores_padding_bytes() {
+    let path = "test_name_len.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/report").unwrap();
+        let raw = vfs.read_dir_raw("/").unwrap();
+        let entry = raw.iter().find(|e| e.name.starts_with(b"report")).unwrap();
+        assert_eq!(entry.name_len, 6);
+        assert_eq!(entry.name_bytes(), b"report");
+    }
+
+    // Garbage after the name, where a NUL-trimmed name would pick it up.
+    let mut image = std::fs::read(path).unwrap();
+    let pos = entry_offset(&image, b"report");
+    image[pos + 4 + 6..pos + 4 + 32].fill(b'x');
+    std::fs::write(path, &image).unwrap();
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert!(vfs.read_dir("/").unwrap().contains(&"report".to_string()));
+    vfs.stat("/report").unwrap();
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_entries_without_length_fall_back_to_trimming() {
+    let path = "test_name_len_legacy.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/old-style.txt").unwrap();
+    }
+    let mut image = std::fs::read(path).unwrap();
+    let pos = entry_offset(&image, b"old-style.txt");
+    image[pos + 37] = 0;
+    std::fs::write(path, &image).unwrap();
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert!(
+        vfs.read_dir("/")
+            .unwrap()
+            .contains(&"old-style.txt".to_string())
+    );
+    vfs.stat("/old-style.txt").unwrap();
+
+    std::fs::remove_file(path).ok();
+}
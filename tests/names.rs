@@ -0,0 +1,60 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_create_rejects_control_characters() {
+    let path = "test_names_control.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    for name in ["/a\0b", "/tab\there", "/new\nline", "/bell\u{7}"] {
+        let err = vfs.create_file(name).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{:?}", name);
+        assert_eq!(
+            vfs.create_dir(name).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", ".."]);
+
+    vfs.create_file("/ok-ăîș.txt").unwrap();
+    assert!(
+        vfs.read_dir("/")
+            .unwrap()
+            .contains(&"ok-ăîș.txt".to_string())
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_invalid_stored_name_is_listed_lossy() {
+    let path = "test_names_lossy.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/xbad.txt").unwrap();
+    }
+    let mut image = std::fs::read(path).unwrap();
+    let pos = image.windows(8).position(|w| w == b"xbad.txt").unwrap();
+    image[pos] = 0xFF;
+    std::fs::write(path, image).unwrap();
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let listed = vfs.read_dir("/").unwrap();
+    assert!(
+        listed.contains(&"\u{FFFD}bad.txt".to_string()),
+        "{:?}",
+        listed
+    );
+
+    let flagged = vfs.read_dir_lossy("/").unwrap();
+    assert!(flagged.contains(&("\u{FFFD}bad.txt".to_string(), true)));
+    assert!(flagged.contains(&(".".to_string(), false)));
+
+    vfs.remove("/\u{FFFD}bad.txt").unwrap();
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", ".."]);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,27 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_path_through_file_is_not_a_directory() {
+    let path = "test_not_a_directory.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+    vfs.create_file("/a/file.txt").unwrap();
+
+    let err = vfs.find_inode_by_path("/a/file.txt/child").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotADirectory);
+    assert!(err.to_string().contains("a/file.txt"), "{}", err);
+
+    assert_eq!(
+        vfs.create_file("/a/file.txt/child").err().unwrap().kind(),
+        ErrorKind::NotADirectory
+    );
+    assert_eq!(
+        vfs.find_inode_by_path("/a/missing").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    std::fs::remove_file(path).ok();
+}
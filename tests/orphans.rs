@@ -0,0 +1,41 @@
+use project::Vfs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_orphaned_inode_is_reclaimed_on_open() {
+    let path = "test_orphans.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let inode_bitmap_start;
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let first = vfs.create_file("/linked.txt").unwrap().inode_id;
+        assert_eq!(first, 1);
+
+        // Simulate a crash between allocating inode 2 and linking it.
+        let mut orphan = vfs.get_inode(1).unwrap();
+        orphan.size = 0;
+        vfs.save_inode(2, orphan).unwrap();
+        inode_bitmap_start = vfs.superblock().inode_bitmap_start;
+    }
+    {
+        let mut raw = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let mut byte = [0u8; 1];
+        raw.seek(SeekFrom::Start(inode_bitmap_start)).unwrap();
+        raw.read_exact(&mut byte).unwrap();
+        byte[0] |= 1 << 2;
+        raw.seek(SeekFrom::Start(inode_bitmap_start)).unwrap();
+        raw.write_all(&byte).unwrap();
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let reused = vfs.create_file("/fresh.txt").unwrap().inode_id;
+    assert_eq!(reused, 2);
+    assert!(vfs.open_file("/linked.txt").is_ok());
+
+    std::fs::remove_file(path).ok();
+}
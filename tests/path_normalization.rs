@@ -0,0 +1,59 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_slashes_are_normalized() {
+    let path = "test_path_normalization.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a/").unwrap();
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", "..", "a"]);
+    assert_eq!(vfs.read_dir("/a").unwrap(), vec![".", ".."]);
+
+    vfs.create_dir("//a//b//").unwrap();
+    vfs.create_file("/a//b/f.txt").unwrap();
+    assert!(
+        vfs.read_dir("/a/b/")
+            .unwrap()
+            .contains(&"f.txt".to_string())
+    );
+
+    vfs.remove("//a//b//f.txt").unwrap();
+    vfs.remove("//a//b//").unwrap();
+    vfs.remove("//a//").unwrap();
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", ".."]);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_paths_without_a_final_name_are_rejected() {
+    let path = "test_path_empty.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+
+    assert_eq!(
+        vfs.find_inode_by_path("").unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(vfs.find_inode_by_path("/").unwrap(), 0);
+    for bad in ["", "/", "//", "/a/.", "/a/.."] {
+        assert_eq!(
+            vfs.create_dir(bad).unwrap_err().kind(),
+            ErrorKind::InvalidInput,
+            "{:?}",
+            bad
+        );
+        assert_eq!(
+            vfs.create_file(bad).err().unwrap().kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(vfs.remove(bad).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+    assert_eq!(vfs.read_dir("/a").unwrap(), vec![".", ".."]);
+
+    std::fs::remove_file(path).ok();
+}
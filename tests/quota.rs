@@ -0,0 +1,98 @@
+use project::Vfs;
+use project::models::BLOCK_SIZE;
+use std::io::{ErrorKind, Write};
+
+#[test]
+fn test_quota_blocks_writes_past_limit() {
+    let path = "test_quota.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/tenant").unwrap();
+    vfs.create_dir("/tenant/docs").unwrap();
+    vfs.set_quota("/tenant", 5).unwrap();
+
+    let mut f = vfs.create_file("/tenant/docs/a.bin").unwrap();
+    f.write_all(&vec![1u8; 5 * BLOCK_SIZE]).unwrap();
+    let err = f.write_all(b"x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::QuotaExceeded);
+    assert_eq!(
+        vfs.metadata("/tenant/docs/a.bin").unwrap().len(),
+        5 * BLOCK_SIZE as u64
+    );
+    drop(f);
+
+    // Space outside the subtree is not limited.
+    let mut other = vfs.create_file("/free.bin").unwrap();
+    other.write_all(&vec![2u8; 8 * BLOCK_SIZE]).unwrap();
+    drop(other);
+
+    // Removing files gives the space back to the quota.
+    vfs.remove("/tenant/docs/a.bin").unwrap();
+    let mut f = vfs.create_file("/tenant/b.bin").unwrap();
+    f.write_all(&vec![3u8; 5 * BLOCK_SIZE]).unwrap();
+    assert_eq!(
+        f.write_all(b"y").unwrap_err().kind(),
+        ErrorKind::QuotaExceeded
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_refused_write_keeps_file_after_reopen() {
+    let path = "test_quota_reopen.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/tenant").unwrap();
+        vfs.set_quota("/tenant", 1).unwrap();
+        let mut f = vfs.create_file("/tenant/a.txt").unwrap();
+        f.write_all(&vec![7u8; BLOCK_SIZE + 10]).unwrap_err();
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(
+        vfs.metadata("/tenant/a.txt").unwrap().len(),
+        BLOCK_SIZE as u64
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_reserved_blocks_are_charged_and_refunded() {
+    let path = "test_quota_reserved.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/tenant").unwrap();
+    vfs.set_quota("/tenant", 5).unwrap();
+    let mut f = vfs.create_file("/tenant/a.bin").unwrap();
+    f.write_all(&vec![1u8; BLOCK_SIZE]).unwrap();
+    f.reserve(3 * BLOCK_SIZE as u64).unwrap();
+    drop(f);
+
+    // The soft removal gives back all four blocks, not just the written one.
+    vfs.remove_soft("/tenant/a.bin").unwrap();
+    let mut f = vfs.create_file("/tenant/b.bin").unwrap();
+    f.write_all(&vec![2u8; 5 * BLOCK_SIZE]).unwrap();
+    drop(f);
+    vfs.remove("/tenant/b.bin").unwrap();
+
+    // Setting a quota counts reserved blocks already there.
+    vfs.create_dir("/other").unwrap();
+    let mut f = vfs.create_file("/other/a.bin").unwrap();
+    f.write_all(&vec![3u8; BLOCK_SIZE]).unwrap();
+    f.reserve(3 * BLOCK_SIZE as u64).unwrap();
+    drop(f);
+    vfs.set_quota("/other", 5).unwrap();
+    let mut f = vfs.create_file("/other/b.bin").unwrap();
+    assert_eq!(
+        f.write_all(&vec![4u8; 2 * BLOCK_SIZE]).unwrap_err().kind(),
+        ErrorKind::QuotaExceeded
+    );
+
+    std::fs::remove_file(path).ok();
+}
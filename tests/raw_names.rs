@@ -0,0 +1,51 @@
+use project::Vfs;
+use project::models::DIR_SIZE;
+use std::io::ErrorKind;
+
+#[test]
+fn test_non_utf8_name_found_and_removed_by_bytes() {
+    let path = "test_raw_names.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/d").unwrap();
+    let id = vfs.create_file_with_id("/d/abcd").unwrap().0;
+    vfs.create_file("/d/other").unwrap();
+
+    // Rewrite the stored name through the raw block API into bytes that are
+    // not valid UTF-8.
+    let raw: &[u8] = b"ab\xff\xfe";
+    let slot = vfs
+        .read_dir_raw("/d")
+        .unwrap()
+        .iter()
+        .position(|e| e.is_active == 1 && e.name_bytes() == b"abcd")
+        .unwrap();
+    let block = vfs.block_map("/d").unwrap()[0];
+    let mut buf = vec![0u8; vfs.block_size() as usize];
+    vfs.read_block(block, &mut buf).unwrap();
+    let name_at = slot * DIR_SIZE + 4;
+    buf[name_at..name_at + 4].copy_from_slice(raw);
+    vfs.write_block(block, &buf).unwrap();
+
+    let names = vfs.read_dir("/d").unwrap();
+    assert!(names.contains(&"ab\u{fffd}\u{fffd}".to_string()));
+
+    assert_eq!(vfs.find_bytes("/d", raw).unwrap(), id);
+    let err = vfs.find_bytes("/d", b"ab\xff").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    vfs.remove_bytes("/d", raw).unwrap();
+    assert_eq!(
+        vfs.find_bytes("/d", raw).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+    let mut names = vfs.read_dir("/d").unwrap();
+    names.retain(|n| n != "." && n != "..");
+    assert_eq!(names, vec!["other".to_string()]);
+
+    let err = vfs.find_bytes("/d/other", b"x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotADirectory);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,103 @@
+use project::Vfs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_small_sequential_reads_match_whole_file() {
+    let path = "test_read_ahead.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let data: Vec<u8> = (0..30_000u32).map(|i| (i * 7 % 256) as u8).collect();
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/data.bin").unwrap();
+        f.write_all(&data).unwrap();
+    }
+
+    let mut whole = Vec::new();
+    vfs.open_file("/data.bin")
+        .unwrap()
+        .read_to_end(&mut whole)
+        .unwrap();
+    assert_eq!(whole, data);
+
+    let mut f = vfs.open_file("/data.bin").unwrap();
+    let before = vfs.io_calls();
+    let mut pieces = Vec::new();
+    let mut chunk = [0u8; 13];
+    loop {
+        let n = f.read(&mut chunk).unwrap();
+        if n == 0 {
+            break;
+        }
+        pieces.extend_from_slice(&chunk[..n]);
+    }
+    assert_eq!(pieces, data);
+    let reads = data.len().div_ceil(13) as u64;
+    assert!(vfs.io_calls() - before < reads / 4);
+
+    // Seeks and writes through the handle drop the buffered bytes.
+    f.seek(SeekFrom::Start(5000)).unwrap();
+    f.read_exact(&mut chunk).unwrap();
+    assert_eq!(chunk, data[5000..5013]);
+    f.seek(SeekFrom::Start(5013)).unwrap();
+    f.write_all(b"XYZ").unwrap();
+    f.seek(SeekFrom::Start(5010)).unwrap();
+    f.read_exact(&mut chunk[..6]).unwrap();
+    assert_eq!(&chunk[..3], &data[5010..5013]);
+    assert_eq!(&chunk[3..6], b"XYZ");
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_buffered_bytes_follow_writes_from_other_handles() {
+    let path = "test_read_ahead_other_handle.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/data.txt").unwrap();
+        f.write_all(b"aaaaaaaaaabbbbbbbbbb").unwrap();
+    }
+
+    let mut reader = vfs.open_file("/data.txt").unwrap();
+    let mut chunk = [0u8; 10];
+    reader.read_exact(&mut chunk).unwrap();
+    assert_eq!(&chunk, b"aaaaaaaaaa");
+
+    // Same size, likely the same second: only the write itself tells.
+    let mut writer = vfs.open_file("/data.txt").unwrap();
+    writer.seek(SeekFrom::Start(10)).unwrap();
+    writer.write_all(b"cccccccccc").unwrap();
+    reader.read_exact(&mut chunk).unwrap();
+    assert_eq!(&chunk, b"cccccccccc");
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_buffered_read_on_reused_inode_is_stale() {
+    let path = "test_read_ahead_stale.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/old.txt").unwrap();
+        f.write_all(b"old contents").unwrap();
+    }
+    let mut reader = Vfs::open_readonly(path).unwrap();
+    let mut stale = reader.open_file("/old.txt").unwrap();
+    let mut chunk = [0u8; 3];
+    stale.read_exact(&mut chunk).unwrap();
+
+    vfs.remove("/old.txt").unwrap();
+    vfs.create_file("/new.txt")
+        .unwrap()
+        .write_all(b"new contents")
+        .unwrap();
+
+    let err = stale.read(&mut chunk).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::StaleNetworkFileHandle);
+
+    std::fs::remove_file(path).ok();
+}
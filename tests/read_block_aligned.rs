@@ -0,0 +1,56 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_block_by_block_copy_matches_bytewise_read() {
+    let path = "test_read_block_aligned.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let data: Vec<u8> = (0..50_000u32).map(|i| (i % 241) as u8).collect();
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/src.bin")
+        .unwrap()
+        .write_all(&data)
+        .unwrap();
+    let bs = vfs.block_size() as usize;
+
+    let mut src = vfs.open_file("/src.bin").unwrap();
+    let mut dst = vfs.create_file("/dst.bin").unwrap();
+    let mut block = vec![0u8; bs];
+    let mut sizes = Vec::new();
+    loop {
+        let n = src.read_block_aligned(&mut block).unwrap();
+        if n == 0 {
+            break;
+        }
+        sizes.push(n);
+        dst.write_all(&block[..n]).unwrap();
+    }
+    assert_eq!(sizes.len(), data.len().div_ceil(bs));
+    assert!(sizes[..sizes.len() - 1].iter().all(|&n| n == bs));
+    assert_eq!(*sizes.last().unwrap(), data.len() % bs);
+    drop(dst);
+
+    let mut copied = Vec::new();
+    let mut byte = [0u8; 1];
+    let mut reader = vfs.open_file("/dst.bin").unwrap();
+    while reader.read(&mut byte).unwrap() == 1 {
+        copied.push(byte[0]);
+    }
+    assert_eq!(copied, data);
+
+    // A position inside a block reads that block from its start.
+    src.seek(SeekFrom::Start(bs as u64 + 10)).unwrap();
+    assert_eq!(src.read_block_aligned(&mut block).unwrap(), bs);
+    assert_eq!(&block[..], &data[bs..2 * bs]);
+    assert_eq!(src.position(), 2 * bs as u64);
+
+    assert_eq!(
+        src.read_block_aligned(&mut block[..bs - 1])
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,27 @@
+use project::Vfs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_single_read_fills_large_buffer() {
+    let path = "test_read_coalescing.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    let data: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let mut f = vfs.create_file("/mare.bin").unwrap();
+    f.write_all(&data).unwrap();
+
+    f.seek(SeekFrom::Start(0)).unwrap();
+    let mut buf = vec![0u8; 2 * 1024 * 1024];
+    let n = f.read(&mut buf).unwrap();
+    assert_eq!(n, data.len());
+    assert_eq!(&buf[..n], &data[..]);
+    assert_eq!(f.read(&mut buf).unwrap(), 0);
+
+    f.seek(SeekFrom::Start(100)).unwrap();
+    let mut middle = vec![0u8; 10_000];
+    assert_eq!(f.read(&mut middle).unwrap(), 10_000);
+    assert_eq!(&middle[..], &data[100..10_100]);
+
+    std::fs::remove_file(path).ok();
+}
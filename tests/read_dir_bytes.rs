@@ -0,0 +1,36 @@
+use project::Vfs;
+use project::models::{DIR_SIZE, DirEntry};
+use std::io::{ErrorKind, Read};
+
+#[test]
+fn test_raw_bytes_parse_back_to_listing() {
+    let path = "test_read_dir_bytes.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/d").unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        vfs.create_file(&format!("/d/{}", name)).unwrap();
+    }
+    vfs.create_dir("/d/sub").unwrap();
+    vfs.remove("/d/b.txt").unwrap();
+
+    let raw = vfs.read_dir_bytes("/d").unwrap();
+    assert_eq!(raw.len() % DIR_SIZE, 0);
+    let names: Vec<String> = raw
+        .chunks_exact(DIR_SIZE)
+        .map(DirEntry::from_bytes)
+        .filter(|e| e.is_active == 1)
+        .map(|e| {
+            let len = e.name.iter().position(|&b| b == 0).unwrap_or(e.name.len());
+            String::from_utf8(e.name[..len].to_vec()).unwrap()
+        })
+        .collect();
+    assert_eq!(names, vfs.read_dir("/d").unwrap());
+
+    let mut dir = vfs.open_file("/d").unwrap();
+    let err = dir.read(&mut [0u8; 16]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::IsADirectory);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,41 @@
+use project::Vfs;
+
+fn name(entry: &project::models::DirEntry) -> String {
+    let len = entry
+        .name
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(entry.name.len());
+    String::from_utf8_lossy(&entry.name[..len]).into_owned()
+}
+
+#[test]
+fn test_removed_entry_stays_visible_in_raw_listing() {
+    let path = "test_read_dir_raw.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/d").unwrap();
+    let keep_id = vfs.create_file("/d/keep.txt").unwrap().inode_id;
+    let gone_id = vfs.create_file("/d/gone.txt").unwrap().inode_id;
+    vfs.remove("/d/gone.txt").unwrap();
+
+    assert!(
+        !vfs.read_dir("/d")
+            .unwrap()
+            .contains(&"gone.txt".to_string())
+    );
+
+    let raw = vfs.read_dir_raw("/d").unwrap();
+    // A whole block of slots, most of them never used.
+    assert_eq!(raw.len(), 4096 / 40);
+    let gone = raw.iter().find(|e| name(e) == "gone.txt").unwrap();
+    assert_eq!(gone.is_active, 0);
+    assert_eq!(gone.inode_id, gone_id);
+    let keep = raw.iter().find(|e| name(e) == "keep.txt").unwrap();
+    assert_eq!(keep.is_active, 1);
+    assert_eq!(keep.inode_id, keep_id);
+    assert_eq!(raw.iter().filter(|e| e.is_active == 1).count(), 3);
+
+    std::fs::remove_file(path).ok();
+}
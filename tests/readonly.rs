@@ -0,0 +1,47 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+fn inode_bitmap(path: &str, start: u64) -> Vec<u8> {
+    let mut raw = std::fs::File::open(path).unwrap();
+    let mut bitmap = vec![0u8; 16];
+    raw.seek(SeekFrom::Start(start)).unwrap();
+    raw.read_exact(&mut bitmap).unwrap();
+    bitmap
+}
+
+#[test]
+fn test_open_readonly_skips_recovery() {
+    let path = "test_readonly.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let bitmap_start;
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/incert.txt").unwrap();
+        let mut inode = vfs.get_inode(1).unwrap();
+        inode.is_valid = 0;
+        vfs.save_inode(1, inode).unwrap();
+        bitmap_start = vfs.superblock().inode_bitmap_start;
+    }
+    let before = inode_bitmap(path, bitmap_start);
+
+    {
+        let mut vfs = Vfs::open_readonly(path).unwrap();
+        assert_eq!(vfs.get_inode(1).unwrap().is_valid, 0);
+        assert_eq!(
+            vfs.create_file("/nou.txt").err().unwrap().kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            vfs.recover().err().unwrap().kind(),
+            ErrorKind::PermissionDenied
+        );
+    }
+    assert_eq!(inode_bitmap(path, bitmap_start), before);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_ne!(inode_bitmap(path, bitmap_start), before);
+    assert_eq!(vfs.recover().unwrap(), 0);
+
+    std::fs::remove_file(path).ok();
+}
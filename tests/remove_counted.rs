@@ -0,0 +1,52 @@
+use project::{RemoveStats, Vfs};
+use std::io::Write;
+
+#[test]
+fn test_remove_reports_freed_blocks_and_size() {
+    let path = "test_remove_counted.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    // Ten direct blocks, five behind the pointer block.
+    vfs.create_file("/big.bin")
+        .unwrap()
+        .write_all(&vec![9u8; 60_000])
+        .unwrap();
+    let data_blocks = vfs.block_map("/big.bin").unwrap().len() as u32;
+    assert_eq!(data_blocks, 15);
+    let free_before = vfs.statfs().unwrap().free_blocks;
+
+    let stats = vfs.remove_counted("/big.bin").unwrap();
+    assert_eq!(
+        stats,
+        RemoveStats {
+            freed_blocks: data_blocks + 1,
+            size: 60_000,
+        }
+    );
+    assert_eq!(
+        vfs.statfs().unwrap().free_blocks,
+        free_before + stats.freed_blocks
+    );
+
+    vfs.create_file("/empty").unwrap();
+    assert_eq!(vfs.remove_counted("/empty").unwrap().freed_blocks, 0);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_open_file_frees_nothing_yet() {
+    let path = "test_remove_counted_open.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/held.txt").unwrap();
+    f.write_all(&[1u8; 5000]).unwrap();
+
+    let stats = vfs.remove_counted("/held.txt").unwrap();
+    assert_eq!(stats.freed_blocks, 0);
+    assert_eq!(stats.size, 5000);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,36 @@
+use project::Vfs;
+use std::io::Write;
+
+fn used_data_blocks(path: &str, vfs: &Vfs) -> u32 {
+    let sb = vfs.superblock();
+    let image = std::fs::read(path).unwrap();
+    image[sb.data_bitmap_start as usize..sb.inode_table_start as usize]
+        .iter()
+        .map(|b| b.count_ones())
+        .sum()
+}
+
+#[test]
+fn test_remove_returns_all_blocks_of_an_indirect_file() {
+    let path = "test_remove_frees_blocks.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 8 * 1024 * 1024).unwrap();
+    vfs.create_dir("/d").unwrap();
+    let baseline = used_data_blocks(path, &vfs);
+
+    let size = vfs.max_file_size() as usize;
+    {
+        let mut f = vfs.create_file("/d/big.bin").unwrap();
+        f.write_all(&vec![3u8; size]).unwrap();
+        vfs.set_xattr("/d/big.bin", "user.tag", b"x").unwrap();
+    }
+    let blocks = vfs.block_map("/d/big.bin").unwrap().len() as u32;
+    // Data blocks, the pointer block and the attribute block.
+    assert_eq!(used_data_blocks(path, &vfs), baseline + blocks + 2);
+
+    vfs.remove("/d/big.bin").unwrap();
+    assert_eq!(used_data_blocks(path, &vfs), baseline);
+
+    std::fs::remove_file(path).ok();
+}
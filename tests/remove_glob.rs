@@ -0,0 +1,47 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_remove_glob_removes_matching_files() {
+    let path = "test_remove_glob.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/var").unwrap();
+    for name in ["log1.txt", "log2.txt", "log-old.txt", "log.md", "app.txt"] {
+        vfs.create_file(&format!("/var/{}", name)).unwrap();
+    }
+
+    assert_eq!(vfs.remove_glob("/var", "log*.txt").unwrap(), 3);
+    let mut left = vfs.read_dir("/var").unwrap();
+    left.sort();
+    assert_eq!(left, vec![".", "..", "app.txt", "log.md"]);
+
+    assert_eq!(vfs.remove_glob("/var", "log?md").unwrap(), 1);
+    assert_eq!(vfs.remove_glob("/var", "nothing*").unwrap(), 0);
+    assert_eq!(vfs.read_dir("/var").unwrap(), vec![".", "..", "app.txt"]);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_remove_glob_directories() {
+    let path = "test_remove_glob_dirs.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/cache-a").unwrap();
+    vfs.create_dir("/cache-a/nested").unwrap();
+    vfs.create_file("/cache-a/nested/x").unwrap();
+    vfs.create_file("/cache-b").unwrap();
+
+    let err = vfs.remove_glob("/", "cache-*").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::IsADirectory);
+    assert_eq!(vfs.entry_count("/").unwrap(), 2);
+
+    assert_eq!(vfs.remove_glob_recursive("/", "cache-*").unwrap(), 2);
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", ".."]);
+    assert_eq!(vfs.recover().unwrap(), 0);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,87 @@
+use project::Vfs;
+use project::models::ENTRY_TRASHED;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_soft_remove_keeps_space_until_purge() {
+    let path = "test_remove_soft.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+    let empty = vfs.statfs().unwrap();
+    let bs = vfs.block_size() as usize;
+    vfs.create_file("/docs/a.bin")
+        .unwrap()
+        .write_all(&vec![9u8; 20 * bs])
+        .unwrap();
+    let full = vfs.statfs().unwrap();
+
+    vfs.remove_soft("/docs/a.bin").unwrap();
+    assert_eq!(
+        vfs.open_file("/docs/a.bin").err().unwrap().kind(),
+        ErrorKind::NotFound
+    );
+    let trashed = vfs.statfs().unwrap();
+    assert_eq!(trashed.free_blocks, full.free_blocks);
+    assert_eq!(trashed.free_inodes, full.free_inodes);
+    assert_eq!(trashed.file_count, 0);
+
+    // New files cannot take the trashed blocks, so undelete still works,
+    // also after reopening the image.
+    vfs.create_file("/docs/b.bin")
+        .unwrap()
+        .write_all(&vec![1u8; 5 * bs])
+        .unwrap();
+    drop(vfs);
+    let mut vfs = Vfs::open(path).unwrap();
+    vfs.undelete("/docs", "a.bin").unwrap();
+    let mut back = Vec::new();
+    vfs.open_file("/docs/a.bin")
+        .unwrap()
+        .read_to_end(&mut back)
+        .unwrap();
+    assert_eq!(back, vec![9u8; 20 * bs]);
+
+    vfs.remove("/docs/b.bin").unwrap();
+    vfs.remove_soft("/docs/a.bin").unwrap();
+    assert_eq!(vfs.purge_deleted().unwrap(), 1);
+    let purged = vfs.statfs().unwrap();
+    assert_eq!(purged.free_blocks, empty.free_blocks);
+    assert_eq!(purged.free_inodes, empty.free_inodes);
+    assert!(vfs.undelete("/docs", "a.bin").is_err());
+    assert_eq!(vfs.purge_deleted().unwrap(), 0);
+
+    let err = vfs.remove_soft("/docs").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::IsADirectory);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_trashed_slot_is_reused_after_purge() {
+    let path = "test_remove_soft_slots.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+    vfs.create_file("/docs/a.txt").unwrap();
+    vfs.remove_soft("/docs/a.txt").unwrap();
+    let slots = vfs.read_dir_raw("/docs").unwrap();
+    assert_eq!(slots[2].is_active, ENTRY_TRASHED);
+
+    // The trashed slot is skipped while undelete may still need it...
+    vfs.create_file("/docs/b.txt").unwrap();
+    let slots = vfs.read_dir_raw("/docs").unwrap();
+    assert_eq!(slots[2].is_active, ENTRY_TRASHED);
+    assert_eq!(slots[3].name_bytes(), b"b.txt");
+
+    // ...and taken again once the purge freed its inode.
+    vfs.purge_deleted().unwrap();
+    vfs.create_file("/docs/c.txt").unwrap();
+    let slots = vfs.read_dir_raw("/docs").unwrap();
+    assert_eq!(slots[2].name_bytes(), b"c.txt");
+    assert_eq!(slots[2].is_active, 1);
+
+    std::fs::remove_file(path).ok();
+}
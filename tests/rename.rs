@@ -0,0 +1,83 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+fn read_all(vfs: &mut Vfs, path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    vfs.open_file(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn test_rename_moves_files_and_directories() {
+    let path = "test_rename.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+    vfs.create_dir("/b").unwrap();
+    vfs.create_file("/a/f.txt")
+        .unwrap()
+        .write_all(b"hello")
+        .unwrap();
+
+    vfs.rename("/a/f.txt", "/b/g.txt").unwrap();
+    assert_eq!(vfs.read_dir("/a").unwrap(), vec![".", ".."]);
+    assert_eq!(read_all(&mut vfs, "/b/g.txt"), b"hello");
+
+    vfs.rename("/b", "/a/b").unwrap();
+    assert_eq!(read_all(&mut vfs, "/a/b/g.txt"), b"hello");
+    let a_id = vfs.find_inode_by_path("/a").unwrap();
+    assert_eq!(vfs.find_inode_by_path("/a/b/..").unwrap(), a_id);
+
+    let err = vfs.rename("/a", "/a/b/inner").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_rename_replaces_existing_file() {
+    let path = "test_rename_replace.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/old").unwrap().write_all(b"old").unwrap();
+    vfs.create_file("/new").unwrap().write_all(b"new").unwrap();
+    vfs.create_dir("/dir").unwrap();
+
+    vfs.rename("/new", "/old").unwrap();
+    assert_eq!(read_all(&mut vfs, "/old"), b"new");
+    assert_eq!(vfs.read_dir("/").unwrap(), vec![".", "..", "old", "dir"]);
+
+    let err = vfs.rename("/old", "/dir").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_write_file_atomic_never_leaves_partial_contents() {
+    let path = "test_write_atomic.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 256 * 1024).unwrap();
+    vfs.create_dir("/etc").unwrap();
+    vfs.write_file_atomic("/etc/app.conf", b"version=1")
+        .unwrap();
+    assert_eq!(read_all(&mut vfs, "/etc/app.conf"), b"version=1");
+
+    vfs.write_file_atomic("/etc/app.conf", b"version=2")
+        .unwrap();
+    assert_eq!(read_all(&mut vfs, "/etc/app.conf"), b"version=2");
+
+    // Too big for the image: the write fails partway through the temp file,
+    // and the target keeps its old contents with no temp file left behind.
+    let err = vfs
+        .write_file_atomic("/etc/app.conf", &vec![b'x'; 300 * 1024])
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::StorageFull);
+    assert_eq!(read_all(&mut vfs, "/etc/app.conf"), b"version=2");
+    assert_eq!(vfs.read_dir("/etc").unwrap(), vec![".", "..", "app.conf"]);
+
+    std::fs::remove_file(path).ok();
+}
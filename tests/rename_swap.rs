@@ -0,0 +1,72 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+fn read(vfs: &mut Vfs, path: &str) -> String {
+    let mut s = String::new();
+    vfs.open_file(path).unwrap().read_to_string(&mut s).unwrap();
+    s
+}
+
+#[test]
+fn test_rename_swap_trades_contents() {
+    let path = "test_rename_swap.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/etc").unwrap();
+        vfs.create_file("/etc/app.conf")
+            .unwrap()
+            .write_all(b"old config")
+            .unwrap();
+        vfs.create_file("/etc/app.conf.new")
+            .unwrap()
+            .write_all(b"new config")
+            .unwrap();
+
+        vfs.rename_swap("/etc/app.conf", "/etc/app.conf.new")
+            .unwrap();
+        assert_eq!(read(&mut vfs, "/etc/app.conf"), "new config");
+        assert_eq!(read(&mut vfs, "/etc/app.conf.new"), "old config");
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(read(&mut vfs, "/etc/app.conf"), "new config");
+    assert_eq!(read(&mut vfs, "/etc/app.conf.new"), "old config");
+    assert_eq!(
+        vfs.rename_swap("/etc/app.conf", "/etc/missing")
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+    assert_eq!(read(&mut vfs, "/etc/app.conf"), "new config");
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_rename_swap_directories_fixes_parent_links() {
+    let path = "test_rename_swap_dirs.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+    vfs.create_dir("/a/x").unwrap();
+    vfs.create_dir("/b").unwrap();
+    vfs.create_file("/b/y").unwrap();
+    vfs.create_file("/a/x/inside.txt").unwrap();
+
+    vfs.rename_swap("/a/x", "/b/y").unwrap();
+    assert!(vfs.open_file("/b/y/inside.txt").is_ok());
+    assert!(!vfs.open_file("/a/x").unwrap().is_dir().unwrap());
+    let b = vfs.find_inode_by_path("/b").unwrap();
+    assert_eq!(vfs.find_inode_by_path("/b/y/..").unwrap(), b);
+
+    assert_eq!(
+        vfs.rename_swap("/b", "/b/y/inside.txt").unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert!(vfs.open_file("/b/y/inside.txt").is_ok());
+
+    std::fs::remove_file(path).ok();
+}
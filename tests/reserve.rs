@@ -0,0 +1,51 @@
+use project::Vfs;
+use std::io::{Read, Write};
+
+#[test]
+fn test_reserve_then_write_stays_contiguous() {
+    let path = "test_reserve.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 8 * 1024 * 1024).unwrap();
+    let size = 2 * 1024 * 1024;
+    let mut f = vfs.create_file("/rezervat.bin").unwrap();
+    let mut other = vfs.create_file("/altul.bin").unwrap();
+
+    f.reserve(size as u64).unwrap();
+    assert_eq!(vfs.stat("/rezervat.bin").unwrap().size, 0);
+
+    let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+    for chunk in data.chunks(4096) {
+        f.write_all(chunk).unwrap();
+        other.write_all(&[1u8; 4096]).unwrap();
+    }
+
+    let stats = vfs.fragmentation("/rezervat.bin").unwrap();
+    assert_eq!(stats.blocks, 512);
+    assert!(stats.extents <= 2, "{:?}", stats);
+
+    let mut content = Vec::new();
+    vfs.open_file("/rezervat.bin")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content, data);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_reserve_fails_without_touching_file() {
+    let path = "test_reserve_full.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 512 * 1024).unwrap();
+    let mut f = vfs.create_file("/prea_mare.bin").unwrap();
+    assert!(f.reserve(2 * 1024 * 1024).is_err());
+
+    let inode = vfs.stat("/prea_mare.bin").unwrap();
+    assert_eq!(inode.direct_blocks, [0; 10]);
+    assert_eq!(inode.indirect_blocks, 0);
+
+    std::fs::remove_file(path).ok();
+}
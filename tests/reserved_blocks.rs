@@ -0,0 +1,105 @@
+use project::Vfs;
+use std::io::{ErrorKind, Write};
+
+#[test]
+fn test_normal_writes_stop_at_the_reserve() {
+    let path = "test_reserved_blocks.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create_with_reserved(path, 1024 * 1024, 10).unwrap();
+    let stats = vfs.statfs().unwrap();
+    assert_eq!(stats.reserved_blocks, stats.total_blocks / 10);
+    assert!(stats.reserved_blocks > 0);
+    assert_eq!(
+        stats.available_blocks,
+        stats.free_blocks - stats.reserved_blocks
+    );
+
+    // Files of eight blocks never need a pointer block, so every allocation
+    // takes exactly one block.
+    let block = vec![0xAB; 4096];
+    let mut full = false;
+    for i in 0.. {
+        let mut f = match vfs.create_file(&format!("/f{}", i)) {
+            Ok(f) => f,
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::StorageFull);
+                break;
+            }
+        };
+        for _ in 0..8 {
+            if let Err(e) = f.write_all(&block) {
+                assert_eq!(e.kind(), ErrorKind::StorageFull);
+                full = true;
+                break;
+            }
+        }
+        if full {
+            break;
+        }
+    }
+
+    let stats = vfs.statfs().unwrap();
+    assert_eq!(stats.available_blocks, 0);
+    assert_eq!(stats.free_blocks, stats.reserved_blocks);
+    assert_eq!(
+        vfs.create_dir("/more").unwrap_err().kind(),
+        ErrorKind::StorageFull
+    );
+
+    let id = vfs.allocate_data_block_reserved().unwrap();
+    assert_ne!(id, 0);
+    assert_eq!(vfs.statfs().unwrap().free_blocks, stats.free_blocks - 1);
+    drop(vfs);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(vfs.statfs().unwrap().reserved_blocks, stats.reserved_blocks);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_reserve_is_limited() {
+    let path = "test_reserved_blocks_limit.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let err = Vfs::create_with_reserved(path, 1024 * 1024, 51)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let stats = vfs.statfs().unwrap();
+    assert_eq!(stats.reserved_blocks, 0);
+    assert_eq!(stats.available_blocks, stats.free_blocks);
+    assert_eq!(stats.free_inodes, stats.total_inodes - 1);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_trim_keeps_the_reserve_free() {
+    let path = "test_reserved_blocks_trim.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let size = 8 * 1024 * 1024;
+    let mut vfs = Vfs::create_with_reserved(path, size, 10).unwrap();
+    let reserved = vfs.statfs().unwrap().reserved_blocks;
+    vfs.create_file("/small.txt")
+        .unwrap()
+        .write_all(b"small")
+        .unwrap();
+    vfs.trim().unwrap();
+    assert!(std::fs::metadata(path).unwrap().len() < size);
+
+    let stats = vfs.statfs().unwrap();
+    assert_eq!(stats.reserved_blocks, reserved);
+    assert!(stats.free_blocks > stats.reserved_blocks);
+    assert!(stats.available_blocks > 0);
+    vfs.create_file("/after.txt")
+        .unwrap()
+        .write_all(&[1u8; 4096])
+        .unwrap();
+
+    std::fs::remove_file(path).ok();
+}
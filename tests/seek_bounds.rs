@@ -0,0 +1,42 @@
+use project::Vfs;
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+
+#[test]
+fn test_seek_current_overflow() {
+    let path = "test_seek_current.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/a.txt").unwrap();
+    f.write_all(b"abc").unwrap();
+
+    let err = f.seek(SeekFrom::Current(i64::MAX)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(f.position(), 3);
+
+    f.seek(SeekFrom::Start(1)).unwrap();
+    let err = f.seek(SeekFrom::Current(-2)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(f.position(), 1);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_seek_start_past_max_size() {
+    let path = "test_seek_start.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/a.txt").unwrap();
+
+    let err = f.seek(SeekFrom::Start(u64::MAX)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(
+        f.seek(SeekFrom::End(i64::MIN)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(f.seek(SeekFrom::Start(4096)).unwrap(), 4096);
+
+    std::fs::remove_file(path).ok();
+}
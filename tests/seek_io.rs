@@ -0,0 +1,40 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_start_and_current_seeks_do_no_io() {
+    let path = "test_seek_io.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/a.txt").unwrap();
+    f.write_all(b"0123456789").unwrap();
+
+    let before = vfs.io_calls();
+    assert_eq!(f.seek(SeekFrom::Start(2)).unwrap(), 2);
+    assert_eq!(f.seek(SeekFrom::Current(3)).unwrap(), 5);
+    assert_eq!(f.seek(SeekFrom::Current(-1)).unwrap(), 4);
+    assert_eq!(f.stream_position().unwrap(), 4);
+    assert_eq!(vfs.io_calls(), before);
+
+    let mut buf = [0u8; 3];
+    f.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"456");
+
+    // End is relative to the size now, including growth through other
+    // handles.
+    let mut other = vfs.open_file("/a.txt").unwrap();
+    other.seek(SeekFrom::End(0)).unwrap();
+    other.write_all(b"abc").unwrap();
+    assert_eq!(f.seek(SeekFrom::End(-2)).unwrap(), 11);
+    f.read_exact(&mut buf[..2]).unwrap();
+    assert_eq!(&buf[..2], b"bc");
+
+    assert_eq!(
+        f.seek(SeekFrom::Current(-100)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(f.stream_position().unwrap(), 13);
+
+    std::fs::remove_file(path).ok();
+}
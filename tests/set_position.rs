@@ -0,0 +1,28 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_set_position_validates_offset() {
+    let path = "test_set_position.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/a.txt").unwrap();
+    f.write_all(b"hello world").unwrap();
+    assert_eq!(f.position(), 11);
+
+    f.set_position(6).unwrap();
+    assert_eq!(f.position(), 6);
+    let mut buf = String::new();
+    f.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "world");
+
+    // The field itself is private, so an out-of-range offset can only be
+    // requested through `set_position`, which refuses it.
+    f.set_position(3).unwrap();
+    let err = f.set_position(u64::MAX).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(f.position(), 3);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,40 @@
+use project::Vfs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_two_handles_write_disjoint_regions_of_one_file() {
+    let path = "test_shared_writes.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let mut a = vfs.create_file("/shared.bin").unwrap();
+        let mut b = vfs.open_file("/shared.bin").unwrap();
+        b.seek(SeekFrom::Start(6000)).unwrap();
+
+        // Interleave so each handle writes after the other grew the file.
+        for _ in 0..6 {
+            a.write_all(&[b'a'; 1000]).unwrap();
+            b.write_all(&[b'b'; 1000]).unwrap();
+        }
+        assert_eq!(a.len().unwrap(), 12_000);
+
+        // A small write near the start must not shrink the file.
+        a.seek(SeekFrom::Start(0)).unwrap();
+        a.write_all(b"A").unwrap();
+        assert_eq!(b.len().unwrap(), 12_000);
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let mut data = Vec::new();
+    vfs.open_file("/shared.bin")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data.len(), 12_000);
+    assert_eq!(data[0], b'A');
+    assert!(data[1..6000].iter().all(|&b| b == b'a'));
+    assert!(data[6000..].iter().all(|&b| b == b'b'));
+
+    std::fs::remove_file(path).ok();
+}
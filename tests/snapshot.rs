@@ -0,0 +1,86 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+fn read_all(vfs: &mut Vfs, path: &str) -> String {
+    let mut content = String::new();
+    vfs.open_file(path)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+#[test]
+fn test_rollback_restores_original_content() {
+    let path = "test_snapshot.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/jurnal.txt").unwrap();
+        f.write_all(b"versiunea originala").unwrap();
+    }
+    vfs.snapshot("backup").unwrap();
+    assert_eq!(
+        vfs.snapshot("backup").unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+
+    {
+        let mut f = vfs.open_file("/jurnal.txt").unwrap();
+        f.write_all(b"VERSIUNEA NOUA, mult mai lunga decat prima")
+            .unwrap();
+    }
+    vfs.create_file("/nou.txt").unwrap();
+    vfs.remove("/jurnal.txt").unwrap();
+    assert!(vfs.open_file("/jurnal.txt").is_err());
+
+    vfs.rollback_to("backup").unwrap();
+    assert_eq!(read_all(&mut vfs, "/jurnal.txt"), "versiunea originala");
+    assert!(vfs.open_file("/nou.txt").is_err());
+    assert_eq!(
+        vfs.rollback_to("backup").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_snapshots_survive_reopening() {
+    let path = "test_snapshot_reopen.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/jurnal.txt").unwrap();
+        f.write_all(&[b'a'; 3000]).unwrap();
+    }
+    let free_before = vfs.statfs().unwrap().free_blocks;
+    vfs.snapshot("inainte").unwrap();
+    {
+        let mut f = vfs.open_file("/jurnal.txt").unwrap();
+        f.write_all(&[b'b'; 3000]).unwrap();
+    }
+    vfs.snapshot("mijloc").unwrap();
+    vfs.remove("/jurnal.txt").unwrap();
+    vfs.create_file("/nou.txt").unwrap();
+    drop(vfs);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    vfs.rollback_to("mijloc").unwrap();
+    assert_eq!(read_all(&mut vfs, "/jurnal.txt"), "b".repeat(3000));
+    assert!(vfs.open_file("/nou.txt").is_err());
+    drop(vfs);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(
+        vfs.rollback_to("mijloc").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+    vfs.rollback_to("inainte").unwrap();
+    assert_eq!(read_all(&mut vfs, "/jurnal.txt"), "a".repeat(3000));
+    assert_eq!(vfs.statfs().unwrap().free_blocks, free_before);
+
+    std::fs::remove_file(path).ok();
+}
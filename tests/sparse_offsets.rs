@@ -0,0 +1,37 @@
+use project::Vfs;
+use std::io::{Seek, SeekFrom, Write};
+
+#[test]
+fn test_data_and_hole_offsets_of_sparse_file() {
+    let path = "test_sparse_offsets.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 2 * 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/sparse.bin").unwrap();
+    // Data in block 0, holes in blocks 1..=2, data in block 3 and past the
+    // direct blocks in block 12, then a hole up to the end.
+    f.write_all(b"head").unwrap();
+    f.seek(SeekFrom::Start(3 * 4096 + 10)).unwrap();
+    f.write_all(&[0u8; 100]).unwrap();
+    f.seek(SeekFrom::Start(12 * 4096)).unwrap();
+    f.write_all(b"tail").unwrap();
+    f.set_len(14 * 4096).unwrap();
+
+    assert_eq!(f.next_data_offset(0).unwrap(), Some(0));
+    assert_eq!(f.next_data_offset(2).unwrap(), Some(2));
+    assert_eq!(f.next_hole_offset(0).unwrap(), Some(4096));
+    assert_eq!(f.next_data_offset(4096).unwrap(), Some(3 * 4096));
+    // Zeros written on purpose are data, not a hole.
+    assert_eq!(f.next_hole_offset(3 * 4096 + 10).unwrap(), Some(4 * 4096));
+    assert_eq!(f.next_data_offset(4 * 4096).unwrap(), Some(12 * 4096));
+    assert_eq!(f.next_hole_offset(12 * 4096).unwrap(), Some(13 * 4096));
+    assert_eq!(f.next_data_offset(13 * 4096).unwrap(), None);
+    assert_eq!(
+        f.next_hole_offset(14 * 4096 - 1).unwrap(),
+        Some(14 * 4096 - 1)
+    );
+    assert_eq!(f.next_hole_offset(14 * 4096).unwrap(), None);
+    assert_eq!(f.next_data_offset(14 * 4096).unwrap(), None);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,33 @@
+use project::Vfs;
+
+#[test]
+fn test_statfs_counts_files_and_directories() {
+    let path = "test_statfs_counts.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let stats = vfs.statfs().unwrap();
+        assert_eq!((stats.file_count, stats.dir_count), (0, 1));
+
+        vfs.create_dirs(["/a/b", "/c"]).unwrap();
+        for file in ["/top.txt", "/a/one", "/a/b/two", "/a/b/three", "/c/four"] {
+            vfs.create_file(file).unwrap();
+        }
+        vfs.remove("/a/b/three").unwrap();
+
+        let stats = vfs.statfs().unwrap();
+        assert_eq!(stats.file_count, 4);
+        assert_eq!(stats.dir_count, 4);
+        assert_eq!(
+            stats.file_count + stats.dir_count,
+            stats.total_inodes - stats.free_inodes
+        );
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let stats = vfs.statfs().unwrap();
+    assert_eq!((stats.file_count, stats.dir_count), (4, 4));
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,43 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_open_detects_corrupted_superblock() {
+    let path = "test_superblock_checksum.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let sb = Vfs::create(path, 1024 * 1024).unwrap().superblock();
+    assert_eq!(sb.checksum, sb.compute_checksum());
+    assert_eq!(Vfs::open(path).unwrap().superblock().checksum, sb.checksum);
+
+    // One flipped bit in the high byte of `inode_table_start`.
+    let mut image = std::fs::read(path).unwrap();
+    image[39] ^= 0x01;
+    std::fs::write(path, &image).unwrap();
+
+    let err = Vfs::open(path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+    assert!(Vfs::open_readonly(path).is_err());
+
+    image[39] ^= 0x01;
+    std::fs::write(path, &image).unwrap();
+    Vfs::open(path).unwrap();
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_images_without_checksum_still_open() {
+    let path = "test_superblock_no_checksum.vfs";
+    let _ = std::fs::remove_file(path);
+
+    Vfs::create(path, 1024 * 1024).unwrap();
+    let mut image = std::fs::read(path).unwrap();
+    image[68..72].copy_from_slice(&[0; 4]);
+    std::fs::write(path, &image).unwrap();
+
+    Vfs::open(path).unwrap();
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,62 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_relative_symlink_resolves_from_link_directory() {
+    let path = "test_symlinks_relative.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+    vfs.create_dir("/a/b").unwrap();
+    vfs.create_file("/a/c.txt")
+        .unwrap()
+        .write_all(b"sibling")
+        .unwrap();
+    vfs.symlink("../c.txt", "/a/b/link").unwrap();
+
+    assert_eq!(vfs.read_link("/a/b/link").unwrap(), "../c.txt");
+    let target = vfs.find_inode_by_path("/a/c.txt").unwrap();
+    assert_eq!(vfs.find_inode_by_path("/a/b/link").unwrap(), target);
+
+    let mut buf = String::new();
+    vfs.open_file("/a/b/link")
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "sibling");
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_symlinked_directory_and_loops() {
+    let path = "test_symlinks_dirs.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/a").unwrap();
+    vfs.create_dir("/a/b").unwrap();
+    vfs.create_file("/a/b/f.txt").unwrap();
+
+    // A link in the middle of a path, with `..` after it, walks the target.
+    vfs.symlink("/a/b", "/shortcut").unwrap();
+    let file = vfs.find_inode_by_path("/a/b/f.txt").unwrap();
+    assert_eq!(vfs.find_inode_by_path("/shortcut/f.txt").unwrap(), file);
+    assert_eq!(
+        vfs.find_inode_by_path("/shortcut/../b/f.txt").unwrap(),
+        file
+    );
+
+    vfs.symlink("loop2", "/loop1").unwrap();
+    vfs.symlink("loop1", "/loop2").unwrap();
+    let err = vfs.find_inode_by_path("/loop1").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let err = vfs.symlink("missing", "/a/b/f.txt").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    let err = vfs.read_link("/a/b/f.txt").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    std::fs::remove_file(path).ok();
+}
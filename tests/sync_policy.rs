@@ -0,0 +1,57 @@
+use project::{SyncPolicy, Vfs};
+use std::io::Write;
+
+/// I/O calls issued by one small write and one flush on a fresh file.
+fn write_then_flush(vfs: &mut Vfs, path: &str) -> (u64, u64) {
+    let mut f = vfs.create_file(path).unwrap();
+    let before = vfs.io_calls();
+    f.write_all(b"hello").unwrap();
+    let after_write = vfs.io_calls();
+    f.flush().unwrap();
+    (after_write - before, vfs.io_calls() - after_write)
+}
+
+#[test]
+fn test_sync_policy_controls_write_syncs() {
+    let path = "test_sync_policy.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    assert_eq!(vfs.sync_policy(), SyncPolicy::EveryWrite);
+    let (every_write, every_flush) = write_then_flush(&mut vfs, "/a.txt");
+    assert_eq!(every_flush, 1);
+
+    vfs.set_sync_policy(SyncPolicy::OnFlush);
+    let (deferred_write, on_flush) = write_then_flush(&mut vfs, "/b.txt");
+    // The write itself skips the invalid-mark, data and inode syncs.
+    assert_eq!(every_write - deferred_write, 3);
+    assert_eq!(on_flush, 1);
+
+    vfs.set_sync_policy(SyncPolicy::Never);
+    let (never_write, never_flush) = write_then_flush(&mut vfs, "/c.txt");
+    assert_eq!(never_write, deferred_write);
+    assert_eq!(never_flush, 0);
+
+    drop(vfs);
+    let mut vfs = Vfs::open(path).unwrap();
+    for name in ["/a.txt", "/b.txt", "/c.txt"] {
+        assert_eq!(vfs.stat(name).unwrap().size, 5);
+    }
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_sync_policy_applies_to_open_handles() {
+    let path = "test_sync_policy_open.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/a.txt").unwrap();
+    vfs.set_sync_policy(SyncPolicy::Never);
+    let before = vfs.io_calls();
+    f.flush().unwrap();
+    assert_eq!(vfs.io_calls(), before);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,111 @@
+use project::Vfs;
+use std::io::{Read, Write};
+
+const OLD_CREATED: u64 = 1_000_000;
+const OLD_MODIFIED: u64 = 2_000_000;
+
+fn age(vfs: &mut Vfs, path: &str) {
+    let id = vfs.find_inode_by_path(path).unwrap();
+    let mut inode = vfs.get_inode(id).unwrap();
+    inode.created_at = OLD_CREATED;
+    inode.modified_at = OLD_MODIFIED;
+    vfs.save_inode(id, inode).unwrap();
+}
+
+fn setup(path: &str) -> Vfs {
+    let _ = std::fs::remove_file(path);
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/src").unwrap();
+    vfs.create_dir("/dst").unwrap();
+    vfs.create_file("/src/f.txt")
+        .unwrap()
+        .write_all(b"data")
+        .unwrap();
+    for p in ["/src/f.txt", "/src", "/dst"] {
+        age(&mut vfs, p);
+    }
+    vfs
+}
+
+#[test]
+fn test_rename_keeps_inode_times_and_touches_parents() {
+    let path = "test_timestamps_rename.vfs";
+    let mut vfs = setup(path);
+
+    vfs.rename("/src/f.txt", "/dst/g.txt").unwrap();
+    let moved = vfs.stat("/dst/g.txt").unwrap();
+    assert_eq!(moved.created_at, OLD_CREATED);
+    assert_eq!(moved.modified_at, OLD_MODIFIED);
+    for dir in ["/src", "/dst"] {
+        let inode = vfs.stat(dir).unwrap();
+        assert_eq!(inode.created_at, OLD_CREATED);
+        assert!(inode.modified_at > OLD_MODIFIED, "{}", dir);
+    }
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_directory_move_touches_both_parents_and_itself() {
+    let path = "test_timestamps_dir_move.vfs";
+    let mut vfs = setup(path);
+    vfs.create_dir("/src/sub").unwrap();
+    vfs.create_dir("/dst/inner").unwrap();
+    for p in ["/src/sub", "/src", "/dst", "/dst/inner"] {
+        age(&mut vfs, p);
+    }
+
+    vfs.rename("/src/sub", "/dst/sub").unwrap();
+    let moved = vfs.stat("/dst/sub").unwrap();
+    assert_eq!(moved.created_at, OLD_CREATED);
+    assert!(moved.modified_at > OLD_MODIFIED);
+    for dir in ["/src", "/dst"] {
+        let inode = vfs.stat(dir).unwrap();
+        assert_eq!(inode.created_at, OLD_CREATED);
+        assert!(inode.modified_at > OLD_MODIFIED, "{}", dir);
+    }
+    assert_eq!(vfs.stat("/dst/inner").unwrap().modified_at, OLD_MODIFIED);
+
+    // Same parent: `..` stays, so only the parent changes.
+    for p in ["/dst/sub", "/dst"] {
+        age(&mut vfs, p);
+    }
+    vfs.rename("/dst/sub", "/dst/renamed").unwrap();
+    let renamed = vfs.stat("/dst/renamed").unwrap();
+    assert_eq!(renamed.created_at, OLD_CREATED);
+    assert_eq!(renamed.modified_at, OLD_MODIFIED);
+    assert!(vfs.stat("/dst").unwrap().modified_at > OLD_MODIFIED);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_copy_timestamp_rules() {
+    let path = "test_timestamps_copy.vfs";
+    let mut vfs = setup(path);
+
+    vfs.copy("/src/f.txt", "/dst/copy.txt").unwrap();
+    let copy = vfs.stat("/dst/copy.txt").unwrap();
+    assert!(copy.created_at > OLD_CREATED);
+    assert_eq!(copy.modified_at, OLD_MODIFIED);
+
+    vfs.copy_preserve_all("/src/f.txt", "/dst/exact.txt")
+        .unwrap();
+    let exact = vfs.stat("/dst/exact.txt").unwrap();
+    assert_eq!(exact.created_at, OLD_CREATED);
+    assert_eq!(exact.modified_at, OLD_MODIFIED);
+
+    let mut data = String::new();
+    vfs.open_file("/dst/exact.txt")
+        .unwrap()
+        .read_to_string(&mut data)
+        .unwrap();
+    assert_eq!(data, "data");
+
+    // The source is untouched.
+    let src = vfs.stat("/src/f.txt").unwrap();
+    assert_eq!(src.created_at, OLD_CREATED);
+    assert_eq!(src.modified_at, OLD_MODIFIED);
+
+    std::fs::remove_file(path).ok();
+}
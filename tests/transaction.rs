@@ -0,0 +1,49 @@
+use project::Vfs;
+use std::io::{Error, Read, Write};
+
+#[test]
+fn test_failed_transaction_leaves_image_unchanged() {
+    let path = "test_transaction_rollback.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+    let before = std::fs::read(path).unwrap();
+
+    let result: std::io::Result<()> = vfs.transaction(|tx| {
+        let mut f = tx.create_file("/docs/nou.txt")?;
+        f.write_all(&vec![5u8; 20_000])?;
+        tx.create_dir("/altceva")?;
+        Err(Error::other("renunt"))
+    });
+    assert_eq!(result.unwrap_err().to_string(), "renunt");
+
+    assert_eq!(std::fs::read(path).unwrap(), before);
+    assert_eq!(vfs.read_dir("/docs").unwrap(), vec![".", ".."]);
+    assert!(vfs.open_file("/altceva").is_err());
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_successful_transaction_is_kept() {
+    let path = "test_transaction_commit.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.transaction(|tx| {
+        let mut f = tx.create_file("/ok.txt")?;
+        f.write_all(b"salvat")?;
+        Ok(())
+    })
+    .unwrap();
+
+    let mut content = String::new();
+    vfs.open_file("/ok.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "salvat");
+
+    std::fs::remove_file(path).ok();
+}
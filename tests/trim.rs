@@ -0,0 +1,121 @@
+use project::Vfs;
+use std::io::{Read, Write};
+
+#[test]
+fn test_trim_shrinks_host_file_and_keeps_data() {
+    let path = "test_trim.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let size = 4 * 1024 * 1024;
+    {
+        let mut vfs = Vfs::create(path, size).unwrap();
+        vfs.create_dir("/docs").unwrap();
+        vfs.create_file("/docs/keep.txt")
+            .unwrap()
+            .write_all(b"still here")
+            .unwrap();
+        vfs.create_file("/bulk.bin")
+            .unwrap()
+            .write_all(&vec![1u8; 3 * 1024 * 1024])
+            .unwrap();
+        vfs.remove("/bulk.bin").unwrap();
+
+        vfs.trim().unwrap();
+        let trimmed = vfs.total_blocks() as u64 * vfs.block_size() as u64;
+        assert!(trimmed < size / 4, "{}", trimmed);
+        assert_eq!(std::fs::metadata(path).unwrap().len(), trimmed);
+
+        // Trimming again has nothing left to cut.
+        vfs.trim().unwrap();
+        assert_eq!(std::fs::metadata(path).unwrap().len(), trimmed);
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let mut data = String::new();
+    vfs.open_file("/docs/keep.txt")
+        .unwrap()
+        .read_to_string(&mut data)
+        .unwrap();
+    assert_eq!(data, "still here");
+    vfs.create_file("/docs/new.txt")
+        .unwrap()
+        .write_all(b"new")
+        .unwrap();
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_trim_stops_at_last_allocated_block() {
+    let path = "test_trim_partial.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 4 * 1024 * 1024).unwrap();
+    vfs.create_file("/first.bin")
+        .unwrap()
+        .write_all(&vec![1u8; 1024 * 1024])
+        .unwrap();
+    vfs.create_file("/last.bin")
+        .unwrap()
+        .write_all(&vec![2u8; 8192])
+        .unwrap();
+    vfs.remove("/first.bin").unwrap();
+    let last_block = *vfs.block_map("/last.bin").unwrap().iter().max().unwrap();
+
+    vfs.trim().unwrap();
+    let end = vfs.data_blocks_start() + (last_block as u64 + 1) * vfs.block_size() as u64;
+    assert!(std::fs::metadata(path).unwrap().len() >= end);
+    drop(vfs);
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let mut data = Vec::new();
+    vfs.open_file("/last.bin")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, vec![2u8; 8192]);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_trim_refuses_while_snapshots_exist() {
+    let path = "test_trim_snapshot.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let size = 8 * 1024 * 1024;
+    let mut vfs = Vfs::create(path, size).unwrap();
+    vfs.create_file("/small.txt")
+        .unwrap()
+        .write_all(b"small")
+        .unwrap();
+    vfs.create_file("/big.bin")
+        .unwrap()
+        .write_all(&vec![7u8; 2 * 1024 * 1024])
+        .unwrap();
+    vfs.snapshot("s").unwrap();
+    vfs.remove("/big.bin").unwrap();
+    assert_eq!(
+        vfs.trim().unwrap_err().kind(),
+        std::io::ErrorKind::ResourceBusy
+    );
+    assert_eq!(std::fs::metadata(path).unwrap().len(), size);
+
+    vfs.rollback_to("s").unwrap();
+    assert_eq!(vfs.total_blocks() as u64 * vfs.block_size() as u64, size);
+    drop(vfs);
+    let mut vfs = Vfs::open(path).unwrap();
+    let mut data = Vec::new();
+    vfs.open_file("/big.bin")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, vec![7u8; 2 * 1024 * 1024]);
+
+    // With the snapshot gone, trimming works again.
+    vfs.remove("/big.bin").unwrap();
+    vfs.trim().unwrap();
+    assert!(std::fs::metadata(path).unwrap().len() < size);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,71 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_truncate_file_by_path_frees_blocks() {
+    let path = "test_truncate_file.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let data: Vec<u8> = (0..60_000u32).map(|i| (i % 251) as u8 + 1).collect();
+    let mut vfs = Vfs::create(path, 2 * 1024 * 1024).unwrap();
+    let empty = vfs.statfs().unwrap().free_blocks;
+    vfs.create_file("/big.bin")
+        .unwrap()
+        .write_all(&data)
+        .unwrap();
+    // 15 data blocks plus the pointer block.
+    assert_eq!(vfs.statfs().unwrap().free_blocks, empty - 16);
+
+    vfs.truncate_file("/big.bin", 5000).unwrap();
+    assert_eq!(vfs.metadata("/big.bin").unwrap().len(), 5000);
+    assert_eq!(vfs.statfs().unwrap().free_blocks, empty - 2);
+    assert_eq!(vfs.block_map("/big.bin").unwrap().len(), 2);
+
+    vfs.truncate_file("/big.bin", 9000).unwrap();
+    assert_eq!(vfs.statfs().unwrap().free_blocks, empty - 2);
+    let mut buf = Vec::new();
+    vfs.open_file("/big.bin")
+        .unwrap()
+        .read_to_end(&mut buf)
+        .unwrap();
+    assert_eq!(buf.len(), 9000);
+    assert_eq!(&buf[..5000], &data[..5000]);
+    assert!(buf[5000..].iter().all(|&b| b == 0));
+
+    vfs.truncate_file("/big.bin", 0).unwrap();
+    assert_eq!(vfs.statfs().unwrap().free_blocks, empty);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_set_len_on_handle_and_errors() {
+    let path = "test_truncate_file_errors.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/dir").unwrap();
+    assert_eq!(
+        vfs.truncate_file("/dir", 0).unwrap_err().kind(),
+        ErrorKind::IsADirectory
+    );
+    assert_eq!(
+        vfs.truncate_file("/missing", 0).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    let mut f = vfs.create_file("/a.txt").unwrap();
+    f.write_all(b"hello world").unwrap();
+    f.set_len(5).unwrap();
+    assert_eq!(f.len().unwrap(), 5);
+    f.seek(SeekFrom::Start(0)).unwrap();
+    let mut s = String::new();
+    f.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "hello");
+    assert_eq!(
+        f.set_len(u64::MAX).unwrap_err().kind(),
+        ErrorKind::FileTooLarge
+    );
+
+    std::fs::remove_file(path).ok();
+}
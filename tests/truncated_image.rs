@@ -0,0 +1,29 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_open_rejects_truncated_image() {
+    let path = "test_truncated_image.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_dir("/docs").unwrap();
+    }
+    let file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+    file.set_len(512 * 1024).unwrap();
+    drop(file);
+
+    for result in [
+        Vfs::open(path).err(),
+        Vfs::open_readonly(path).err(),
+        Vfs::open_shared(path).err(),
+    ] {
+        let err = result.unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("truncated"), "{}", err);
+        assert!(err.to_string().contains("1048576"), "{}", err);
+    }
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,42 @@
+use project::Vfs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_clone_has_independent_cursor() {
+    let path = "test_try_clone.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let mut f = vfs.create_file("/data.bin").unwrap();
+    f.write_all(&data).unwrap();
+
+    let mut g = f.try_clone().unwrap();
+    assert_eq!(g.inode_id, f.inode_id);
+    assert_eq!(g.position(), 10_000);
+
+    f.seek(SeekFrom::Start(100)).unwrap();
+    g.seek(SeekFrom::Start(6000)).unwrap();
+    let mut a = [0u8; 500];
+    let mut b = [0u8; 500];
+    f.read_exact(&mut a).unwrap();
+    g.read_exact(&mut b).unwrap();
+    assert_eq!(&a[..], &data[100..600]);
+    assert_eq!(&b[..], &data[6000..6500]);
+    assert_eq!(f.position(), 600);
+    assert_eq!(g.position(), 6500);
+
+    // The clone keeps the inode alive after the original is gone.
+    drop(f);
+    vfs.remove("/data.bin").unwrap();
+    let mut rest = Vec::new();
+    g.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, &data[6500..]);
+    drop(g);
+    assert_eq!(
+        vfs.stat("/data.bin").unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,68 @@
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn test_undelete_restores_content() {
+    let path = "test_undelete.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/docs").unwrap();
+    // Large enough to need the indirect block.
+    let data: Vec<u8> = (0..60_000u32).map(|i| (i % 251) as u8).collect();
+    vfs.create_file("/docs/report.bin")
+        .unwrap()
+        .write_all(&data)
+        .unwrap();
+    let before = vfs.statfs().unwrap();
+
+    vfs.remove("/docs/report.bin").unwrap();
+    assert!(vfs.statfs().unwrap().free_blocks > before.free_blocks);
+
+    vfs.undelete("/docs", "report.bin").unwrap();
+    assert_eq!(vfs.statfs().unwrap(), before);
+    let mut buf = Vec::new();
+    vfs.open_file("/docs/report.bin")
+        .unwrap()
+        .read_to_end(&mut buf)
+        .unwrap();
+    assert_eq!(buf, data);
+
+    assert_eq!(
+        vfs.undelete("/docs", "report.bin").unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+    assert_eq!(
+        vfs.undelete("/docs", "never.txt").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_undelete_refuses_reused_blocks() {
+    let path = "test_undelete_reused.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_dir("/other").unwrap();
+    vfs.create_file("/old.txt")
+        .unwrap()
+        .write_all(&[1u8; 8192])
+        .unwrap();
+    vfs.remove("/old.txt").unwrap();
+    // Takes the inode and the blocks `old.txt` had, but not its entry slot.
+    vfs.create_file("/other/new.txt")
+        .unwrap()
+        .write_all(&[2u8; 8192])
+        .unwrap();
+    let before = vfs.statfs().unwrap();
+
+    let err = vfs.undelete("/", "old.txt").unwrap_err();
+    assert!(err.to_string().contains("reused"), "{}", err);
+    assert_eq!(vfs.statfs().unwrap(), before);
+    assert!(!vfs.read_dir("/").unwrap().contains(&"old.txt".to_string()));
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,46 @@
+#![cfg(feature = "unicode")]
+
+use project::Vfs;
+use std::io::{ErrorKind, Read, Write};
+
+const COMPOSED: &str = "caf\u{e9}.txt";
+const DECOMPOSED: &str = "cafe\u{301}.txt";
+
+#[test]
+fn test_nfc_names_match_either_composition() {
+    let path = "test_unicode_names.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap().with_nfc_names(true);
+    vfs.create_file(&format!("/{}", COMPOSED))
+        .unwrap()
+        .write_all(b"menu")
+        .unwrap();
+
+    let mut buf = String::new();
+    vfs.open_file(&format!("/{}", DECOMPOSED))
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "menu");
+
+    // Both spellings name the same entry, and the stored bytes are kept.
+    let err = vfs.create_file(&format!("/{}", DECOMPOSED)).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert!(vfs.read_dir("/").unwrap().contains(&COMPOSED.to_string()));
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_nfc_names_off_by_default() {
+    let path = "test_unicode_names_off.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file(&format!("/{}", COMPOSED)).unwrap();
+    let err = vfs.open_file(&format!("/{}", DECOMPOSED)).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    std::fs::remove_file(path).ok();
+}
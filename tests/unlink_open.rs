@@ -0,0 +1,39 @@
+use project::Vfs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_removed_file_lives_until_last_handle_drops() {
+    let path = "test_unlink_open.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut old = vfs.create_file("/a.txt").unwrap();
+    old.write_all(b"inainte").unwrap();
+
+    vfs.remove("/a.txt").unwrap();
+    assert!(vfs.open_file("/a.txt").is_err());
+    old.write_all(b" si dupa").unwrap();
+
+    let mut fresh = vfs.create_file("/b.txt").unwrap();
+    assert_ne!(fresh.inode_id, old.inode_id);
+    fresh.write_all(b"alt fisier").unwrap();
+
+    let mut content = String::new();
+    old.seek(SeekFrom::Start(0)).unwrap();
+    old.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "inainte si dupa");
+
+    let old_id = old.inode_id;
+    drop(old);
+    let mut content = String::new();
+    vfs.open_file("/b.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "alt fisier");
+
+    // The inode is free again once the last handle is gone.
+    assert_eq!(vfs.create_file("/c.txt").unwrap().inode_id, old_id);
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,51 @@
+use project::Vfs;
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+
+#[test]
+fn test_vectored_record_roundtrip() {
+    let path = "test_vectored.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let header = b"HDR1".to_vec();
+    let payload = vec![0xABu8; 5000];
+    let trailer = b"END".to_vec();
+    {
+        let mut f = vfs.create_file("/record.bin").unwrap();
+        let slices = [
+            IoSlice::new(&header),
+            IoSlice::new(&payload),
+            IoSlice::new(&trailer),
+        ];
+        let written = f.write_vectored(&slices).unwrap();
+        assert_eq!(written, header.len() + payload.len() + trailer.len());
+    }
+
+    let mut contents = Vec::new();
+    vfs.open_file("/record.bin")
+        .unwrap()
+        .read_to_end(&mut contents)
+        .unwrap();
+    assert_eq!(
+        contents,
+        [header.clone(), payload.clone(), trailer.clone()].concat()
+    );
+
+    let mut h = [0u8; 4];
+    let mut p = vec![0u8; 5000];
+    let mut t = [0u8; 3];
+    let mut f = vfs.open_file("/record.bin").unwrap();
+    let n = f
+        .read_vectored(&mut [
+            IoSliceMut::new(&mut h),
+            IoSliceMut::new(&mut p),
+            IoSliceMut::new(&mut t),
+        ])
+        .unwrap();
+    assert_eq!(n, 5007);
+    assert_eq!(&h, b"HDR1");
+    assert_eq!(p, payload);
+    assert_eq!(&t, b"END");
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,100 @@
+use project::Vfs;
+use project::models::INODE_SIZE;
+use std::io::{ErrorKind, Write};
+
+#[test]
+fn test_verify_file_flags_corrupt_block_pointer() {
+    let path = "test_verify_file.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let bs = vfs.block_size() as usize;
+    let (id, mut f) = vfs.create_file_with_id("/important.bin").unwrap();
+    f.write_all(&vec![3u8; 3 * bs]).unwrap();
+    drop(f);
+    vfs.verify_file("/important.bin").unwrap();
+    let good = vfs.get_inode(id).unwrap();
+
+    // A pointer to a block that was never allocated.
+    let spare = vfs.allocate_data_block().unwrap();
+    vfs.free_data_block(spare).unwrap();
+    let mut inode = good;
+    inode.direct_blocks[1] = spare;
+    vfs.save_inode(id, inode).unwrap();
+    let err = vfs.verify_file("/important.bin").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("free block"), "{}", err);
+
+    // A pointer past the end of the image.
+    inode.direct_blocks[1] = u32::MAX;
+    vfs.save_inode(id, inode).unwrap();
+    let err = vfs.verify_file("/important.bin").unwrap_err();
+    assert!(err.to_string().contains("out of range"), "{}", err);
+
+    // Two slots sharing one block.
+    inode.direct_blocks[1] = good.direct_blocks[0];
+    vfs.save_inode(id, inode).unwrap();
+    let err = vfs.verify_file("/important.bin").unwrap_err();
+    assert!(err.to_string().contains("reuses"), "{}", err);
+
+    // Blocks past the size are reserved ones only when they follow the end
+    // without a gap.
+    let mut inode = good;
+    inode.size = bs as u64;
+    vfs.save_inode(id, inode).unwrap();
+    vfs.verify_file("/important.bin").unwrap();
+    inode.direct_blocks[1] = 0;
+    vfs.save_inode(id, inode).unwrap();
+    let err = vfs.verify_file("/important.bin").unwrap_err();
+    assert!(err.to_string().contains("past the end"), "{}", err);
+
+    vfs.save_inode(id, good).unwrap();
+    vfs.verify_file("/important.bin").unwrap();
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_verify_file_accepts_reserved_tail_blocks() {
+    let path = "test_verify_file_reserve.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let bs = vfs.block_size() as u64;
+    let mut f = vfs.create_file("/log.txt").unwrap();
+    f.write_all(b"first line").unwrap();
+    f.reserve(12 * bs).unwrap();
+    drop(f);
+    assert!(vfs.block_map("/log.txt").unwrap().len() < 12);
+    vfs.verify_file("/log.txt").unwrap();
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_verify_file_flags_unknown_inode_type() {
+    let path = "test_verify_file_type.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let (table_start, id) = {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        let (id, mut f) = vfs.create_file_with_id("/odd.bin").unwrap();
+        f.write_all(b"contents").unwrap();
+        (vfs.inode_table_start(), id)
+    };
+    let mut image = std::fs::read(path).unwrap();
+    image[table_start as usize + id as usize * INODE_SIZE] = 9;
+    std::fs::write(path, image).unwrap();
+
+    // Opening still works; the inode is reported rather than taken for a file.
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(
+        vfs.get_inode(id).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    let err = vfs.verify_file("/odd.bin").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("Unknown inode type 9"), "{}", err);
+
+    std::fs::remove_file(path).ok();
+}
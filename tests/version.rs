@@ -0,0 +1,33 @@
+use project::Vfs;
+use project::models::FORMAT_VERSION;
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+
+#[test]
+fn test_open_rejects_newer_format_version() {
+    let path = "test_version.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        Vfs::create(path, 1024 * 1024).unwrap();
+    }
+    assert_eq!(Vfs::image_version(path).unwrap(), FORMAT_VERSION);
+
+    {
+        let mut raw = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        raw.seek(SeekFrom::Start(48)).unwrap();
+        raw.write_all(&(FORMAT_VERSION + 1).to_le_bytes()).unwrap();
+    }
+    assert_eq!(Vfs::image_version(path).unwrap(), FORMAT_VERSION + 1);
+
+    let err = Vfs::open(path).err().expect("newer image must be rejected");
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    let msg = err.to_string();
+    assert!(
+        msg.contains(&format!("version {}", FORMAT_VERSION + 1)),
+        "{}",
+        msg
+    );
+    assert!(msg.contains("newer"), "{}", msg);
+
+    std::fs::remove_file(path).ok();
+}
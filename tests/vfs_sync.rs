@@ -0,0 +1,36 @@
+use project::{SyncPolicy, Vfs};
+use std::io::{Read, Write};
+
+#[test]
+fn test_sync_is_a_single_checkpoint() {
+    let path = "test_vfs_sync.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.set_sync_policy(SyncPolicy::Never);
+        vfs.create_dir("/logs").unwrap();
+        let mut f = vfs.create_file("/logs/today.txt").unwrap();
+        f.write_all(b"first line\n").unwrap();
+        drop(f);
+
+        let before = vfs.io_calls();
+        vfs.sync().unwrap();
+        // A seek and a write for the superblock, then a single sync.
+        assert_eq!(vfs.io_calls() - before, 3);
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    let mut text = String::new();
+    vfs.open_file("/logs/today.txt")
+        .unwrap()
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(text, "first line\n");
+    drop(vfs);
+
+    let mut vfs = Vfs::open_readonly(path).unwrap();
+    vfs.sync().unwrap();
+
+    std::fs::remove_file(path).ok();
+}
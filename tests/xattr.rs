@@ -0,0 +1,80 @@
+use project::Vfs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_xattrs_survive_reopen() {
+    let path = "test_xattr.vfs";
+    let _ = std::fs::remove_file(path);
+
+    {
+        let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+        vfs.create_file("/poza.png").unwrap();
+        vfs.set_xattr("/poza.png", "mime", b"image/png").unwrap();
+        vfs.set_xattr("/poza.png", "tags", b"vacanta,mare").unwrap();
+    }
+
+    let mut vfs = Vfs::open(path).unwrap();
+    assert_eq!(
+        vfs.list_xattr("/poza.png").unwrap(),
+        vec!["mime".to_string(), "tags".to_string()]
+    );
+    assert_eq!(
+        vfs.get_xattr("/poza.png", "mime").unwrap().as_deref(),
+        Some(&b"image/png"[..])
+    );
+    assert_eq!(
+        vfs.get_xattr("/poza.png", "tags").unwrap().as_deref(),
+        Some(&b"vacanta,mare"[..])
+    );
+
+    vfs.remove_xattr("/poza.png", "mime").unwrap();
+    assert_eq!(vfs.get_xattr("/poza.png", "mime").unwrap(), None);
+    assert_eq!(
+        vfs.remove_xattr("/poza.png", "mime").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_xattr_block_full() {
+    let path = "test_xattr_full.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/a.txt").unwrap();
+    vfs.set_xattr("/a.txt", "mare", &[0u8; 3000]).unwrap();
+    let err = vfs
+        .set_xattr("/a.txt", "prea_mare", &[0u8; 2000])
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::StorageFull);
+    assert_eq!(vfs.list_xattr("/a.txt").unwrap(), vec!["mare".to_string()]);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_xattrs_of_immutable_file_are_frozen() {
+    let path = "test_xattr_immutable.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    vfs.create_file("/a.txt").unwrap();
+    vfs.set_xattr("/a.txt", "autor", b"ana").unwrap();
+    vfs.set_immutable("/a.txt", true).unwrap();
+    assert_eq!(
+        vfs.set_xattr("/a.txt", "autor", b"ion").unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+    assert_eq!(
+        vfs.remove_xattr("/a.txt", "autor").unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+    assert_eq!(vfs.get_xattr("/a.txt", "autor").unwrap().unwrap(), b"ana");
+
+    vfs.set_immutable("/a.txt", false).unwrap();
+    vfs.remove_xattr("/a.txt", "autor").unwrap();
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,43 @@
+use project::Vfs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn test_reused_block_reads_as_zeros() {
+    let path = "test_zero_fill.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    {
+        let mut f = vfs.create_file("/old.bin").unwrap();
+        f.write_all(&[0xAB; 8192]).unwrap();
+    }
+    let old_blocks = vfs.block_map("/old.bin").unwrap();
+    vfs.remove("/old.bin").unwrap();
+
+    let mut f = vfs.create_file("/new.bin").unwrap();
+    f.write_all(b"abc").unwrap();
+    // Skip ahead so the second block is only partly written too.
+    f.seek(SeekFrom::Start(4096 + 100)).unwrap();
+    f.write_all(b"xyz").unwrap();
+    drop(f);
+    let new_blocks = vfs.block_map("/new.bin").unwrap();
+    assert!(new_blocks.iter().any(|id| old_blocks.contains(id)));
+
+    let mut data = Vec::new();
+    vfs.open_file("/new.bin")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(&data[..3], b"abc");
+    assert!(data[3..4196].iter().all(|&b| b == 0));
+    assert_eq!(&data[4196..], b"xyz");
+
+    let mut image = std::fs::File::open(path).unwrap();
+    let mut tail = vec![0u8; 4096 - 3];
+    let start = vfs.data_blocks_start() + new_blocks[0] as u64 * 4096 + 3;
+    image.seek(SeekFrom::Start(start)).unwrap();
+    image.read_exact(&mut tail).unwrap();
+    assert!(tail.iter().all(|&b| b == 0));
+
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,60 @@
+use project::Vfs;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+
+#[test]
+fn test_format_zeroes_exactly_the_metadata_area() {
+    let path = "test_zero_region.bin";
+    let _ = std::fs::remove_file(path);
+
+    let base = 1000u64;
+    let size = 1024 * 1024u64;
+    std::fs::write(path, vec![0xAAu8; (base + size) as usize]).unwrap();
+
+    let data_start;
+    {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        file.seek(SeekFrom::Start(base)).unwrap();
+        let vfs = Vfs::create_in_handle(file, size).unwrap();
+        data_start = vfs.data_blocks_start();
+    }
+
+    let raw = std::fs::read(path).unwrap();
+    let image = &raw[base as usize..];
+    assert!(raw[..base as usize].iter().all(|&b| b == 0xAA));
+    // Between the superblock and the end of the bad block table only zeros
+    // were written.
+    assert!(image[72..3072].iter().all(|&b| b == 0));
+    // The last bytes of the inode table are unused slots.
+    let table_tail = &image[data_start as usize - 64..data_start as usize];
+    assert!(table_tail.iter().all(|&b| b == 0));
+    // Data blocks nothing was allocated in keep their old bytes.
+    let unused = data_start as usize + 10 * 4096;
+    assert!(image[unused..unused + 4096].iter().all(|&b| b == 0xAA));
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_shrink_zeroes_only_the_dropped_tail() {
+    let path = "test_zero_region_shrink.vfs";
+    let _ = std::fs::remove_file(path);
+
+    let mut vfs = Vfs::create(path, 1024 * 1024).unwrap();
+    let mut f = vfs.create_file("/a.bin").unwrap();
+    f.write_all(&[0x55u8; 3000]).unwrap();
+    f.set_len(1000).unwrap();
+    drop(f);
+    let block = vfs.block_map("/a.bin").unwrap()[0];
+
+    let mut buf = vec![0u8; 4096];
+    vfs.read_block(block, &mut buf).unwrap();
+    assert!(buf[..1000].iter().all(|&b| b == 0x55));
+    assert!(buf[1000..].iter().all(|&b| b == 0));
+
+    std::fs::remove_file(path).ok();
+}